@@ -0,0 +1,483 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+use crate::audio_stages::to_dbfs;
+use crate::config::InboundAnalysisConfig;
+use crate::fft::power_spectrum;
+use crate::inbound::InboundSink;
+
+/// Samples at or above this magnitude count as clipped (about -0.2 dBFS, just under
+/// the largest value a G.711 code word decodes to)
+const CLIP_LEVEL: i32 = 32000;
+/// Window over which silence is judged
+const SILENCE_WINDOW_SECONDS: f64 = 0.02;
+/// Length of each spectral analysis block
+const FFT_BLOCK_SECONDS: f64 = 0.032;
+/// Most tones reported per block
+const MAX_TONES_PER_BLOCK: usize = 4;
+/// Edges of the spectral summary bands, in Hz (capped at nyquist)
+const SPECTRAL_BAND_EDGES: [f32; 5] = [300.0, 1000.0, 2000.0, 3400.0, 7000.0];
+
+/// Quality summary of the audio received on a call
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AudioAnalysis {
+    pub sample_rate: u32,
+    pub samples: u64,
+    pub duration_seconds: f64,
+    pub peak_dbfs: f32,
+    pub rms_dbfs: f32,
+    pub clipped_samples: u64,
+    /// Fraction of 20ms windows below the silence threshold
+    pub silence_ratio: f64,
+    /// Steady tones, merged by frequency
+    pub tones: Vec<DetectedTone>,
+    pub spectrum: Option<SpectralSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectedTone {
+    pub frequency_hz: f32,
+    /// Total time the tone was present
+    pub duration_ms: u64,
+    /// Number of separate bursts
+    pub occurrences: u32,
+    pub level_dbfs: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpectralSummary {
+    /// Average level per band
+    pub bands: Vec<SpectralBand>,
+    pub centroid_hz: f32,
+    /// Frequency below which 85% of the energy lies
+    pub rolloff_hz: f32,
+    /// Strongest bin of the average spectrum
+    pub dominant_hz: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpectralBand {
+    pub low_hz: f32,
+    pub high_hz: f32,
+    pub level_dbfs: f32,
+}
+
+/// A tone followed across consecutive spectral blocks
+#[derive(Debug, Clone)]
+struct ToneTrack {
+    frequency_sum: f64,
+    mean_square_sum: f64,
+    blocks: u64,
+    last_frequency: f32,
+}
+
+/// Streaming analyser of received audio
+pub struct AudioAnalyzer {
+    config: InboundAnalysisConfig,
+    sample_rate: u32,
+    samples: u64,
+    peak: i32,
+    sum_squares: f64,
+    clipped: u64,
+    // Silence detection over fixed windows
+    window_len: usize,
+    window_fill: usize,
+    window_sum_squares: f64,
+    windows: u64,
+    silent_windows: u64,
+    // Spectral analysis over Hann-windowed FFT blocks
+    fft_size: usize,
+    hann: Vec<f32>,
+    pending: Vec<f32>,
+    spectrum_sum: Vec<f64>,
+    spectrum_blocks: u64,
+    tracks: Vec<ToneTrack>,
+    tones: Vec<DetectedTone>,
+}
+
+impl AudioAnalyzer {
+    pub fn new(config: InboundAnalysisConfig) -> Self {
+        let mut analyzer = Self {
+            config,
+            sample_rate: 0,
+            samples: 0,
+            peak: 0,
+            sum_squares: 0.0,
+            clipped: 0,
+            window_len: 0,
+            window_fill: 0,
+            window_sum_squares: 0.0,
+            windows: 0,
+            silent_windows: 0,
+            fft_size: 0,
+            hann: Vec::new(),
+            pending: Vec::new(),
+            spectrum_sum: Vec::new(),
+            spectrum_blocks: 0,
+            tracks: Vec::new(),
+            tones: Vec::new(),
+        };
+        analyzer.set_sample_rate(8000);
+        analyzer
+    }
+
+    /// Size the silence windows and FFT for a sample rate, dropping spectral history
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.end_tracks();
+        self.sample_rate = sample_rate;
+        self.window_len = ((sample_rate as f64 * SILENCE_WINDOW_SECONDS) as usize).max(1);
+        self.window_fill = 0;
+        self.window_sum_squares = 0.0;
+
+        self.fft_size = ((sample_rate as f64 * FFT_BLOCK_SECONDS) as usize).next_power_of_two().max(16);
+        self.hann = (0..self.fft_size)
+            .map(|n| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * n as f32 / self.fft_size as f32).cos())
+            .collect();
+        self.pending.clear();
+        self.spectrum_sum = vec![0.0; self.fft_size / 2 + 1];
+        self.spectrum_blocks = 0;
+    }
+
+    pub fn process(&mut self, samples: &[i16], sample_rate: u32) {
+        if sample_rate != self.sample_rate && sample_rate > 0 {
+            self.set_sample_rate(sample_rate);
+        }
+
+        let silence_mean_square = 10f64.powf(self.config.silence_threshold_dbfs as f64 / 10.0);
+        for &sample in samples {
+            let magnitude = (sample as i32).abs();
+            self.peak = self.peak.max(magnitude);
+            if magnitude >= CLIP_LEVEL {
+                self.clipped += 1;
+            }
+
+            let x = sample as f64 / 32768.0;
+            self.sum_squares += x * x;
+            self.samples += 1;
+
+            self.window_sum_squares += x * x;
+            self.window_fill += 1;
+            if self.window_fill == self.window_len {
+                self.windows += 1;
+                if self.window_sum_squares / (self.window_len as f64) < silence_mean_square {
+                    self.silent_windows += 1;
+                }
+                self.window_fill = 0;
+                self.window_sum_squares = 0.0;
+            }
+
+            self.pending.push(x as f32);
+            if self.pending.len() == self.fft_size {
+                self.analyse_block();
+                self.pending.clear();
+            }
+        }
+    }
+
+    /// Mean square contributed by a sum of one-sided Hann-windowed bin powers
+    fn bin_mean_square(&self, power: f64) -> f64 {
+        let n = self.fft_size as f64;
+        power * 16.0 / (3.0 * n * n)
+    }
+
+    fn bin_hz(&self) -> f32 {
+        self.sample_rate as f32 / self.fft_size as f32
+    }
+
+    fn block_ms(&self) -> f64 {
+        self.fft_size as f64 * 1000.0 / self.sample_rate as f64
+    }
+
+    fn analyse_block(&mut self) {
+        let windowed: Vec<f32> = self.pending.iter().zip(&self.hann).map(|(x, w)| x * w).collect();
+        let power = power_spectrum(&windowed);
+        for (sum, &bin) in self.spectrum_sum.iter_mut().zip(&power) {
+            *sum += bin;
+        }
+        self.spectrum_blocks += 1;
+
+        let total: f64 = power[1..].iter().sum();
+        let silence_mean_square = 10f64.powf(self.config.silence_threshold_dbfs as f64 / 10.0);
+        let peaks = if self.bin_mean_square(total) < silence_mean_square {
+            Vec::new()
+        } else {
+            self.find_tones(&power, total)
+        };
+        self.update_tracks(&peaks);
+    }
+
+    /// Spectral peaks that stand well above the floor and carry a good part of the energy
+    fn find_tones(&self, power: &[f64], total: f64) -> Vec<(f32, f64)> {
+        let mut sorted: Vec<f64> = power[1..].to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let floor = sorted[sorted.len() / 2].max(1e-20);
+
+        let mut peaks: Vec<(f32, f64)> = (2..power.len() - 1)
+            .filter(|&k| power[k] >= power[k - 1] && power[k] > power[k + 1])
+            .filter_map(|k| {
+                let energy = power[k - 1] + power[k] + power[k + 1];
+                if energy < 0.1 * total || power[k] < 100.0 * floor {
+                    return None;
+                }
+                // Parabolic interpolation on the log spectrum
+                let (a, b, c) = (power[k - 1].max(1e-30).ln(), power[k].ln(), power[k + 1].max(1e-30).ln());
+                let denominator = a - 2.0 * b + c;
+                let offset = if denominator.abs() > 1e-12 { 0.5 * (a - c) / denominator } else { 0.0 };
+                Some(((k as f64 + offset) as f32 * self.bin_hz(), self.bin_mean_square(energy)))
+            })
+            .collect();
+
+        peaks.sort_by(|a, b| b.1.total_cmp(&a.1));
+        peaks.truncate(MAX_TONES_PER_BLOCK);
+        peaks
+    }
+
+    fn update_tracks(&mut self, peaks: &[(f32, f64)]) {
+        let tolerance = 1.5 * self.bin_hz();
+        let mut continued = Vec::with_capacity(peaks.len());
+
+        for &(frequency, mean_square) in peaks {
+            let position = self.tracks.iter()
+                .position(|track| (track.last_frequency - frequency).abs() <= tolerance);
+            let mut track = match position {
+                Some(index) => self.tracks.swap_remove(index),
+                None => ToneTrack { frequency_sum: 0.0, mean_square_sum: 0.0, blocks: 0, last_frequency: frequency },
+            };
+            track.frequency_sum += frequency as f64;
+            track.mean_square_sum += mean_square;
+            track.blocks += 1;
+            track.last_frequency = frequency;
+            continued.push(track);
+        }
+
+        // Whatever was not continued has ended
+        for track in std::mem::replace(&mut self.tracks, continued) {
+            self.finish_track(&track);
+        }
+    }
+
+    fn end_tracks(&mut self) {
+        for track in std::mem::take(&mut self.tracks) {
+            self.finish_track(&track);
+        }
+    }
+
+    fn finish_track(&mut self, track: &ToneTrack) {
+        let mut tones = std::mem::take(&mut self.tones);
+        self.merge_track(track, &mut tones);
+        self.tones = tones;
+    }
+
+    /// Add a track to `tones` if it lasted long enough, merging it with a tone of the same frequency
+    fn merge_track(&self, track: &ToneTrack, tones: &mut Vec<DetectedTone>) {
+        let duration_ms = (track.blocks as f64 * self.block_ms()) as u64;
+        if duration_ms < self.config.min_tone_duration_ms as u64 {
+            return;
+        }
+
+        let frequency = (track.frequency_sum / track.blocks as f64) as f32;
+        let level_dbfs = 10.0 * (track.mean_square_sum / track.blocks as f64).max(1e-20).log10() as f32;
+        let tolerance = 1.5 * self.bin_hz();
+
+        match tones.iter_mut().find(|tone| (tone.frequency_hz - frequency).abs() <= tolerance) {
+            Some(tone) => {
+                let total = (tone.duration_ms + duration_ms).max(1) as f32;
+                tone.frequency_hz = (tone.frequency_hz * tone.duration_ms as f32 + frequency * duration_ms as f32) / total;
+                tone.level_dbfs = tone.level_dbfs.max(level_dbfs);
+                tone.duration_ms += duration_ms;
+                tone.occurrences += 1;
+            }
+            None => tones.push(DetectedTone { frequency_hz: frequency, duration_ms, occurrences: 1, level_dbfs }),
+        }
+    }
+
+    fn spectral_summary(&self) -> Option<SpectralSummary> {
+        if self.spectrum_blocks == 0 {
+            return None;
+        }
+
+        let bin_hz = self.bin_hz();
+        let nyquist = self.sample_rate as f32 / 2.0;
+        let average: Vec<f64> = self.spectrum_sum.iter().map(|&sum| sum / self.spectrum_blocks as f64).collect();
+        let total: f64 = average[1..].iter().sum();
+
+        let mut edges = vec![0.0];
+        edges.extend(SPECTRAL_BAND_EDGES.iter().copied().filter(|&edge| edge < nyquist));
+        edges.push(nyquist);
+        let bands = edges.windows(2)
+            .map(|band| {
+                let power: f64 = average.iter().enumerate()
+                    .filter(|(k, _)| { let f = *k as f32 * bin_hz; f >= band[0] && f < band[1] })
+                    .map(|(_, &p)| p)
+                    .sum();
+                SpectralBand {
+                    low_hz: band[0],
+                    high_hz: band[1],
+                    level_dbfs: 10.0 * self.bin_mean_square(power).max(1e-20).log10() as f32,
+                }
+            })
+            .collect();
+
+        let centroid = if total > 0.0 {
+            average.iter().enumerate().map(|(k, &p)| k as f64 * p).sum::<f64>() / total
+        } else {
+            0.0
+        };
+
+        let mut cumulative = 0.0;
+        let rolloff_bin = average.iter().enumerate().skip(1)
+            .find(|(_, &p)| { cumulative += p; cumulative >= 0.85 * total })
+            .map_or(0, |(k, _)| k);
+
+        let dominant_bin = average.iter().enumerate().skip(1)
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map_or(0, |(k, _)| k);
+
+        Some(SpectralSummary {
+            bands,
+            centroid_hz: centroid as f32 * bin_hz,
+            rolloff_hz: rolloff_bin as f32 * bin_hz,
+            dominant_hz: dominant_bin as f32 * bin_hz,
+        })
+    }
+
+    /// Results so far; tones still sounding are included
+    pub fn snapshot(&self) -> AudioAnalysis {
+        let mut tones = self.tones.clone();
+        for track in &self.tracks {
+            self.merge_track(track, &mut tones);
+        }
+        tones.sort_by(|a, b| a.frequency_hz.total_cmp(&b.frequency_hz));
+
+        let mean_square = if self.samples == 0 { 0.0 } else { self.sum_squares / self.samples as f64 };
+        AudioAnalysis {
+            sample_rate: self.sample_rate,
+            samples: self.samples,
+            duration_seconds: self.samples as f64 / self.sample_rate.max(1) as f64,
+            peak_dbfs: to_dbfs(self.peak as f32 / 32768.0),
+            rms_dbfs: to_dbfs(mean_square.sqrt() as f32),
+            clipped_samples: self.clipped,
+            silence_ratio: if self.windows == 0 { 0.0 } else { self.silent_windows as f64 / self.windows as f64 },
+            tones,
+            spectrum: self.spectral_summary(),
+        }
+    }
+}
+
+/// Analyser shared between the inbound pipeline and anyone asking for results
+#[derive(Clone)]
+pub struct SharedAnalyzer(Arc<Mutex<AudioAnalyzer>>);
+
+impl SharedAnalyzer {
+    pub fn new(config: InboundAnalysisConfig) -> Self {
+        Self(Arc::new(Mutex::new(AudioAnalyzer::new(config))))
+    }
+
+    pub fn snapshot(&self) -> AudioAnalysis {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).snapshot()
+    }
+}
+
+impl InboundSink for SharedAnalyzer {
+    fn name(&self) -> &'static str {
+        "analysis"
+    }
+
+    fn on_frame(&mut self, samples: &[i16], sample_rate: u32) {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).process(samples, sample_rate);
+    }
+
+    fn finish(&mut self) {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).end_tracks();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(frequencies: &[f32], amplitude: f32, seconds: f32) -> Vec<i16> {
+        (0..(8000.0 * seconds) as usize)
+            .map(|i| {
+                let t = i as f32 / 8000.0;
+                let sum: f32 = frequencies.iter().map(|f| (2.0 * std::f32::consts::PI * f * t).sin()).sum();
+                (amplitude * sum * 32767.0) as i16
+            })
+            .collect()
+    }
+
+    fn analyse(samples: &[i16]) -> AudioAnalysis {
+        let mut analyzer = AudioAnalyzer::new(InboundAnalysisConfig::default());
+        for frame in samples.chunks(160) {
+            analyzer.process(frame, 8000);
+        }
+        analyzer.end_tracks();
+        analyzer.snapshot()
+    }
+
+    #[test]
+    fn test_levels_clipping_and_silence() {
+        let mut samples = tone(&[1000.0], 0.5, 1.0);
+        samples.extend(std::iter::repeat_n(0, 8000));
+        samples.extend(std::iter::repeat_n(32767, 10));
+
+        let analysis = analyse(&samples);
+        assert_eq!(analysis.samples, 16010);
+        assert!((analysis.peak_dbfs - 0.0).abs() < 0.01);
+        assert_eq!(analysis.clipped_samples, 10);
+        assert!((analysis.silence_ratio - 0.5).abs() < 0.01, "silence {}", analysis.silence_ratio);
+        // Half a second's worth of energy of a -9 dBFS RMS tone over two seconds
+        assert!((analysis.rms_dbfs - (-9.03 - 3.01)).abs() < 0.2, "rms {}", analysis.rms_dbfs);
+    }
+
+    #[test]
+    fn test_detects_tones() {
+        // DTMF '5' (770 + 1336Hz) bursts, then a steady 1004Hz test tone
+        let mut samples = Vec::new();
+        for _ in 0..3 {
+            samples.extend(tone(&[770.0, 1336.0], 0.25, 0.2));
+            samples.extend(std::iter::repeat_n(0, 1600));
+        }
+        samples.extend(tone(&[1004.0], 0.5, 2.0));
+
+        let analysis = analyse(&samples);
+        let frequencies: Vec<f32> = analysis.tones.iter().map(|t| t.frequency_hz).collect();
+        assert_eq!(analysis.tones.len(), 3, "tones {:?}", frequencies);
+
+        let low = &analysis.tones[0];
+        assert!((low.frequency_hz - 770.0).abs() < 10.0);
+        assert_eq!(low.occurrences, 3);
+        assert!((low.level_dbfs - to_dbfs(0.25 / std::f32::consts::SQRT_2)).abs() < 1.5);
+
+        let test_tone = &analysis.tones[1];
+        assert!((test_tone.frequency_hz - 1004.0).abs() < 5.0);
+        assert!(test_tone.duration_ms >= 1900);
+        assert!((analysis.tones[2].frequency_hz - 1336.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn test_spectral_summary() {
+        let analysis = analyse(&tone(&[2500.0], 0.5, 1.0));
+        let spectrum = analysis.spectrum.unwrap();
+
+        assert!((spectrum.dominant_hz - 2500.0).abs() < 32.0);
+        assert!((spectrum.centroid_hz - 2500.0).abs() < 50.0);
+        let loudest = spectrum.bands.iter().max_by(|a, b| a.level_dbfs.total_cmp(&b.level_dbfs)).unwrap();
+        assert_eq!((loudest.low_hz, loudest.high_hz), (2000.0, 3400.0));
+        assert!((loudest.level_dbfs - to_dbfs(0.5 / std::f32::consts::SQRT_2)).abs() < 0.5);
+        assert_eq!(spectrum.bands.last().unwrap().high_hz, 4000.0);
+    }
+
+    #[test]
+    fn test_noise_has_no_tones() {
+        // Deterministic white-ish noise from an LCG
+        let mut state = 12345u32;
+        let noise: Vec<i16> = (0..16000)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                ((state >> 16) as i16) / 4
+            })
+            .collect();
+        assert!(analyse(&noise).tones.is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+use anyhow::{Context, Result};
+use hound::{SampleFormat, WavReader};
+use log::info;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Audio a streaming cursor reads from disk at a time, off the async worker threads
+const READ_AHEAD: Duration = Duration::from_secs(1);
+
+/// Prompt audio prepared once at startup and shared by every call that plays it
+#[derive(Debug, Clone)]
+pub enum PromptAudio {
+    /// Short prompt held once in memory as PCM; calls only clone the `Arc`
+    Buffered { samples: Arc<[i16]>, sample_rate: u32 },
+    /// Long prompt decoded from the processed WAV file on the fly by each call
+    Streaming { path: PathBuf, sample_rate: u32, total_samples: u64 },
+}
+
+impl PromptAudio {
+    /// Wrap PCM samples; they are encoded per call for the negotiated codec
+    pub fn from_pcm(samples: Vec<i16>, sample_rate: u32) -> Self {
+        PromptAudio::Buffered { samples: samples.into(), sample_rate }
+    }
+
+    /// Load a mono 16-bit WAV, buffering it only if it is no longer than `max_buffered_seconds`
+    pub fn open_wav<P: AsRef<Path>>(path: P, max_buffered_seconds: u32) -> Result<Self> {
+        let path = path.as_ref();
+        let reader = WavReader::open(path)
+            .with_context(|| format!("Failed to open WAV file: {}", path.display()))?;
+
+        let spec = reader.spec();
+        if spec.channels != 1 || spec.bits_per_sample != 16 || spec.sample_format != SampleFormat::Int {
+            return Err(anyhow::anyhow!("Unsupported prompt format in {}: {} channels, {}-bit {:?} (expected mono 16-bit PCM)",
+                path.display(), spec.channels, spec.bits_per_sample, spec.sample_format));
+        }
+
+        let total_samples = reader.duration() as u64;
+        let max_buffered_samples = max_buffered_seconds as u64 * spec.sample_rate as u64;
+
+        if total_samples > max_buffered_samples {
+            info!("📼 Prompt {} ({}s) will be streamed from disk per call",
+                  path.display(), total_samples / spec.sample_rate as u64);
+            return Ok(PromptAudio::Streaming {
+                path: path.to_path_buf(),
+                sample_rate: spec.sample_rate,
+                total_samples,
+            });
+        }
+
+        let pcm_samples: Result<Vec<i16>, _> = reader.into_samples::<i16>().collect();
+        let pcm_samples = pcm_samples.context("Failed to read WAV samples")?;
+        info!("📼 Prompt {} buffered in memory ({} PCM samples shared by all calls)",
+              path.display(), pcm_samples.len());
+
+        Ok(Self::from_pcm(pcm_samples, spec.sample_rate))
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        match self {
+            PromptAudio::Buffered { sample_rate, .. } | PromptAudio::Streaming { sample_rate, .. } => *sample_rate,
+        }
+    }
+
+    /// Playback length of the whole prompt
+    pub fn duration(&self) -> Duration {
+        let total_samples = match self {
+            PromptAudio::Buffered { samples, .. } => samples.len() as u64,
+            PromptAudio::Streaming { total_samples, .. } => *total_samples,
+        };
+        Duration::from_millis(total_samples * 1000 / self.sample_rate().max(1) as u64)
+    }
+
+    /// Start an independent read position for one call
+    ///
+    /// Streaming cursors start reading ahead straight away, so this must be called
+    /// within a Tokio runtime.
+    pub fn cursor(&self) -> Result<PromptCursor> {
+        match self {
+            PromptAudio::Buffered { samples, .. } => Ok(PromptCursor::Buffered {
+                samples: Arc::clone(samples),
+                position: 0,
+            }),
+            PromptAudio::Streaming { path, sample_rate, .. } => {
+                let path = path.clone();
+                let chunk_len = (*sample_rate as u64 * READ_AHEAD.as_millis() as u64 / 1000) as usize;
+                let read_ahead = tokio::task::spawn_blocking(move || {
+                    let reader = WavReader::open(&path)
+                        .with_context(|| format!("Failed to open WAV file: {}", path.display()))?;
+                    read_chunk(reader, chunk_len)
+                });
+                Ok(PromptCursor::Streaming {
+                    chunk: Vec::new(),
+                    position: 0,
+                    chunk_len,
+                    read_ahead: Some(read_ahead),
+                })
+            }
+        }
+    }
+}
+
+type ChunkRead = JoinHandle<Result<(WavReader<BufReader<File>>, Vec<i16>)>>;
+
+/// Per-call read position into a prompt
+pub enum PromptCursor {
+    Buffered { samples: Arc<[i16]>, position: usize },
+    /// Plays one chunk while the next is read on a blocking thread; the reader
+    /// travels with the read, and is gone once the file is exhausted
+    Streaming { chunk: Vec<i16>, position: usize, chunk_len: usize, read_ahead: Option<ChunkRead> },
+}
+
+impl PromptCursor {
+    /// Replace `frame` with up to `frame_len` PCM samples; returns false once the prompt is exhausted
+    pub async fn next_frame(&mut self, frame: &mut Vec<i16>, frame_len: usize) -> Result<bool> {
+        frame.clear();
+
+        match self {
+            PromptCursor::Buffered { samples, position } => {
+                let end = (*position + frame_len).min(samples.len());
+                frame.extend_from_slice(&samples[*position..end]);
+                *position = end;
+            }
+            PromptCursor::Streaming { chunk, position, chunk_len, read_ahead } => {
+                while frame.len() < frame_len {
+                    if *position == chunk.len() {
+                        let Some(read) = read_ahead.take() else { break };
+                        let (reader, next) = read.await.context("WAV read task failed")??;
+                        if next.is_empty() {
+                            break;
+                        }
+                        *chunk = next;
+                        *position = 0;
+                        let chunk_len = *chunk_len;
+                        *read_ahead = Some(tokio::task::spawn_blocking(move || read_chunk(reader, chunk_len)));
+                    }
+                    let end = (*position + frame_len - frame.len()).min(chunk.len());
+                    frame.extend_from_slice(&chunk[*position..end]);
+                    *position = end;
+                }
+            }
+        }
+
+        Ok(!frame.is_empty())
+    }
+}
+
+/// Read up to `chunk_len` samples, handing the reader back for the next chunk
+fn read_chunk(mut reader: WavReader<BufReader<File>>, chunk_len: usize) -> Result<(WavReader<BufReader<File>>, Vec<i16>)> {
+    let chunk = reader.samples::<i16>().take(chunk_len).collect::<Result<Vec<_>, _>>()
+        .context("Failed to read WAV sample")?;
+    Ok((reader, chunk))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::{WavSpec, WavWriter};
+    use tempfile::TempDir;
+
+    fn write_wav(path: &Path, samples: &[i16]) {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(path, spec).unwrap();
+        for &sample in samples {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    async fn read_all(cursor: &mut PromptCursor) -> Vec<i16> {
+        let mut frame = Vec::new();
+        let mut samples = Vec::new();
+        while cursor.next_frame(&mut frame, 160).await.unwrap() {
+            assert!(frame.len() <= 160);
+            samples.extend_from_slice(&frame);
+        }
+        samples
+    }
+
+    #[tokio::test]
+    async fn test_buffered_prompt_is_shared() {
+        let prompt = PromptAudio::from_pcm(vec![0, 1000, -1000, 0], 8000);
+        let mut first = prompt.cursor().unwrap();
+        let second = prompt.cursor().unwrap();
+
+        if let PromptAudio::Buffered { samples, .. } = &prompt {
+            // The prompt itself plus one reference per cursor, no copies
+            assert_eq!(Arc::strong_count(samples), 3);
+        }
+
+        assert_eq!(read_all(&mut first).await, vec![0, 1000, -1000, 0]);
+        drop(second);
+    }
+
+    #[tokio::test]
+    async fn test_open_wav_selects_mode_by_length() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("prompt.wav");
+        let samples: Vec<i16> = (0..16000).map(|i| ((i % 100) * 100) as i16).collect();
+        write_wav(&path, &samples);
+
+        let buffered = PromptAudio::open_wav(&path, 5).unwrap();
+        assert!(matches!(buffered, PromptAudio::Buffered { .. }));
+        assert_eq!(buffered.duration(), Duration::from_secs(2));
+
+        let streaming = PromptAudio::open_wav(&path, 1).unwrap();
+        assert!(matches!(streaming, PromptAudio::Streaming { .. }));
+        assert_eq!(streaming.duration(), Duration::from_secs(2));
+
+        // Streamed playback delivers the exact PCM, frame by frame across read-ahead chunks
+        let mut cursor = streaming.cursor().unwrap();
+        assert_eq!(read_all(&mut cursor).await, samples);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_cursors_are_independent() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("prompt.wav");
+        let samples: Vec<i16> = (0..1000).map(|i| i as i16).collect();
+        write_wav(&path, &samples);
+
+        let prompt = PromptAudio::open_wav(&path, 0).unwrap();
+        let mut first = prompt.cursor().unwrap();
+        let mut second = prompt.cursor().unwrap();
+
+        let mut frame = Vec::new();
+        assert!(first.next_frame(&mut frame, 160).await.unwrap());
+        assert!(first.next_frame(&mut frame, 160).await.unwrap());
+        assert_eq!(frame[0], 160);
+
+        assert!(second.next_frame(&mut frame, 160).await.unwrap());
+        assert_eq!(frame[0], 0);
+    }
+}
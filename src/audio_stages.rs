@@ -0,0 +1,932 @@
+use std::collections::VecDeque;
+
+use crate::biquad::{Biquad, BiquadCoefficients};
+use crate::crossover::Crossover;
+use crate::config::{AudioProcessingConfig, CompressorBandConfig, NoiseGateConfig, StageKind};
+
+/// One step of the telephony processing chain
+///
+/// Stages run in the order given by `[audio_processing] stages`, either sample by
+/// sample or on whole blocks. New processing (EQ, AGC, de-esser, ...) is added by implementing this trait and
+/// registering a `StageKind` in `build_stage`; the processor itself does not change.
+pub trait AudioStage: Send {
+    /// Short identifier used in statistics and logs
+    fn name(&self) -> &'static str;
+
+    fn process_sample(&mut self, input: f32) -> f32;
+
+    /// Process a block in place; identical to calling `process_sample` on each sample
+    fn process_block(&mut self, block: &mut [f32]) {
+        for sample in block.iter_mut() {
+            *sample = self.process_sample(*sample);
+        }
+    }
+
+    /// Clear filter and envelope state along with the statistics
+    fn reset(&mut self);
+
+    /// Samples between input and output
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Activity of the stage since creation or the last reset
+    fn stats(&self) -> StageStats {
+        StageStats::default()
+    }
+}
+
+/// Activity reported by a stage
+#[derive(Debug, Clone, Default)]
+pub struct StageStats {
+    /// Samples the stage acted on (gate closed, limiter engaged, ...), if it tracks them
+    pub active_samples: Option<u64>,
+    /// Per-band statistics of multi-band stages
+    pub bands: Vec<BandStats>,
+}
+
+/// Peak and RMS level of a signal
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LevelStats {
+    pub peak: f32,
+    sum_squares: f64,
+    samples: u64,
+}
+
+impl LevelStats {
+    pub fn observe(&mut self, sample: f32) {
+        self.peak = self.peak.max(sample.abs());
+        self.sum_squares += (sample as f64) * (sample as f64);
+        self.samples += 1;
+    }
+
+    pub fn rms(&self) -> f32 {
+        if self.samples == 0 {
+            0.0
+        } else {
+            (self.sum_squares / self.samples as f64).sqrt() as f32
+        }
+    }
+
+    pub fn peak_dbfs(&self) -> f32 {
+        to_dbfs(self.peak)
+    }
+
+    pub fn rms_dbfs(&self) -> f32 {
+        to_dbfs(self.rms())
+    }
+}
+
+/// Gain applied by one compressor band
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BandStats {
+    /// Frequency range of the band in Hz
+    pub low_hz: f32,
+    pub high_hz: f32,
+    pub enabled: bool,
+    /// Level of the band before compression
+    pub level: LevelStats,
+    /// Largest gain reduction seen, in dB (positive = attenuation)
+    pub max_gain_reduction_db: f32,
+    /// Samples where the envelope was above the threshold
+    pub compressed_samples: u64,
+    gain_db_sum: f64,
+    samples: u64,
+}
+
+impl BandStats {
+    fn observe(&mut self, input: f32, gain: f32, compressing: bool) {
+        let gain_db = to_dbfs(gain);
+        self.level.observe(input);
+        self.max_gain_reduction_db = self.max_gain_reduction_db.max(-gain_db);
+        self.gain_db_sum += gain_db as f64;
+        self.samples += 1;
+        if compressing {
+            self.compressed_samples += 1;
+        }
+    }
+
+    /// Mean gain applied to the band, in dB (negative = attenuation)
+    pub fn average_gain_db(&self) -> f32 {
+        if self.samples == 0 {
+            0.0
+        } else {
+            (self.gain_db_sum / self.samples as f64) as f32
+        }
+    }
+}
+
+/// Linear amplitude to dB relative to full scale
+pub fn to_dbfs(level: f32) -> f32 {
+    20.0 * level.max(1e-10).log10()
+}
+
+/// Create the stage described by a config entry
+pub fn build_stage(kind: &StageKind, sample_rate: f32) -> Box<dyn AudioStage> {
+    match kind {
+        StageKind::Preemphasis { alpha } => Box::new(Preemphasis::new(*alpha)),
+        StageKind::Bandpass { low_freq, high_freq } => Box::new(Bandpass::new(*low_freq, *high_freq, sample_rate)),
+        StageKind::Compressor { split_freqs, bands } => {
+            Box::new(MultibandCompressor::new(split_freqs, bands, sample_rate))
+        }
+        StageKind::NoiseGate(config) => Box::new(NoiseGate::new(config, sample_rate)),
+        StageKind::SoftLimiter { threshold } => Box::new(SoftLimiter::new(*threshold)),
+        StageKind::Limiter { ceiling_dbfs, release_time, lookahead_time } => {
+            Box::new(LookaheadLimiter::new(*ceiling_dbfs, *release_time, *lookahead_time, sample_rate))
+        }
+    }
+}
+
+/// Create the enabled stages of the configured chain, in order
+pub fn build_chain(config: &AudioProcessingConfig, sample_rate: f32) -> Vec<Box<dyn AudioStage>> {
+    config
+        .effective_stages()
+        .iter()
+        .filter(|stage| stage.enabled)
+        .map(|stage| build_stage(&stage.kind, sample_rate))
+        .collect()
+}
+
+/// First-order preemphasis - boosts high frequencies for better telephony transmission
+pub struct Preemphasis {
+    alpha: f32,
+    prev: f32,
+}
+
+impl Preemphasis {
+    pub fn new(alpha: f32) -> Self {
+        Self { alpha, prev: 0.0 }
+    }
+}
+
+impl AudioStage for Preemphasis {
+    fn name(&self) -> &'static str {
+        "preemphasis"
+    }
+
+    fn process_sample(&mut self, input: f32) -> f32 {
+        let output = input - self.alpha * self.prev;
+        self.prev = input;
+        output
+    }
+
+    fn process_block(&mut self, block: &mut [f32]) {
+        let mut prev = self.prev;
+        for sample in block.iter_mut() {
+            let input = *sample;
+            *sample = input - self.alpha * prev;
+            prev = input;
+        }
+        self.prev = prev;
+    }
+
+    fn reset(&mut self) {
+        self.prev = 0.0;
+    }
+}
+
+/// Telephony bandwidth limiting (300-3400Hz by default)
+pub struct Bandpass {
+    filter: Biquad,
+}
+
+impl Bandpass {
+    pub fn new(low_freq: f32, high_freq: f32, sample_rate: f32) -> Self {
+        // Ensure frequencies are within Nyquist limit
+        let nyquist = sample_rate / 2.0;
+        let low_freq = low_freq.min(nyquist * 0.95);
+        let high_freq = high_freq.min(nyquist * 0.95);
+
+        Self {
+            filter: Biquad::new(BiquadCoefficients::bandpass(low_freq, high_freq, sample_rate)),
+        }
+    }
+}
+
+impl AudioStage for Bandpass {
+    fn name(&self) -> &'static str {
+        "bandpass"
+    }
+
+    fn process_sample(&mut self, input: f32) -> f32 {
+        self.filter.process(input)
+    }
+
+    fn process_block(&mut self, block: &mut [f32]) {
+        self.filter.process_block(block);
+    }
+
+    fn reset(&mut self) {
+        self.filter.reset();
+    }
+}
+
+/// One band of the multi-band compressor
+struct CompressorBand {
+    enabled: bool,
+    ratio: f32,
+    knee_width: f32,
+    threshold: f32,
+    /// Gain applied while the envelope stays below the threshold
+    makeup_gain: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+    envelope: f32,
+    stats: BandStats,
+}
+
+impl CompressorBand {
+    fn new(config: &CompressorBandConfig, sample_rate: f32, stats: BandStats) -> Self {
+        let threshold = config.target_level * config.threshold_factor;
+        Self {
+            enabled: config.enabled,
+            ratio: config.ratio,
+            knee_width: config.knee_width,
+            threshold,
+            // Gentle makeup gain for quiet signals
+            makeup_gain: (config.target_level / (threshold + 1e-10)).min(1.2),
+            attack_coeff: (-1.0 / (config.attack_time * sample_rate)).exp(),
+            release_coeff: (-1.0 / (config.release_time * sample_rate)).exp(),
+            envelope: 0.0,
+            stats,
+        }
+    }
+
+    fn compress(&mut self, input: f32) -> f32 {
+        if !self.enabled {
+            self.stats.observe(input, 1.0, false);
+            return input;
+        }
+
+        let input_level = input.abs();
+
+        // Envelope follower with proper attack/release
+        if input_level > self.envelope {
+            self.envelope = self.attack_coeff * self.envelope + (1.0 - self.attack_coeff) * input_level;
+        } else {
+            self.envelope = self.release_coeff * self.envelope + (1.0 - self.release_coeff) * input_level;
+        }
+
+        let threshold = self.threshold;
+        let gain = if self.envelope > threshold {
+            let excess = self.envelope - threshold;
+
+            // Soft knee compression
+            let knee_ratio = if excess < self.knee_width {
+                1.0 + (self.ratio - 1.0) * (excess / self.knee_width).powi(2)
+            } else {
+                self.ratio
+            };
+
+            let compressed_excess = excess / knee_ratio;
+            let compressed_level = threshold + compressed_excess;
+
+            // Calculate gain reduction
+            if self.envelope > 1e-10 {
+                compressed_level / self.envelope
+            } else {
+                1.0
+            }
+        } else {
+            self.makeup_gain
+        };
+
+        // Apply gain with safety limits
+        let gain = gain.clamp(0.1, 2.0);
+        self.stats.observe(input, gain, self.envelope > threshold);
+        let output = input * gain;
+
+        // Prevent NaN/Inf propagation
+        if output.is_finite() { output } else { 0.0 }
+    }
+}
+
+/// Dynamic range compressor with any number of bands
+///
+/// `split_freqs` holds the crossover frequencies in ascending order, so there is
+/// one more band than split frequencies. A Linkwitz-Riley crossover splits the
+/// signal, each band is compressed on its own and the bands are summed back
+/// together; with every band bypassed the sum has a flat magnitude response.
+pub struct MultibandCompressor {
+    crossover: Crossover,
+    bands: Vec<CompressorBand>,
+    /// Per-band samples of the current sample or block, reused between calls
+    band_samples: Vec<f32>,
+    band_blocks: Vec<Vec<f32>>,
+}
+
+impl MultibandCompressor {
+    /// # Panics
+    ///
+    /// If `bands` does not have exactly one more entry than `split_freqs`; configs
+    /// that passed `ServerConfig::validate` always do.
+    pub fn new(split_freqs: &[f32], bands: &[CompressorBandConfig], sample_rate: f32) -> Self {
+        assert_eq!(bands.len(), split_freqs.len() + 1,
+                   "compressor needs one more band than split frequencies");
+
+        let nyquist = sample_rate / 2.0;
+        let splits: Vec<f32> = split_freqs
+            .iter()
+            .map(|&freq| freq.min(nyquist * 0.95))
+            .collect();
+
+        let bands: Vec<CompressorBand> = bands
+            .iter()
+            .enumerate()
+            .map(|(i, config)| {
+                let stats = BandStats {
+                    low_hz: if i == 0 { 0.0 } else { splits[i - 1] },
+                    high_hz: splits.get(i).copied().unwrap_or(nyquist),
+                    enabled: config.enabled,
+                    ..BandStats::default()
+                };
+                CompressorBand::new(config, sample_rate, stats)
+            })
+            .collect();
+
+        Self {
+            crossover: Crossover::new(&splits, sample_rate),
+            band_samples: vec![0.0; bands.len()],
+            band_blocks: vec![Vec::new(); bands.len()],
+            bands,
+        }
+    }
+}
+
+impl AudioStage for MultibandCompressor {
+    fn name(&self) -> &'static str {
+        "compressor"
+    }
+
+    fn process_sample(&mut self, input: f32) -> f32 {
+        if self.bands.is_empty() {
+            return 0.0;
+        }
+
+        self.crossover.split(input, &mut self.band_samples);
+        let combined: f32 = self.bands.iter_mut()
+            .zip(&self.band_samples)
+            .map(|(band, &sample)| band.compress(sample))
+            .sum();
+
+        // Prevent NaN/Inf propagation
+        if combined.is_finite() { combined } else { 0.0 }
+    }
+
+    fn process_block(&mut self, block: &mut [f32]) {
+        if self.bands.is_empty() {
+            block.fill(0.0);
+            return;
+        }
+
+        self.crossover.split_block(block, &mut self.band_blocks);
+        block.fill(0.0);
+
+        for (band, samples) in self.bands.iter_mut().zip(&self.band_blocks) {
+            for (output, &sample) in block.iter_mut().zip(samples) {
+                *output += band.compress(sample);
+            }
+        }
+
+        // Prevent NaN/Inf propagation
+        for sample in block.iter_mut() {
+            if !sample.is_finite() {
+                *sample = 0.0;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.crossover.reset();
+        for band in &mut self.bands {
+            band.envelope = 0.0;
+            band.stats = BandStats {
+                low_hz: band.stats.low_hz,
+                high_hz: band.stats.high_hz,
+                enabled: band.stats.enabled,
+                ..BandStats::default()
+            };
+        }
+    }
+
+    fn stats(&self) -> StageStats {
+        StageStats {
+            active_samples: None,
+            bands: self.bands.iter().map(|band| band.stats).collect(),
+        }
+    }
+}
+
+/// Peak envelope decay of the noise gate detector; bridges zero crossings
+const GATE_ENVELOPE_TIME: f32 = 0.01;
+
+/// Noise gate to reduce background noise
+///
+/// Follows a peak envelope rather than individual samples, opens above the open
+/// threshold, holds while the envelope stays above the close threshold plus
+/// `hold_time`, and ramps the gain with the attack and release times.
+pub struct NoiseGate {
+    open_threshold: f32,
+    close_threshold: f32,
+    ratio: f32,
+    envelope_coeff: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+    hold_samples: u32,
+    envelope: f32,
+    gain: f32,
+    open: bool,
+    hold_remaining: u32,
+    gated_samples: u64,
+}
+
+impl NoiseGate {
+    pub fn new(config: &NoiseGateConfig, sample_rate: f32) -> Self {
+        let coeff = |time: f32| (-1.0 / (time * sample_rate)).exp();
+        Self {
+            open_threshold: config.open_threshold,
+            close_threshold: config.close_threshold.min(config.open_threshold),
+            ratio: config.ratio,
+            envelope_coeff: coeff(GATE_ENVELOPE_TIME),
+            attack_coeff: coeff(config.attack_time),
+            release_coeff: coeff(config.release_time),
+            hold_samples: (config.hold_time * sample_rate).round() as u32,
+            envelope: 0.0,
+            gain: config.ratio,
+            open: false,
+            hold_remaining: 0,
+            gated_samples: 0,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+impl AudioStage for NoiseGate {
+    fn name(&self) -> &'static str {
+        "noise_gate"
+    }
+
+    fn process_sample(&mut self, input: f32) -> f32 {
+        self.envelope = input.abs().max(self.envelope * self.envelope_coeff);
+
+        if self.envelope >= self.open_threshold {
+            self.open = true;
+            self.hold_remaining = self.hold_samples;
+        } else if self.open {
+            if self.envelope >= self.close_threshold {
+                self.hold_remaining = self.hold_samples;
+            } else if self.hold_remaining > 0 {
+                self.hold_remaining -= 1;
+            } else {
+                self.open = false;
+            }
+        }
+
+        let (target, coeff) = if self.open {
+            (1.0, self.attack_coeff)
+        } else {
+            self.gated_samples += 1;
+            (self.ratio, self.release_coeff)
+        };
+        self.gain = target + (self.gain - target) * coeff;
+
+        input * self.gain
+    }
+
+    fn reset(&mut self) {
+        self.envelope = 0.0;
+        self.gain = self.ratio;
+        self.open = false;
+        self.hold_remaining = 0;
+        self.gated_samples = 0;
+    }
+
+    fn stats(&self) -> StageStats {
+        StageStats { active_samples: Some(self.gated_samples), bands: Vec::new() }
+    }
+}
+
+/// Soft limiter to prevent clipping
+pub struct SoftLimiter {
+    threshold: f32,
+    limited_samples: u64,
+}
+
+impl SoftLimiter {
+    pub fn new(threshold: f32) -> Self {
+        Self { threshold, limited_samples: 0 }
+    }
+}
+
+impl AudioStage for SoftLimiter {
+    fn name(&self) -> &'static str {
+        "soft_limiter"
+    }
+
+    fn process_sample(&mut self, input: f32) -> f32 {
+        let threshold = self.threshold;
+
+        if input.abs() > threshold {
+            self.limited_samples += 1;
+            threshold * input.signum() * (1.0 - (-3.0 * (input.abs() - threshold)).exp())
+        } else {
+            input
+        }
+    }
+
+    fn reset(&mut self) {
+        self.limited_samples = 0;
+    }
+
+    fn stats(&self) -> StageStats {
+        StageStats { active_samples: Some(self.limited_samples), bands: Vec::new() }
+    }
+}
+
+/// Taps per phase of the true-peak interpolator
+const TRUE_PEAK_TAPS: usize = 8;
+/// Oversampling factor of the true-peak estimate
+const TRUE_PEAK_OVERSAMPLING: usize = 4;
+
+/// Lookahead true-peak limiter
+///
+/// The signal is delayed by the lookahead time so the gain is already down when a
+/// peak arrives. Peaks are estimated at 4x oversampling, which catches the inter-sample
+/// overs a plain sample-peak limiter lets through. The required gain is held over the
+/// lookahead window and averaged over it, giving a smooth attack that reaches the
+/// required gain exactly at the peak, then recovers with `release_time`. A final clamp
+/// keeps every output sample within the ceiling. Adds `lookahead + 3` samples of latency.
+pub struct LookaheadLimiter {
+    ceiling: f32,
+    release_coeff: f32,
+    lookahead: usize,
+    /// Fractional-delay FIR for each intermediate phase (1/4, 2/4, 3/4)
+    interpolation: [[f32; TRUE_PEAK_TAPS]; TRUE_PEAK_OVERSAMPLING - 1],
+    history: [f32; TRUE_PEAK_TAPS],
+    /// Audio waiting for the gain computed `lookahead` samples ahead
+    delay: VecDeque<f32>,
+    /// Ascending window minimum of the required gain: (sample index, gain)
+    hold: VecDeque<(u64, f32)>,
+    /// Last `lookahead` held gains and their sum
+    average: VecDeque<f32>,
+    average_sum: f64,
+    gain: f32,
+    position: u64,
+    limited_samples: u64,
+}
+
+impl LookaheadLimiter {
+    pub fn new(ceiling_dbfs: f32, release_time: f32, lookahead_time: f32, sample_rate: f32) -> Self {
+        let interpolation = std::array::from_fn(|phase| {
+            let fraction = (phase + 1) as f32 / TRUE_PEAK_OVERSAMPLING as f32;
+            let half = TRUE_PEAK_TAPS as f32 / 2.0;
+            let mut taps: [f32; TRUE_PEAK_TAPS] = std::array::from_fn(|tap| {
+                // Distance from the interpolated point to history[tap]
+                let t = fraction + (half - 1.0) - tap as f32;
+                let sinc = if t == 0.0 { 1.0 } else { (std::f32::consts::PI * t).sin() / (std::f32::consts::PI * t) };
+                let window = 0.5 * (1.0 + (std::f32::consts::PI * t / half).cos());
+                sinc * window
+            });
+            let sum: f32 = taps.iter().sum();
+            taps.iter_mut().for_each(|tap| *tap /= sum);
+            taps
+        });
+
+        let mut limiter = Self {
+            ceiling: 10f32.powf(ceiling_dbfs / 20.0),
+            release_coeff: (-1.0 / (release_time * sample_rate)).exp(),
+            lookahead: ((lookahead_time * sample_rate).round() as usize).max(1),
+            interpolation,
+            history: [0.0; TRUE_PEAK_TAPS],
+            delay: VecDeque::new(),
+            hold: VecDeque::new(),
+            average: VecDeque::new(),
+            average_sum: 0.0,
+            gain: 1.0,
+            position: 0,
+            limited_samples: 0,
+        };
+        limiter.reset();
+        limiter
+    }
+
+    /// Highest magnitude between the centre history sample and the next one
+    fn true_peak(&self) -> f32 {
+        let center = self.history[TRUE_PEAK_TAPS / 2 - 1].abs();
+        self.interpolation.iter().fold(center, |peak, taps| {
+            let value: f32 = taps.iter().zip(&self.history).map(|(tap, sample)| tap * sample).sum();
+            peak.max(value.abs())
+        })
+    }
+}
+
+impl AudioStage for LookaheadLimiter {
+    fn name(&self) -> &'static str {
+        "limiter"
+    }
+
+    fn process_sample(&mut self, input: f32) -> f32 {
+        self.history.copy_within(1.., 0);
+        self.history[TRUE_PEAK_TAPS - 1] = input;
+        let current = self.history[TRUE_PEAK_TAPS / 2 - 1];
+
+        let peak = self.true_peak();
+        let required = if peak > self.ceiling { self.ceiling / peak } else { 1.0 };
+
+        // Minimum required gain over the lookahead window
+        while self.hold.back().is_some_and(|&(_, gain)| gain >= required) {
+            self.hold.pop_back();
+        }
+        self.hold.push_back((self.position, required));
+        while self.hold.front().is_some_and(|&(index, _)| index + self.lookahead as u64 <= self.position) {
+            self.hold.pop_front();
+        }
+        self.position += 1;
+        let held = self.hold.front().map_or(1.0, |&(_, gain)| gain);
+
+        // Moving average of the held gain ramps down over the lookahead window
+        self.average.push_back(held);
+        self.average_sum += held as f64;
+        if let Some(oldest) = self.average.pop_front() {
+            self.average_sum -= oldest as f64;
+        }
+        let target = (self.average_sum / self.lookahead as f64) as f32;
+
+        self.gain = if target < self.gain {
+            target
+        } else {
+            target + (self.gain - target) * self.release_coeff
+        };
+
+        self.delay.push_back(current);
+        let delayed = self.delay.pop_front().unwrap_or(0.0);
+
+        if self.gain < 1.0 {
+            self.limited_samples += 1;
+        }
+        let output = (delayed * self.gain).clamp(-self.ceiling, self.ceiling);
+
+        // Prevent NaN/Inf propagation
+        if output.is_finite() { output } else { 0.0 }
+    }
+
+    fn reset(&mut self) {
+        self.history = [0.0; TRUE_PEAK_TAPS];
+        self.delay = std::iter::repeat_n(0.0, self.lookahead - 1).collect();
+        self.hold.clear();
+        self.average = std::iter::repeat_n(1.0, self.lookahead).collect();
+        self.average_sum = self.lookahead as f64;
+        self.gain = 1.0;
+        self.position = 0;
+        self.limited_samples = 0;
+    }
+
+    fn latency(&self) -> usize {
+        self.lookahead - 1 + TRUE_PEAK_TAPS / 2
+    }
+
+    fn stats(&self) -> StageStats {
+        StageStats { active_samples: Some(self.limited_samples), bands: Vec::new() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AudioStageConfig;
+
+    fn sine(freq: f32, amplitude: f32, samples: usize) -> impl Iterator<Item = f32> {
+        (0..samples).map(move |i| amplitude * (2.0 * std::f32::consts::PI * freq * i as f32 / 8000.0).sin())
+    }
+
+    #[test]
+    fn test_legacy_chain() {
+        let chain = build_chain(&AudioProcessingConfig::default(), 8000.0);
+        let names: Vec<&str> = chain.iter().map(|stage| stage.name()).collect();
+        assert_eq!(names, ["preemphasis", "bandpass", "compressor", "noise_gate", "limiter"]);
+        assert_eq!(chain[2].stats().bands.len(), 3);
+    }
+
+    #[test]
+    fn test_configured_chain_order_and_enable() {
+        let config: AudioProcessingConfig = toml::from_str(r#"
+            [[stages]]
+            stage = "soft_limiter"
+            threshold = 0.8
+
+            [[stages]]
+            stage = "noise_gate"
+            enabled = false
+            open_threshold = 0.01
+            ratio = 0.1
+
+            [[stages]]
+            stage = "compressor"
+            split_freqs = [500.0, 1000.0, 2000.0, 3000.0]
+            bands = [
+                { target_level = 0.5, attack_time = 0.01, release_time = 0.1, ratio = 3.0, threshold_factor = 0.7, knee_width = 0.1, enabled = true },
+                { target_level = 0.5, attack_time = 0.01, release_time = 0.1, ratio = 3.0, threshold_factor = 0.7, knee_width = 0.1, enabled = true },
+                { target_level = 0.5, attack_time = 0.01, release_time = 0.1, ratio = 3.0, threshold_factor = 0.7, knee_width = 0.1, enabled = true },
+                { target_level = 0.5, attack_time = 0.01, release_time = 0.1, ratio = 3.0, threshold_factor = 0.7, knee_width = 0.1, enabled = false },
+                { target_level = 0.5, attack_time = 0.01, release_time = 0.1, ratio = 3.0, threshold_factor = 0.7, knee_width = 0.1, enabled = true },
+            ]
+        "#).unwrap();
+
+        let chain = build_chain(&config, 8000.0);
+        let names: Vec<&str> = chain.iter().map(|stage| stage.name()).collect();
+        assert_eq!(names, ["soft_limiter", "compressor"]);
+
+        let bands = chain[1].stats().bands;
+        assert_eq!(bands.len(), 5);
+        assert_eq!((bands[0].low_hz, bands[0].high_hz), (0.0, 500.0));
+        assert_eq!((bands[2].low_hz, bands[2].high_hz), (1000.0, 2000.0));
+        assert_eq!((bands[4].low_hz, bands[4].high_hz), (3000.0, 4000.0));
+        assert!(!bands[3].enabled);
+    }
+
+    #[test]
+    fn test_compressor_band_isolation() {
+        let bands = vec![CompressorBandConfig::default(); 4];
+        let mut compressor = MultibandCompressor::new(&[600.0, 1200.0, 2400.0], &bands, 8000.0);
+        for sample in sine(1800.0, 0.9, 8000) {
+            compressor.process_sample(sample);
+        }
+
+        // Only the band containing the tone is driven into compression
+        let stats = compressor.stats();
+        let loudest = stats.bands.iter()
+            .enumerate()
+            .max_by(|a, b| a.1.level.rms().total_cmp(&b.1.level.rms()))
+            .map(|(i, _)| i);
+        assert_eq!(loudest, Some(2));
+        assert!(stats.bands[2].max_gain_reduction_db > 0.0);
+        assert_eq!(stats.bands[0].compressed_samples, 0);
+
+        compressor.reset();
+        assert_eq!(compressor.stats().bands[2].compressed_samples, 0);
+        assert_eq!(compressor.stats().bands[2].low_hz, 1200.0);
+    }
+
+    #[test]
+    fn test_single_band_compressor_is_unfiltered() {
+        let mut compressor = MultibandCompressor::new(&[], &[CompressorBandConfig {
+            enabled: false,
+            ..CompressorBandConfig::default()
+        }], 8000.0);
+
+        for sample in sine(200.0, 0.5, 100) {
+            assert_eq!(compressor.process_sample(sample), sample);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "one more band than split frequencies")]
+    fn test_compressor_rejects_mismatched_bands() {
+        MultibandCompressor::new(&[800.0, 2500.0], &vec![CompressorBandConfig::default(); 2], 8000.0);
+    }
+
+    #[test]
+    fn test_bypassed_compressor_is_flat() {
+        let bypassed = CompressorBandConfig { enabled: false, ..CompressorBandConfig::default() };
+        let mut compressor = MultibandCompressor::new(&[800.0, 2500.0], &vec![bypassed; 3], 8000.0);
+
+        for freq in [100.0, 400.0, 800.0, 1200.0, 2500.0, 3000.0, 3800.0] {
+            compressor.reset();
+            let output: Vec<f32> = sine(freq, 0.5, 8000).map(|s| compressor.process_sample(s)).collect();
+            let rms = (output[2000..].iter().map(|s| s * s).sum::<f32>() / 6000.0).sqrt();
+            let gain_db = to_dbfs(rms / (0.5 / std::f32::consts::SQRT_2));
+            assert!(gain_db.abs() < 0.05, "{}Hz: {:.3} dB", freq, gain_db);
+        }
+    }
+
+    #[test]
+    fn test_block_processing_matches_per_sample() {
+        let input: Vec<f32> = sine(440.0, 0.8, 4000).zip(sine(2900.0, 0.3, 4000)).map(|(a, b)| a + b).collect();
+
+        let mut per_sample = build_chain(&AudioProcessingConfig::default(), 8000.0);
+        let expected: Vec<f32> = input.iter()
+            .map(|&sample| per_sample.iter_mut().fold(sample, |s, stage| stage.process_sample(s)))
+            .collect();
+
+        // One 20ms RTP frame at a time, stage by stage
+        let mut block_chain = build_chain(&AudioProcessingConfig::default(), 8000.0);
+        let mut output = input.clone();
+        for block in output.chunks_mut(160) {
+            for stage in &mut block_chain {
+                stage.process_block(block);
+            }
+        }
+
+        assert_eq!(output, expected);
+        assert_eq!(block_chain[2].stats().bands[1].compressed_samples,
+                   per_sample[2].stats().bands[1].compressed_samples);
+    }
+
+    #[test]
+    fn test_limiter_transparent_below_ceiling() {
+        let mut limiter = LookaheadLimiter::new(-1.0, 0.05, 0.005, 8000.0);
+        let input: Vec<f32> = sine(440.0, 0.5, 800).collect();
+        let output: Vec<f32> = input.iter().map(|&s| limiter.process_sample(s)).collect();
+
+        let latency = limiter.latency();
+        assert_eq!(latency, 43);
+        assert!(output[..latency].iter().all(|&s| s == 0.0));
+        assert_eq!(&output[latency..], &input[..input.len() - latency]);
+        assert_eq!(limiter.stats().active_samples, Some(0));
+    }
+
+    #[test]
+    fn test_limiter_catches_inter_sample_peaks() {
+        // At fs/4 with a 45 degree phase every sample is at 0.707 of the true peak,
+        // so a sample-peak limiter never sees the overs
+        let mut limiter = LookaheadLimiter::new(-1.0, 0.05, 0.005, 8000.0);
+        let ceiling = 10f32.powf(-1.0 / 20.0);
+        let output: Vec<f32> = (0..4000)
+            .map(|i| 0.99 * (std::f32::consts::PI * (i as f32 / 2.0 + 0.25)).sin())
+            .map(|s| limiter.process_sample(s))
+            .collect();
+
+        let steady_peak = output[2000..].iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!(steady_peak < 0.7 * 0.99, "sample peak {}", steady_peak);
+        assert!((steady_peak / std::f32::consts::FRAC_1_SQRT_2 - ceiling).abs() < 0.02);
+        assert!(limiter.stats().active_samples.unwrap() > 3000);
+    }
+
+    #[test]
+    fn test_limiter_output_fits_g711() {
+        let mut limiter = LookaheadLimiter::new(crate::g711::full_scale_dbfs(), 0.05, 0.002, 8000.0);
+
+        // Hard-driven square wave with sudden bursts
+        let mut peak = 0i16;
+        let mut gain_before_burst = 1.0;
+        for i in 0..8000 {
+            let amplitude = if (i / 1000) % 2 == 0 { 0.1 } else { 1.6 };
+            let input = if (i / 7) % 2 == 0 { amplitude } else { -amplitude };
+            let output = limiter.process_sample(input);
+            let pcm = (output * 32767.0).clamp(-32768.0, 32767.0) as i16;
+            peak = peak.max(pcm.saturating_abs());
+
+            // The gain is already reduced before the first loud sample leaves the delay
+            if i == 1000 + limiter.latency() - 1 {
+                gain_before_burst = output.abs() / 0.1;
+            }
+        }
+
+        assert!(peak <= crate::g711::FULL_SCALE, "peak {}", peak);
+        assert!(gain_before_burst < 0.99, "gain {}", gain_before_burst);
+    }
+
+    #[test]
+    fn test_noise_gate_does_not_chatter() {
+        // A quiet 100Hz tone just above the open threshold passes whole once the gate is
+        // open, zero crossings included
+        let mut gate = NoiseGate::new(&NoiseGateConfig::default(), 8000.0);
+        let input: Vec<f32> = sine(100.0, 0.02, 4000).collect();
+        let output: Vec<f32> = input.iter().map(|&s| gate.process_sample(s)).collect();
+
+        assert!(gate.is_open());
+        assert!(gate.stats().active_samples.unwrap() < 10);
+        for (out, inp) in output[400..].iter().zip(&input[400..]) {
+            assert!((out - inp).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_noise_gate_hysteresis_and_hold() {
+        let config = NoiseGateConfig::default();
+        let mut gate = NoiseGate::new(&config, 8000.0);
+        let mut run = |amplitude: f32, samples: usize| {
+            let mut max_step = 0.0f32;
+            let mut previous = gate.gain;
+            for s in sine(200.0, amplitude, samples) {
+                gate.process_sample(s);
+                max_step = max_step.max((gate.gain - previous).abs());
+                previous = gate.gain;
+            }
+            (gate.is_open(), gate.gain, max_step)
+        };
+
+        assert!(run(0.05, 800).0);
+        // Between the close and open thresholds the gate stays open
+        assert!(run(0.007, 2000).0);
+        // Below the close threshold it holds for 50ms, then closes
+        assert!(run(0.002, 300).0);
+        let (open, gain, max_step) = run(0.002, 4000);
+        assert!(!open);
+        assert!((gain - config.ratio).abs() < 0.01);
+        // Smooth release instead of an instant drop
+        assert!(max_step < 0.01, "gain step {}", max_step);
+        // Falling below the open threshold again does not reopen it
+        assert!(!run(0.007, 2000).0);
+    }
+
+    #[test]
+    fn test_stage_entry_defaults_to_enabled() {
+        let stage: AudioStageConfig = toml::from_str("stage = \"preemphasis\"\nalpha = 0.9").unwrap();
+        assert!(stage.enabled);
+        assert!(matches!(stage.kind, StageKind::Preemphasis { alpha } if alpha == 0.9));
+    }
+}
@@ -0,0 +1,291 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::{Arg, Command};
+use hound::{WavReader, WavSpec, WavWriter};
+
+use rvoip_sip_server::config::ServerConfig;
+use rvoip_sip_server::g711;
+use rvoip_sip_server::loudness::LoudnessReport;
+use rvoip_sip_server::mp3_handler::{self, ProcessorStats};
+
+/// G.711 variant used for the round-trip output
+#[derive(Debug, Clone, Copy)]
+enum Codec {
+    Pcmu,
+    Pcma,
+}
+
+impl Codec {
+    fn parse(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "pcmu" | "ulaw" | "mulaw" => Ok(Codec::Pcmu),
+            "pcma" | "alaw" => Ok(Codec::Pcma),
+            other => Err(anyhow::anyhow!("Unsupported codec: {} (expected pcmu or pcma)", other)),
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Codec::Pcmu => "pcmu",
+            Codec::Pcma => "pcma",
+        }
+    }
+
+    fn encode(self, pcm_samples: &[i16]) -> Vec<u8> {
+        match self {
+            Codec::Pcmu => g711::pcm_to_mulaw(pcm_samples),
+            Codec::Pcma => g711::pcm_to_alaw(pcm_samples),
+        }
+    }
+
+    fn decode(self, encoded: &[u8]) -> Vec<i16> {
+        match self {
+            Codec::Pcmu => g711::mulaw_to_pcm(encoded),
+            Codec::Pcma => g711::alaw_to_pcm(encoded),
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let matches = Command::new("audio-tool")
+        .version("0.1.0")
+        .about("Run an audio file through the telephony processing pipeline offline")
+        .arg(
+            Arg::new("input")
+                .value_name("INPUT")
+                .help("Audio file to process (MP3, WAV, FLAC)")
+                .required(true),
+        )
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .value_name("FILE")
+                .help("Server configuration file providing [audio_processing] (built-in defaults if omitted)"),
+        )
+        .arg(
+            Arg::new("output-dir")
+                .short('o')
+                .long("output-dir")
+                .value_name("DIR")
+                .help("Directory for the processed and round-trip files")
+                .default_value("."),
+        )
+        .arg(
+            Arg::new("codec")
+                .long("codec")
+                .value_name("CODEC")
+                .help("G.711 codec for the round trip (pcmu or pcma)")
+                .default_value("pcmu"),
+        )
+        .arg(
+            Arg::new("sample-rate")
+                .short('r')
+                .long("sample-rate")
+                .value_name("HZ")
+                .help("Target sample rate (defaults to media.audio_sample_rate)")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("seconds")
+                .short('s')
+                .long("seconds")
+                .value_name("SECONDS")
+                .help("Maximum seconds of audio to process (0 = whole file)")
+                .value_parser(clap::value_parser!(u32))
+                .default_value("0"),
+        )
+        .get_matches();
+
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn"))
+        .format_timestamp_secs()
+        .init();
+
+    let input = Path::new(matches.get_one::<String>("input").unwrap());
+    let output_dir = PathBuf::from(matches.get_one::<String>("output-dir").unwrap());
+    let codec = Codec::parse(matches.get_one::<String>("codec").unwrap())?;
+    let max_seconds = *matches.get_one::<u32>("seconds").unwrap();
+
+    let server_config = match matches.get_one::<String>("config") {
+        Some(path) => {
+            let config = ServerConfig::load_from_file(path)
+                .with_context(|| format!("Failed to load config from {}", path))?;
+            config.validate()?;
+            config
+        }
+        None => ServerConfig::default(),
+    };
+    let sample_rate = matches.get_one::<u32>("sample-rate").copied()
+        .unwrap_or(server_config.media.audio_sample_rate);
+
+    fs::create_dir_all(&output_dir)
+        .with_context(|| format!("Failed to create output directory {}", output_dir.display()))?;
+    let stem = input.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    let processed_path = output_dir.join(format!("{}.processed.wav", stem));
+    let encoded_path = output_dir.join(format!("{}.{}", stem, codec.extension()));
+    let round_trip_path = output_dir.join(format!("{}.{}.wav", stem, codec.extension()));
+
+    // Telephony processing, exactly as the server prepares its prompt
+    let report = mp3_handler::convert_to_telephony_wav(
+        input,
+        &processed_path,
+        &server_config.audio_processing,
+        sample_rate,
+        1,
+        max_seconds,
+    )?;
+
+    // G.711 encode and decode of the processed audio
+    let processed = read_pcm(&processed_path)?;
+    let encoded = codec.encode(&processed);
+    let decoded = codec.decode(&encoded);
+
+    fs::write(&encoded_path, &encoded)
+        .with_context(|| format!("Failed to write {}", encoded_path.display()))?;
+    write_pcm(&round_trip_path, &decoded, sample_rate)?;
+
+    println!("Input:      {}", input.display());
+    println!("Processed:  {}", processed_path.display());
+    println!("Encoded:    {} ({} bytes, {:?})", encoded_path.display(), encoded.len(), codec);
+    println!("Round trip: {}", round_trip_path.display());
+    println!();
+    print_report(&report.channels[0], sample_rate);
+    println!();
+    if let Some(loudness) = &report.loudness {
+        print_loudness(loudness);
+        println!();
+    } else if server_config.audio_processing.loudness.enabled {
+        println!("Loudness: no active signal, normalization skipped");
+        println!();
+    }
+    println!("G.711 round-trip SNR: {:.1} dB", snr_db(&processed, &decoded));
+
+    Ok(())
+}
+
+fn read_pcm(path: &Path) -> Result<Vec<i16>> {
+    let mut reader = WavReader::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let samples: Result<Vec<i16>, _> = reader.samples::<i16>().collect();
+    samples.context("Failed to read WAV samples")
+}
+
+fn write_pcm(path: &Path, samples: &[i16], sample_rate: u32) -> Result<()> {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(path, spec)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    for &sample in samples {
+        writer.write_sample(sample).context("Failed to write sample")?;
+    }
+    writer.finalize().context("Failed to finalize WAV file")
+}
+
+fn print_report(stats: &ProcessorStats, sample_rate: u32) {
+    let percent = |count: u64| 100.0 * count as f64 / stats.samples.max(1) as f64;
+
+    println!("Processed {} samples ({:.1}s at {}Hz)",
+        stats.samples, stats.samples as f64 / sample_rate as f64, sample_rate);
+    println!("{:<8} {:>12} {:>12}", "", "peak dBFS", "RMS dBFS");
+    println!("{:<8} {:>12.1} {:>12.1}", "input", stats.input.peak_dbfs(), stats.input.rms_dbfs());
+    println!("{:<8} {:>12.1} {:>12.1}", "output", stats.output.peak_dbfs(), stats.output.rms_dbfs());
+
+    for (name, stage) in &stats.stages {
+        if let Some(active_samples) = stage.active_samples {
+            println!();
+            println!("{}: active on {:.1}% of samples", name, percent(active_samples));
+        }
+        if stage.bands.is_empty() {
+            continue;
+        }
+
+        println!();
+        println!("{}:", name);
+        println!("{:<6} {:>13} {:>10} {:>10} {:>10} {:>10} {:>11}",
+            "band", "range Hz", "peak dBFS", "RMS dBFS", "avg gain", "max GR", "compressed");
+        for (i, band) in stage.bands.iter().enumerate() {
+            let range = format!("{:.0}-{:.0}", band.low_hz, band.high_hz);
+            if !band.enabled {
+                println!("{:<6} {:>13} {:>10.1} {:>10.1} {:>10} {:>10} {:>11}",
+                    i + 1, range, band.level.peak_dbfs(), band.level.rms_dbfs(), "off", "-", "-");
+                continue;
+            }
+            println!("{:<6} {:>13} {:>10.1} {:>10.1} {:>9.1}dB {:>8.1}dB {:>10.1}%",
+                i + 1, range, band.level.peak_dbfs(), band.level.rms_dbfs(),
+                band.average_gain_db(), band.max_gain_reduction_db, percent(band.compressed_samples));
+        }
+    }
+}
+
+fn print_loudness(loudness: &LoudnessReport) {
+    let unit = loudness.method.unit();
+    println!("Loudness ({:?}):", loudness.method);
+    println!("  measured  {:>7.1} {} ({:.0}% active)", loudness.measured_level, unit, loudness.activity * 100.0);
+    println!("  target    {:>7.1} {}", loudness.target_level, unit);
+    println!("  gain      {:>+7.1} dB{}", loudness.applied_gain_db,
+        if loudness.peak_limited { " (limited by peak)" } else { "" });
+    println!("  peak      {:>7.1} dBFS before normalization", loudness.peak_dbfs);
+}
+
+/// Signal-to-noise ratio of the round trip, treating the difference as noise
+fn snr_db(reference: &[i16], decoded: &[i16]) -> f64 {
+    let (signal, noise) = reference.iter().zip(decoded).fold((0.0, 0.0), |(signal, noise), (&r, &d)| {
+        let error = r as f64 - d as f64;
+        (signal + (r as f64).powi(2), noise + error * error)
+    });
+
+    if noise == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (signal / noise).log10()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rvoip_sip_server::config::AudioProcessingConfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_convert_to_telephony_wav_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let input_path = dir.path().join("tone.wav");
+        let processed_path = dir.path().join("tone.processed.wav");
+
+        // Two seconds of a 1kHz tone at 16kHz, resampled to 8kHz and capped at one second
+        let tone: Vec<i16> = (0..32000)
+            .map(|i| (8000.0 * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 16000.0).sin()) as i16)
+            .collect();
+        write_pcm(&input_path, &tone, 16000).unwrap();
+
+        let report = mp3_handler::convert_to_telephony_wav(
+            &input_path,
+            &processed_path,
+            &AudioProcessingConfig::default(),
+            8000,
+            1,
+            1,
+        ).unwrap();
+        assert_eq!(report.channels.len(), 1);
+
+        let reader = WavReader::open(&processed_path).unwrap();
+        assert_eq!((reader.spec().channels, reader.spec().sample_rate), (1, 8000));
+
+        let processed = read_pcm(&processed_path).unwrap();
+        assert_eq!(processed.len(), 8000);
+        assert!(processed.iter().any(|&sample| sample.unsigned_abs() > 1000));
+
+        for codec in [Codec::Pcmu, Codec::Pcma] {
+            let decoded = codec.decode(&codec.encode(&processed));
+            assert_eq!(decoded.len(), processed.len());
+            assert!(snr_db(&processed, &decoded) > 30.0, "{:?}", codec);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::f32::consts::{PI, SQRT_2};
+
+/// Normalised (a0 = 1) coefficients of a 2nd order IIR section
+///
+/// Designed once from the configuration and copied into every filter instance, so
+/// per-call filters never evaluate trigonometric functions while running.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiquadCoefficients {
+    pub b0: f32,
+    pub b1: f32,
+    pub b2: f32,
+    pub a1: f32,
+    pub a2: f32,
+}
+
+impl BiquadCoefficients {
+    /// Coefficients already normalised so that a0 = 1
+    pub fn new(b0: f32, b1: f32, b2: f32, a1: f32, a2: f32) -> Self {
+        Self { b0, b1, b2, a1, a2 }
+    }
+
+    /// Divide raw coefficients by `a0`
+    pub fn normalized(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Self {
+        Self::new(
+            (b0 / a0) as f32,
+            (b1 / a0) as f32,
+            (b2 / a0) as f32,
+            (a1 / a0) as f32,
+            (a2 / a0) as f32,
+        )
+    }
+
+    /// Passes the input unchanged
+    pub fn identity() -> Self {
+        Self::new(1.0, 0.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Pre-warped bilinear transform frequency
+    fn prewarp(freq: f32, sample_rate: f32) -> f32 {
+        let normalized = freq / (sample_rate / 2.0);
+        (PI * normalized / 2.0).tan()
+    }
+
+    /// 2nd order Butterworth low-pass
+    pub fn lowpass(cutoff_freq: f32, sample_rate: f32) -> Self {
+        let k = Self::prewarp(cutoff_freq, sample_rate);
+        let norm = 1.0 + SQRT_2 * k + k * k;
+        let b0 = k * k / norm;
+        let a1 = (2.0 * (k * k - 1.0)) / norm;
+        let a2 = (1.0 - SQRT_2 * k + k * k) / norm;
+        Self::new(b0, 2.0 * b0, b0, a1, a2)
+    }
+
+    /// 2nd order Butterworth high-pass
+    pub fn highpass(cutoff_freq: f32, sample_rate: f32) -> Self {
+        let k = Self::prewarp(cutoff_freq, sample_rate);
+        let norm = 1.0 + SQRT_2 * k + k * k;
+        let b0 = 1.0 / norm;
+        let a1 = (2.0 * (k * k - 1.0)) / norm;
+        let a2 = (1.0 - SQRT_2 * k + k * k) / norm;
+        Self::new(b0, -2.0 * b0, b0, a1, a2)
+    }
+
+    /// 2nd order Butterworth all-pass; equals the sum of the Linkwitz-Riley
+    /// low-pass and high-pass outputs at the same frequency
+    pub fn allpass(freq: f32, sample_rate: f32) -> Self {
+        let k = Self::prewarp(freq, sample_rate);
+        let norm = 1.0 + SQRT_2 * k + k * k;
+        let a1 = (2.0 * (k * k - 1.0)) / norm;
+        let a2 = (1.0 - SQRT_2 * k + k * k) / norm;
+        Self::new(a2, a1, 1.0, a1, a2)
+    }
+
+    /// 2nd order band-pass between two frequencies
+    pub fn bandpass(low_freq: f32, high_freq: f32, sample_rate: f32) -> Self {
+        let k1 = Self::prewarp(low_freq, sample_rate);
+        let k2 = Self::prewarp(high_freq, sample_rate);
+
+        let bw = k2 - k1;
+        let wc = (k1 * k2).sqrt();
+
+        let norm = 1.0 + bw + wc * wc;
+        let b0 = bw / norm;
+        let a1 = (2.0 * (wc * wc - 1.0)) / norm;
+        let a2 = (1.0 - bw + wc * wc) / norm;
+        Self::new(b0, 0.0, -b0, a1, a2)
+    }
+}
+
+/// 2nd order IIR filter (Direct Form I) with its own state
+#[derive(Debug, Clone)]
+pub struct Biquad {
+    coefficients: BiquadCoefficients,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl Biquad {
+    pub fn new(coefficients: BiquadCoefficients) -> Self {
+        Self { coefficients, x1: 0.0, x2: 0.0, y1: 0.0, y2: 0.0 }
+    }
+
+    pub fn coefficients(&self) -> &BiquadCoefficients {
+        &self.coefficients
+    }
+
+    #[inline]
+    pub fn process(&mut self, input: f32) -> f32 {
+        let c = &self.coefficients;
+        let output = c.b0 * input + c.b1 * self.x1 + c.b2 * self.x2
+                   - c.a1 * self.y1 - c.a2 * self.y2;
+
+        self.x2 = self.x1;
+        self.x1 = input;
+        self.y2 = self.y1;
+        self.y1 = output;
+
+        // Prevent NaN/Inf propagation
+        if output.is_finite() { output } else { 0.0 }
+    }
+
+    /// Filter a block in place
+    pub fn process_block(&mut self, block: &mut [f32]) {
+        let BiquadCoefficients { b0, b1, b2, a1, a2 } = self.coefficients;
+        let (mut x1, mut x2, mut y1, mut y2) = (self.x1, self.x2, self.y1, self.y2);
+
+        for sample in block.iter_mut() {
+            let input = *sample;
+            let output = b0 * input + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2;
+            x2 = x1;
+            x1 = input;
+            y2 = y1;
+            y1 = output;
+            *sample = if output.is_finite() { output } else { 0.0 };
+        }
+
+        (self.x1, self.x2, self.y1, self.y2) = (x1, x2, y1, y2);
+    }
+
+    /// Clear the filter history, keeping the coefficients
+    pub fn reset(&mut self) {
+        self.x1 = 0.0;
+        self.x2 = 0.0;
+        self.y1 = 0.0;
+        self.y2 = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, samples: usize) -> Vec<f32> {
+        (0..samples).map(|i| (2.0 * PI * freq * i as f32 / 8000.0).sin()).collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_block_matches_per_sample() {
+        let input = sine(440.0, 1000);
+        let coefficients = BiquadCoefficients::bandpass(300.0, 3400.0, 8000.0);
+
+        let mut per_sample = Biquad::new(coefficients);
+        let expected: Vec<f32> = input.iter().map(|&s| per_sample.process(s)).collect();
+
+        // Uneven block sizes carry the state across block boundaries
+        let mut block_filter = Biquad::new(coefficients);
+        let mut output = input.clone();
+        for block in output.chunks_mut(37) {
+            block_filter.process_block(block);
+        }
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_butterworth_responses() {
+        // Well inside the passband the level is kept, an octave or more outside it drops
+        let mut lowpass = Biquad::new(BiquadCoefficients::lowpass(1000.0, 8000.0));
+        let mut pass = sine(200.0, 4000);
+        let mut stop = sine(3000.0, 4000);
+        lowpass.process_block(&mut pass);
+        lowpass.reset();
+        lowpass.process_block(&mut stop);
+        assert!((rms(&pass[800..]) - rms(&sine(200.0, 4000))).abs() < 0.01);
+        assert!(rms(&stop[800..]) < 0.1);
+
+        let mut highpass = Biquad::new(BiquadCoefficients::highpass(1000.0, 8000.0));
+        let mut low = sine(100.0, 4000);
+        highpass.process_block(&mut low);
+        assert!(rms(&low[800..]) < 0.02);
+
+        let mut identity = Biquad::new(BiquadCoefficients::identity());
+        assert_eq!(identity.process(0.25), 0.25);
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::analysis::AudioAnalysis;
+use crate::config::CdrConfig;
+use crate::impairment::ImpairmentStats;
+use crate::loopback::LoopbackScore;
+use crate::rtp_stats::RtpCallStats;
+use crate::transfer::TransferRecord;
+
+/// Summary of one call, written once the call has ended
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallDetailRecord {
+    pub call_id: String,
+    pub caller: String,
+    pub callee: String,
+    /// Dialplan rule the call matched, if any
+    pub dialplan_rule: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub answer_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub duration_seconds: f64,
+    /// Time from answer to end
+    pub billable_seconds: f64,
+    #[serde(default)]
+    pub rtp: Option<RtpCallStats>,
+    /// Impairments applied to the audio we sent
+    #[serde(default)]
+    pub impairment: Option<ImpairmentStats>,
+    /// Times the call was put on hold, by the server, and the total time held
+    #[serde(default)]
+    pub hold_count: u32,
+    #[serde(default)]
+    pub hold_seconds: f64,
+    #[serde(default)]
+    pub transfer: Option<TransferRecord>,
+    pub inbound_analysis: Option<AudioAnalysis>,
+    /// Results of the dialplan's loopback tests, in order
+    #[serde(default)]
+    pub loopback_tests: Vec<LoopbackScore>,
+}
+
+impl CallDetailRecord {
+    pub fn new(call_id: impl Into<String>, caller: impl Into<String>, callee: impl Into<String>) -> Self {
+        Self {
+            call_id: call_id.into(),
+            caller: caller.into(),
+            callee: callee.into(),
+            dialplan_rule: None,
+            start_time: Utc::now(),
+            answer_time: None,
+            end_time: None,
+            duration_seconds: 0.0,
+            billable_seconds: 0.0,
+            rtp: None,
+            impairment: None,
+            hold_count: 0,
+            hold_seconds: 0.0,
+            transfer: None,
+            inbound_analysis: None,
+            loopback_tests: Vec::new(),
+        }
+    }
+
+    pub fn answered(&mut self, at: DateTime<Utc>) {
+        self.answer_time.get_or_insert(at);
+    }
+
+    pub fn ended(&mut self, at: DateTime<Utc>) {
+        self.end_time = Some(at);
+        self.duration_seconds = seconds_between(self.start_time, at);
+        self.billable_seconds = self.answer_time.map_or(0.0, |answer| seconds_between(answer, at));
+    }
+}
+
+fn seconds_between(start: DateTime<Utc>, end: DateTime<Utc>) -> f64 {
+    (end - start).num_milliseconds().max(0) as f64 / 1000.0
+}
+
+/// Appends records to a JSON-lines file
+pub struct CdrWriter {
+    path: PathBuf,
+    // Keeps concurrent calls from interleaving their lines
+    lock: Mutex<()>,
+}
+
+impl CdrWriter {
+    pub fn new(config: &CdrConfig) -> Result<Self> {
+        let path = PathBuf::from(&config.path);
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create CDR directory {}", parent.display()))?;
+        }
+        Ok(Self { path, lock: Mutex::new(()) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, record: &CallDetailRecord) -> Result<()> {
+        let line = serde_json::to_string(record).context("Failed to serialize CDR")?;
+
+        let _guard = self.lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open CDR file {}", self.path.display()))?;
+        writeln!(file, "{}", line)
+            .with_context(|| format!("Failed to write CDR to {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_records_appended_as_json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = CdrConfig {
+            enabled: true,
+            path: dir.path().join("cdr").join("calls.jsonl").to_string_lossy().into_owned(),
+        };
+        let writer = CdrWriter::new(&config).unwrap();
+
+        let mut first = CallDetailRecord::new("call-1", "sip:alice@example.com", "sip:echo@example.com");
+        first.answered(first.start_time + Duration::milliseconds(500));
+        first.ended(first.start_time + Duration::milliseconds(3500));
+        first.inbound_analysis = Some(AudioAnalysis { samples: 24000, ..AudioAnalysis::default() });
+        first.rtp = Some(RtpCallStats { packets_received: 150, ..RtpCallStats::default() });
+        writer.append(&first).unwrap();
+
+        let mut second = CallDetailRecord::new("call-2", "sip:bob@example.com", "sip:busy@example.com");
+        second.ended(second.start_time + Duration::seconds(1));
+        writer.append(&second).unwrap();
+
+        let contents = fs::read_to_string(writer.path()).unwrap();
+        let records: Vec<CallDetailRecord> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].call_id, "call-1");
+        assert_eq!((records[0].duration_seconds, records[0].billable_seconds), (3.5, 3.0));
+        assert_eq!(records[0].inbound_analysis.as_ref().unwrap().samples, 24000);
+        assert_eq!(records[0].rtp.as_ref().unwrap().packets_received, 150);
+        // Never answered: nothing billable
+        assert_eq!((records[1].duration_seconds, records[1].billable_seconds), (1.0, 0.0));
+        assert!(records[1].inbound_analysis.is_none());
+    }
+}
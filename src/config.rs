@@ -4,7 +4,12 @@ use std::fs;
 use std::net::IpAddr;
 use std::path::Path;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::loudness::LoudnessMethod;
+use crate::mp3_handler::DownmixMode;
+use crate::resampler::ResamplerQuality;
+use crate::tone_generator::DtmfConfig;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub sip: SipConfig,
     pub behavior: BehaviorConfig,
@@ -12,6 +17,224 @@ pub struct ServerConfig {
     pub logging: LoggingConfig,
     pub health: HealthConfig,
     pub audio_processing: AudioProcessingConfig,
+    #[serde(default)]
+    pub dialplan: DialplanConfig,
+    #[serde(default)]
+    pub inbound_processing: InboundProcessingConfig,
+    #[serde(default)]
+    pub inbound_analysis: InboundAnalysisConfig,
+    #[serde(default)]
+    pub cdr: CdrConfig,
+    #[serde(default)]
+    pub rtp_stats: RtpStatsConfig,
+    #[serde(default)]
+    pub hold: HoldConfig,
+    #[serde(default)]
+    pub transfer: TransferConfig,
+    #[serde(default)]
+    pub originate: OriginateConfig,
+    #[serde(default)]
+    pub load: LoadConfig,
+}
+
+/// Load profile of the `load` subcommand; the calls themselves follow `[originate]`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoadConfig {
+    /// Phases run in order, each ramping linearly from where the previous one ended
+    pub profile: Vec<LoadPhase>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoadPhase {
+    pub duration_ms: u64,
+    /// Call rate reached at the end of the phase
+    pub calls_per_second: f64,
+    /// Concurrency limit reached at the end of the phase
+    pub max_concurrent: u32,
+}
+
+impl LoadConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.profile.is_empty() {
+            return Err(anyhow::anyhow!("Invalid load profile: no phases"));
+        }
+        for phase in &self.profile {
+            if !(1..=86_400_000).contains(&phase.duration_ms) {
+                return Err(anyhow::anyhow!("Invalid load phase duration_ms: {} (must be between 1 and 86400000)", phase.duration_ms));
+            }
+            if !(0.0..=1000.0).contains(&phase.calls_per_second) {
+                return Err(anyhow::anyhow!("Invalid load phase calls_per_second: {} (must be between 0 and 1000)", phase.calls_per_second));
+            }
+            if phase.max_concurrent > 10_000 {
+                return Err(anyhow::anyhow!("Invalid load phase max_concurrent: {} (must be at most 10000)", phase.max_concurrent));
+            }
+        }
+        if self.profile.iter().all(|phase| phase.calls_per_second == 0.0) {
+            return Err(anyhow::anyhow!("Invalid load profile: calls_per_second is 0 throughout"));
+        }
+        Ok(())
+    }
+}
+
+/// Outbound calls placed by the server (the `originate` subcommand and `POST /originate`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OriginateConfig {
+    /// URI to call
+    pub target: String,
+    /// From URI; sip:server@<domain> when unset
+    pub from: Option<String>,
+    /// Number of calls to place
+    pub calls: u32,
+    pub calls_per_second: f64,
+    /// Calls up at once; new attempts wait for a slot
+    pub max_concurrent: u32,
+    /// Dialplan rule, by name, run on each answered call; the default playback when unset
+    pub rule: Option<String>,
+    /// Give up on calls not answered within this time
+    pub answer_timeout_ms: u64,
+    /// Hang up answered calls still up after this long (0 = leave it to the rule)
+    pub max_duration_ms: u64,
+}
+
+impl Default for OriginateConfig {
+    fn default() -> Self {
+        Self {
+            target: String::new(),
+            from: None,
+            calls: 1,
+            calls_per_second: 1.0,
+            max_concurrent: 10,
+            rule: None,
+            answer_timeout_ms: 30_000,
+            max_duration_ms: 0,
+        }
+    }
+}
+
+impl OriginateConfig {
+    /// These settings with the fields of a JSON object replacing theirs
+    pub fn with_overrides(&self, overrides: &serde_json::Value) -> Result<Self> {
+        let mut merged = serde_json::to_value(self)?;
+        let (Some(fields), Some(overrides)) = (merged.as_object_mut(), overrides.as_object()) else {
+            return Err(anyhow::anyhow!("Origination overrides must be a JSON object"));
+        };
+        fields.extend(overrides.iter().map(|(name, value)| (name.clone(), value.clone())));
+        serde_json::from_value(merged).context("Invalid origination settings")
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !crate::transfer::is_sip_uri(&self.target) {
+            return Err(anyhow::anyhow!("Invalid origination target: '{}' (must be a sip: or sips: URI)", self.target));
+        }
+        if let Some(from) = self.from.as_deref().filter(|from| !crate::transfer::is_sip_uri(from)) {
+            return Err(anyhow::anyhow!("Invalid origination from: '{}' (must be a sip: or sips: URI)", from));
+        }
+        if self.calls == 0 {
+            return Err(anyhow::anyhow!("Invalid origination calls: 0 (must be greater than 0)"));
+        }
+        if !(self.calls_per_second > 0.0 && self.calls_per_second <= 1000.0) {
+            return Err(anyhow::anyhow!("Invalid origination calls_per_second: {} (must be greater than 0 and at most 1000)", self.calls_per_second));
+        }
+        if !(1..=10_000).contains(&self.max_concurrent) {
+            return Err(anyhow::anyhow!("Invalid origination max_concurrent: {} (must be between 1 and 10000)", self.max_concurrent));
+        }
+        if !(1000..=300_000).contains(&self.answer_timeout_ms) {
+            return Err(anyhow::anyhow!("Invalid origination answer_timeout_ms: {} (must be between 1000 and 300000)", self.answer_timeout_ms));
+        }
+        Ok(())
+    }
+}
+
+/// Call transfers sent by the `transfer` dialplan action
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransferConfig {
+    /// How long a referred caller may take to leave the call
+    pub timeout_ms: u64,
+}
+
+impl Default for TransferConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 30_000,
+        }
+    }
+}
+
+/// Behaviour while a call is on hold
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HoldConfig {
+    /// WAV file looped to callers the server puts on hold (silence when unset)
+    pub music_file: Option<String>,
+}
+
+/// Optional processing of the audio received from each caller
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InboundProcessingConfig {
+    pub enabled: bool,
+    // Chain applied to received audio before it reaches recording, echo or analysis;
+    // independent of the prompt chain in [audio_processing]
+    pub profile: AudioProcessingConfig,
+}
+
+/// Quality analysis of the audio received from each caller
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InboundAnalysisConfig {
+    pub enabled: bool,
+    /// 20ms windows quieter than this count as silence
+    pub silence_threshold_dbfs: f32,
+    /// Shorter tone bursts are not reported
+    pub min_tone_duration_ms: u32,
+}
+
+impl Default for InboundAnalysisConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            silence_threshold_dbfs: -50.0,
+            min_tone_duration_ms: 60,
+        }
+    }
+}
+
+/// Per-call RTP/RTCP statistics, polled from the media layer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RtpStatsConfig {
+    pub enabled: bool,
+    /// Also the resolution of the reported maximum gap
+    pub poll_interval_ms: u64,
+}
+
+impl Default for RtpStatsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            poll_interval_ms: 200,
+        }
+    }
+}
+
+/// Call detail records, appended as one JSON object per line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CdrConfig {
+    pub enabled: bool,
+    pub path: String,
+}
+
+impl Default for CdrConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "/var/log/rvoip-sip-server/cdr.jsonl".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,6 +263,20 @@ pub struct MediaConfig {
     pub preferred_codecs: Vec<String>,
     pub enable_dtmf: bool,
     pub audio_sample_rate: u32,
+    // Seconds of the MP3 rendered into the prompt (0 = whole file)
+    #[serde(default = "default_prompt_duration_seconds")]
+    pub prompt_duration_seconds: u32,
+    // Prompts longer than this are streamed from disk per call instead of held in memory
+    #[serde(default = "default_max_buffered_prompt_seconds")]
+    pub max_buffered_prompt_seconds: u32,
+}
+
+fn default_prompt_duration_seconds() -> u32 {
+    30
+}
+
+fn default_max_buffered_prompt_seconds() -> u32 {
+    60
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,7 +299,10 @@ pub struct HealthConfig {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AudioProcessingConfig {
+    // Ordered processing chain; when empty the chain is built from the flat settings below
+    pub stages: Vec<AudioStageConfig>,
     pub preemphasis_alpha: f32,
     pub bandpass_low_freq: f32,
     pub bandpass_high_freq: f32,
@@ -72,35 +312,296 @@ pub struct AudioProcessingConfig {
     pub band1_compressor: CompressorBandConfig,  // Low-Mid band (300-800Hz)
     pub band2_compressor: CompressorBandConfig,  // Mid band (800-2500Hz)
     pub band3_compressor: CompressorBandConfig,  // High-Mid band (2500-3400Hz)
-    pub noise_gate_threshold: f32,
-    pub noise_gate_ratio: f32,
-    pub soft_limiter_threshold: f32,
+    pub noise_gate: NoiseGateConfig,
+    // Replaced by the `noise_gate` table; carried over to it when an older config is loaded
+    #[serde(skip_serializing)]
+    pub noise_gate_threshold: Option<f32>,
+    #[serde(skip_serializing)]
+    pub noise_gate_ratio: Option<f32>,
+    // Lookahead true-peak limiter, the last stage of the fixed chain
+    pub limiter_ceiling_dbfs: f32,
+    pub limiter_release_time: f32,
+    pub limiter_lookahead_time: f32,
+    // Replaced by the limiter above; carried over to its ceiling when an older config is loaded
+    #[serde(skip_serializing)]
+    pub soft_limiter_threshold: Option<f32>,
+    // Sample rate conversion quality for the source MP3
+    pub resampler_quality: ResamplerQuality,
+    // How stereo/multi-channel sources are folded to mono
+    pub downmix_mode: DownmixMode,
+    // Two-pass level normalization of the processed prompt
+    pub loudness: LoudnessConfig,
 }
 
+/// Normalizes each converted prompt to a common level
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CompressorBandConfig {
-    pub target_level: f32,
+#[serde(default)]
+pub struct LoudnessConfig {
+    pub enabled: bool,
+    pub method: LoudnessMethod,
+    // Target in dBov for p56, LUFS for r128
+    pub target_level: f64,
+    // The applied gain never pushes the sample peak above this
+    pub max_peak_dbfs: f64,
+}
+
+impl Default for LoudnessConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            method: LoudnessMethod::P56,
+            target_level: -26.0,
+            max_peak_dbfs: -1.0,
+        }
+    }
+}
+
+/// One entry of the `[[audio_processing.stages]]` chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioStageConfig {
+    #[serde(default = "default_stage_enabled")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub kind: StageKind,
+}
+
+fn default_stage_enabled() -> bool {
+    true
+}
+
+/// Processing stages available to the chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum StageKind {
+    /// First-order high-frequency boost
+    Preemphasis { alpha: f32 },
+    /// Telephony band limiting
+    Bandpass { low_freq: f32, high_freq: f32 },
+    /// Multi-band compressor; `bands` needs one more entry than `split_freqs`
+    Compressor {
+        split_freqs: Vec<f32>,
+        bands: Vec<CompressorBandConfig>,
+    },
+    /// Envelope gate with hysteresis that attenuates quiet passages by `ratio`
+    NoiseGate(NoiseGateConfig),
+    /// Memoryless soft-knee curve towards `threshold`; does not catch inter-sample peaks
+    SoftLimiter { threshold: f32 },
+    /// Lookahead true-peak limiter; output never exceeds `ceiling_dbfs`
+    Limiter {
+        ceiling_dbfs: f32,
+        release_time: f32,
+        lookahead_time: f32,
+    },
+}
+
+/// Noise gate settings (`[audio_processing.noise_gate]` or a `noise_gate` stage)
+///
+/// The gate opens when the signal envelope rises above `open_threshold` and closes
+/// once it has stayed below `close_threshold` for `hold_time`; in between it keeps
+/// its state, so it does not chatter on zero crossings or decaying words.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseGateConfig {
+    pub open_threshold: f32,
+    pub close_threshold: f32,
+    /// Gain applied while closed (0.0 mutes)
+    pub ratio: f32,
     pub attack_time: f32,
+    pub hold_time: f32,
     pub release_time: f32,
-    pub ratio: f32,
-    pub threshold_factor: f32,
-    pub knee_width: f32,
-    pub enabled: bool,
+    // Replaced by `open_threshold` and `close_threshold`; only read so that old configs are rejected
+    #[serde(skip_serializing)]
+    pub threshold: Option<f32>,
 }
 
-impl Default for ServerConfig {
+impl Default for NoiseGateConfig {
     fn default() -> Self {
         Self {
-            sip: SipConfig::default(),
-            behavior: BehaviorConfig::default(),
-            media: MediaConfig::default(),
-            logging: LoggingConfig::default(),
-            health: HealthConfig::default(),
-            audio_processing: AudioProcessingConfig::default(),
+            open_threshold: 0.01,
+            close_threshold: 0.005,
+            ratio: 0.1,
+            attack_time: 0.002,
+            hold_time: 0.05,
+            release_time: 0.1,
+            threshold: None,
         }
     }
 }
 
+/// Per-call scripted behaviour, matched against the callee URI
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DialplanConfig {
+    #[serde(default)]
+    pub rules: Vec<DialplanRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DialplanRule {
+    pub name: String,
+    /// Substring of the callee URI this rule applies to (matches every call when unset)
+    #[serde(default)]
+    pub match_callee: Option<String>,
+    pub actions: Vec<DialplanAction>,
+    /// Network impairments applied to the audio sent on matching calls
+    #[serde(default)]
+    pub impairment: Option<ImpairmentConfig>,
+}
+
+/// Actions executed in order once a call matching the rule is connected
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum DialplanAction {
+    /// Start playback of the prepared MP3 prompt
+    PlayAudio,
+    /// Send a DTMF string such as "1234,,#" where each ',' is a pause
+    SendDtmf {
+        digits: String,
+        #[serde(default)]
+        mode: DtmfMode,
+        #[serde(flatten)]
+        dtmf: DtmfConfig,
+    },
+    /// Do nothing for the given time
+    Wait { duration_ms: u64 },
+    /// Play a reference signal, record what the far end loops back and score it
+    LoopbackTest(LoopbackTestConfig),
+    /// Put the caller on hold `after_ms` from now, for `duration_ms` or until the call ends;
+    /// the following actions do not wait for it
+    Hold {
+        #[serde(default)]
+        after_ms: u64,
+        #[serde(default)]
+        duration_ms: Option<u64>,
+    },
+    /// Transfer the caller with a REFER; the call ends here once the transfer succeeds
+    Transfer(TransferActionConfig),
+    /// Hang up the call
+    Hangup,
+}
+
+/// Blind transfer only: the client API cannot add the `Replaces` an attended transfer needs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransferActionConfig {
+    /// URI the caller is referred to, e.g. "sip:queue@pbx"
+    pub target: String,
+}
+
+/// Reference signal and timing of a loopback quality test
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoopbackTestConfig {
+    /// WAV file to play (e.g. a speech sample); a logarithmic sweep when unset
+    pub reference_file: Option<String>,
+    pub sweep_start_hz: f32,
+    pub sweep_end_hz: f32,
+    pub sweep_duration_ms: u64,
+    /// Peak level of the sweep
+    pub level_dbfs: f32,
+    /// Longest round trip expected; recording continues this long after playback
+    pub max_delay_ms: u64,
+}
+
+impl Default for LoopbackTestConfig {
+    fn default() -> Self {
+        Self {
+            reference_file: None,
+            sweep_start_hz: 100.0,
+            sweep_end_hz: 3800.0,
+            sweep_duration_ms: 4000,
+            level_dbfs: -12.0,
+            max_delay_ms: 1000,
+        }
+    }
+}
+
+/// Emulated network impairments on the outbound audio, one 20ms frame per packet
+///
+/// Loss follows a Gilbert-Elliott model: `loss_percent` applies in the good state,
+/// `burst_loss_percent` in the bad state entered and left with the given per-packet
+/// probabilities. Leaving `burst_enter_percent` at 0 gives purely random loss.
+///
+/// There are no reordering or duplication settings: the client API numbers packets
+/// itself and cannot send one twice. Frames only leave out of order as a side effect
+/// of `jitter_ms`, and then carry fresh, in-order sequence numbers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImpairmentConfig {
+    /// The same seed reproduces the same impairments
+    pub seed: u64,
+    pub loss_percent: f64,
+    pub burst_enter_percent: f64,
+    pub burst_exit_percent: f64,
+    pub burst_loss_percent: f64,
+    /// Each packet is delayed by up to this much, uniformly
+    pub jitter_ms: u32,
+    /// Positive values send faster than the nominal clock
+    pub clock_drift_ppm: f64,
+}
+
+impl Default for ImpairmentConfig {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            loss_percent: 0.0,
+            burst_enter_percent: 0.0,
+            burst_exit_percent: 25.0,
+            burst_loss_percent: 100.0,
+            jitter_ms: 0,
+            clock_drift_ppm: 0.0,
+        }
+    }
+}
+
+impl ImpairmentConfig {
+    pub fn validate(&self) -> Result<()> {
+        let percentages = [
+            ("loss_percent", self.loss_percent),
+            ("burst_enter_percent", self.burst_enter_percent),
+            ("burst_exit_percent", self.burst_exit_percent),
+            ("burst_loss_percent", self.burst_loss_percent),
+        ];
+        for (name, value) in percentages {
+            if !(0.0..=100.0).contains(&value) {
+                return Err(anyhow::anyhow!("Invalid impairment {}: {} (must be between 0.0 and 100.0)", name, value));
+            }
+        }
+
+        if self.burst_enter_percent > 0.0 && self.burst_exit_percent == 0.0 {
+            return Err(anyhow::anyhow!("Invalid impairment burst_exit_percent: 0 (must be greater than 0 when bursts are enabled)"));
+        }
+
+        if self.jitter_ms > 1000 {
+            return Err(anyhow::anyhow!("Invalid impairment jitter_ms: {} (must be at most 1000)", self.jitter_ms));
+        }
+
+        if !(-10_000.0..=10_000.0).contains(&self.clock_drift_ppm) {
+            return Err(anyhow::anyhow!("Invalid impairment clock_drift_ppm: {} (must be between -10000 and 10000)", self.clock_drift_ppm));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DtmfMode {
+    /// Dual tones mixed into the audio stream; the only mode, as the rvoip client API
+    /// can send neither RTP telephone-events (RFC 4733) nor `application/dtmf-relay` INFO
+    #[default]
+    Inband,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressorBandConfig {
+    pub target_level: f32,
+    pub attack_time: f32,
+    pub release_time: f32,
+    pub ratio: f32,
+    pub threshold_factor: f32,
+    pub knee_width: f32,
+    pub enabled: bool,
+}
+
 impl Default for SipConfig {
     fn default() -> Self {
         Self {
@@ -134,6 +635,8 @@ impl Default for MediaConfig {
             preferred_codecs: vec!["PCMU".to_string(), "PCMA".to_string()],
             enable_dtmf: true,
             audio_sample_rate: 8000,
+            prompt_duration_seconds: default_prompt_duration_seconds(),
+            max_buffered_prompt_seconds: default_max_buffered_prompt_seconds(),
         }
     }
 }
@@ -202,10 +705,96 @@ impl Default for AudioProcessingConfig {
                 knee_width: 0.1,
                 enabled: true,
             },
-            noise_gate_threshold: 0.01,
-            noise_gate_ratio: 0.1,
-            soft_limiter_threshold: 0.9,
+            noise_gate: NoiseGateConfig::default(),
+            noise_gate_threshold: None,
+            noise_gate_ratio: None,
+            limiter_ceiling_dbfs: -1.0,
+            limiter_release_time: 0.05,
+            limiter_lookahead_time: 0.005,
+            soft_limiter_threshold: None,
+            stages: Vec::new(),
+            resampler_quality: ResamplerQuality::default(),
+            downmix_mode: DownmixMode::default(),
+            loudness: LoudnessConfig::default(),
+        }
+    }
+}
+
+impl AudioProcessingConfig {
+    /// Carry settings of older releases over to the ones that replaced them
+    ///
+    /// `raw` is the `[audio_processing]` table as written, to tell settings given
+    /// in the file from defaults.
+    fn migrate_legacy_settings(&mut self, raw: Option<&toml::Table>) -> Result<()> {
+        let given = |key: &str| raw.is_some_and(|table| table.contains_key(key));
+
+        let threshold = self.noise_gate_threshold.take();
+        let ratio = self.noise_gate_ratio.take();
+        if threshold.is_some() || ratio.is_some() {
+            if given("noise_gate") {
+                return Err(anyhow::anyhow!("Invalid audio processing noise_gate_threshold/noise_gate_ratio (replaced by [audio_processing.noise_gate], remove one of them)"));
+            }
+            // The old gate had a single threshold, so open and close at the same level
+            if let Some(threshold) = threshold {
+                self.noise_gate.open_threshold = threshold;
+                self.noise_gate.close_threshold = threshold;
+            }
+            if let Some(ratio) = ratio {
+                self.noise_gate.ratio = ratio;
+            }
+            log::warn!("audio_processing.noise_gate_threshold and noise_gate_ratio are deprecated, using open_threshold = close_threshold = {} and ratio = {} under [audio_processing.noise_gate] instead; set those in the config",
+                self.noise_gate.open_threshold, self.noise_gate.ratio);
+        }
+
+        if let Some(threshold) = self.soft_limiter_threshold.take() {
+            if given("limiter_ceiling_dbfs") {
+                return Err(anyhow::anyhow!("Invalid audio processing soft_limiter_threshold: {} (replaced by limiter_ceiling_dbfs, remove one of them)",
+                    threshold));
+            }
+            if threshold <= 0.0 || threshold > 1.0 {
+                return Err(anyhow::anyhow!("Invalid audio processing soft_limiter_threshold: {} (must be between 0.0 and 1.0)", threshold));
+            }
+            // The soft limiter could go right up to full scale; the encoder cannot
+            self.limiter_ceiling_dbfs = (20.0 * threshold.log10()).min(crate::g711::full_scale_dbfs());
+            log::warn!("audio_processing.soft_limiter_threshold is deprecated, using limiter_ceiling_dbfs = {:.2} instead; set that in the config",
+                self.limiter_ceiling_dbfs);
+        }
+
+        Ok(())
+    }
+
+    fn limiter_stage(&self) -> StageKind {
+        StageKind::Limiter {
+            ceiling_dbfs: self.limiter_ceiling_dbfs,
+            release_time: self.limiter_release_time,
+            lookahead_time: self.limiter_lookahead_time,
+        }
+    }
+
+    /// The configured stage chain, or the classic fixed chain built from the flat settings
+    pub fn effective_stages(&self) -> Vec<AudioStageConfig> {
+        if !self.stages.is_empty() {
+            return self.stages.clone();
         }
+
+        let stage = |kind| AudioStageConfig { enabled: true, kind };
+        vec![
+            stage(StageKind::Preemphasis { alpha: self.preemphasis_alpha }),
+            stage(StageKind::Bandpass {
+                low_freq: self.bandpass_low_freq,
+                high_freq: self.bandpass_high_freq,
+            }),
+            stage(StageKind::Compressor {
+                split_freqs: vec![self.band_split_freq_1, self.band_split_freq_2],
+                bands: vec![
+                    self.band1_compressor.clone(),
+                    self.band2_compressor.clone(),
+                    self.band3_compressor.clone(),
+                ],
+            }),
+            stage(StageKind::NoiseGate(self.noise_gate.clone())),
+            stage(self.limiter_stage()),
+        ]
     }
 }
 
@@ -237,9 +826,15 @@ impl ServerConfig {
         let content = fs::read_to_string(path)
             .with_context(|| format!("Failed to read config file: {}", path.display()))?;
 
-        let config: ServerConfig = toml::from_str(&content)
-            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+        Self::from_toml_str(&content)
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))
+    }
 
+    /// Parse a config, carrying over the settings older releases wrote
+    pub fn from_toml_str(content: &str) -> Result<Self> {
+        let raw: toml::Table = toml::from_str(content)?;
+        let mut config: ServerConfig = toml::from_str(content)?;
+        config.audio_processing.migrate_legacy_settings(raw.get("audio_processing").and_then(toml::Value::as_table))?;
         Ok(config)
     }
 
@@ -301,35 +896,277 @@ impl ServerConfig {
         }
 
         // Validate audio processing parameters
-        if self.audio_processing.preemphasis_alpha < 0.0 || self.audio_processing.preemphasis_alpha > 1.0 {
+        self.validate_audio_processing(&self.audio_processing)?;
+        if self.inbound_processing.enabled {
+            self.validate_audio_processing(&self.inbound_processing.profile)
+                .context("Invalid [inbound_processing.profile]")?;
+        }
+
+        let loudness = &self.audio_processing.loudness;
+        if loudness.enabled {
+            if !(-70.0..=0.0).contains(&loudness.target_level) {
+                return Err(anyhow::anyhow!("Invalid loudness target level: {} (must be between -70 and 0)", loudness.target_level));
+            }
+            // Normalization runs after the limiter; above the G.711 full scale the encoder would clip
+            let max_ceiling = crate::g711::full_scale_dbfs() as f64;
+            if !(-20.0..=max_ceiling).contains(&loudness.max_peak_dbfs) {
+                return Err(anyhow::anyhow!("Invalid loudness max_peak_dbfs: {} (must be between -20.0 and {:.2})",
+                    loudness.max_peak_dbfs, max_ceiling));
+            }
+        }
+
+        let analysis = &self.inbound_analysis;
+        if analysis.enabled {
+            if !(-90.0..=0.0).contains(&analysis.silence_threshold_dbfs) {
+                return Err(anyhow::anyhow!("Invalid inbound analysis silence_threshold_dbfs: {} (must be between -90 and 0)", analysis.silence_threshold_dbfs));
+            }
+            if analysis.min_tone_duration_ms > 10_000 {
+                return Err(anyhow::anyhow!("Invalid inbound analysis min_tone_duration_ms: {} (must be at most 10000)", analysis.min_tone_duration_ms));
+            }
+        }
+
+        if self.rtp_stats.enabled && !(20..=60_000).contains(&self.rtp_stats.poll_interval_ms) {
+            return Err(anyhow::anyhow!("Invalid RTP stats poll_interval_ms: {} (must be between 20 and 60000)", self.rtp_stats.poll_interval_ms));
+        }
+
+        if self.cdr.enabled && self.cdr.path.is_empty() {
+            return Err(anyhow::anyhow!("CDR path cannot be empty"));
+        }
+
+        if let Some(path) = &self.hold.music_file {
+            if !Path::new(path).is_file() {
+                return Err(anyhow::anyhow!("Music on hold file not found: {}", path));
+            }
+        }
+
+        if !(1000..=300_000).contains(&self.transfer.timeout_ms) {
+            return Err(anyhow::anyhow!("Invalid transfer timeout_ms: {} (must be between 1000 and 300000)", self.transfer.timeout_ms));
+        }
+
+        // The target may also come from the command line or the API
+        if !self.originate.target.is_empty() {
+            self.validate_originate(&self.originate)?;
+        }
+
+        if !self.load.profile.is_empty() {
+            self.load.validate()?;
+        }
+
+        // Validate dialplan rules
+        for rule in &self.dialplan.rules {
+            self.validate_dialplan_rule(rule)?;
+        }
+
+        log::info!("Configuration validation passed");
+        Ok(())
+    }
+
+    /// Check origination settings, including that their dialplan rule exists
+    pub fn validate_originate(&self, originate: &OriginateConfig) -> Result<()> {
+        originate.validate()?;
+        if let Some(rule) = &originate.rule {
+            if self.dialplan.rule_named(rule).is_none() {
+                return Err(anyhow::anyhow!("Invalid origination rule: '{}' (no dialplan rule has that name)", rule));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_dialplan_rule(&self, rule: &DialplanRule) -> Result<()> {
+        if rule.name.is_empty() {
+            return Err(anyhow::anyhow!("Dialplan rule name cannot be empty"));
+        }
+
+        if let Some(impairment) = &rule.impairment {
+            impairment.validate()
+                .with_context(|| format!("Invalid impairment in dialplan rule '{}'", rule.name))?;
+        }
+
+        for action in &rule.actions {
+            if let DialplanAction::LoopbackTest(test) = action {
+                self.validate_loopback_test(rule, test)?;
+            }
+
+            if let DialplanAction::Transfer(transfer) = action {
+                if !crate::transfer::is_sip_uri(&transfer.target) {
+                    return Err(anyhow::anyhow!("Invalid transfer target in dialplan rule '{}': {} (must be a sip: or sips: URI)",
+                        rule.name, transfer.target));
+                }
+            }
+
+            if let DialplanAction::Hold { duration_ms: Some(0), .. } = action {
+                return Err(anyhow::anyhow!("Invalid hold duration in dialplan rule '{}': 0ms (must be greater than 0, or omitted to hold until the call ends)",
+                    rule.name));
+            }
+
+            if let DialplanAction::SendDtmf { digits, dtmf, .. } = action {
+                crate::tone_generator::parse_dtmf_sequence(digits)
+                    .with_context(|| format!("Invalid DTMF digits in dialplan rule '{}'", rule.name))?;
+
+                if dtmf.on_time_ms == 0 {
+                    return Err(anyhow::anyhow!("Invalid DTMF on-time in dialplan rule '{}': {}ms (must be greater than 0)", 
+                        rule.name, dtmf.on_time_ms));
+                }
+
+                for level in [dtmf.low_group_level_dbm0, dtmf.high_group_level_dbm0] {
+                    if !(-60.0..=0.0).contains(&level) {
+                        return Err(anyhow::anyhow!("Invalid DTMF level in dialplan rule '{}': {}dBm0 (must be between -60.0 and 0.0)", 
+                            rule.name, level));
+                    }
+                }
+                if dtmf.peak_amplitude() > 1.0 {
+                    return Err(anyhow::anyhow!("Invalid DTMF levels in dialplan rule '{}': {}dBm0 low / {}dBm0 high (the summed tones peak at {:.2} of full scale and would clip; must be at most 1.0)",
+                        rule.name, dtmf.low_group_level_dbm0, dtmf.high_group_level_dbm0, dtmf.peak_amplitude()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_loopback_test(&self, rule: &DialplanRule, test: &LoopbackTestConfig) -> Result<()> {
+        if let Some(path) = &test.reference_file {
+            if !Path::new(path).is_file() {
+                return Err(anyhow::anyhow!("Loopback reference file not found in dialplan rule '{}': {}", rule.name, path));
+            }
+        } else {
+            let nyquist = self.media.audio_sample_rate as f32 / 2.0;
+            if test.sweep_start_hz <= 0.0 || test.sweep_start_hz >= test.sweep_end_hz || test.sweep_end_hz >= nyquist {
+                return Err(anyhow::anyhow!("Invalid loopback sweep in dialplan rule '{}': {}Hz to {}Hz (must rise and stay below {}Hz)",
+                    rule.name, test.sweep_start_hz, test.sweep_end_hz, nyquist));
+            }
+            if !(500..=60_000).contains(&test.sweep_duration_ms) {
+                return Err(anyhow::anyhow!("Invalid loopback sweep duration in dialplan rule '{}': {}ms (must be between 500 and 60000)",
+                    rule.name, test.sweep_duration_ms));
+            }
+            if !(-60.0..=0.0).contains(&test.level_dbfs) {
+                return Err(anyhow::anyhow!("Invalid loopback level in dialplan rule '{}': {}dBFS (must be between -60.0 and 0.0)",
+                    rule.name, test.level_dbfs));
+            }
+        }
+
+        if test.max_delay_ms > 5000 {
+            return Err(anyhow::anyhow!("Invalid loopback max_delay_ms in dialplan rule '{}': {} (must be at most 5000)",
+                rule.name, test.max_delay_ms));
+        }
+        Ok(())
+    }
+
+    fn validate_audio_processing(&self, audio: &AudioProcessingConfig) -> Result<()> {
+        if audio.stages.is_empty() {
+            self.validate_legacy_audio_processing(audio)
+        } else {
+            audio.stages.iter().try_for_each(|stage| self.validate_stage(&stage.kind))
+        }
+    }
+
+    fn validate_legacy_audio_processing(&self, audio: &AudioProcessingConfig) -> Result<()> {
+        if audio.preemphasis_alpha < 0.0 || audio.preemphasis_alpha > 1.0 {
             return Err(anyhow::anyhow!("Invalid preemphasis alpha: {} (must be between 0.0 and 1.0)", 
-                self.audio_processing.preemphasis_alpha));
+                audio.preemphasis_alpha));
         }
 
-        if self.audio_processing.bandpass_low_freq >= self.audio_processing.bandpass_high_freq {
+        if audio.bandpass_low_freq >= audio.bandpass_high_freq {
             return Err(anyhow::anyhow!("Invalid bandpass filter frequencies: low {} >= high {}", 
-                self.audio_processing.bandpass_low_freq, self.audio_processing.bandpass_high_freq));
+                audio.bandpass_low_freq, audio.bandpass_high_freq));
         }
 
         // Validate 3-band compressor frequencies
-        if self.audio_processing.band_split_freq_1 <= self.audio_processing.bandpass_low_freq ||
-           self.audio_processing.band_split_freq_1 >= self.audio_processing.bandpass_high_freq {
+        if audio.band_split_freq_1 <= audio.bandpass_low_freq ||
+           audio.band_split_freq_1 >= audio.bandpass_high_freq {
             return Err(anyhow::anyhow!("Invalid band split frequency 1: {} (must be between {} and {})", 
-                self.audio_processing.band_split_freq_1, self.audio_processing.bandpass_low_freq, self.audio_processing.bandpass_high_freq));
+                audio.band_split_freq_1, audio.bandpass_low_freq, audio.bandpass_high_freq));
         }
 
-        if self.audio_processing.band_split_freq_2 <= self.audio_processing.band_split_freq_1 ||
-           self.audio_processing.band_split_freq_2 >= self.audio_processing.bandpass_high_freq {
+        if audio.band_split_freq_2 <= audio.band_split_freq_1 ||
+           audio.band_split_freq_2 >= audio.bandpass_high_freq {
             return Err(anyhow::anyhow!("Invalid band split frequency 2: {} (must be between {} and {})", 
-                self.audio_processing.band_split_freq_2, self.audio_processing.band_split_freq_1, self.audio_processing.bandpass_high_freq));
+                audio.band_split_freq_2, audio.band_split_freq_1, audio.bandpass_high_freq));
         }
 
         // Validate each compressor band
-        self.validate_compressor_band(&self.audio_processing.band1_compressor, "Band 1")?;
-        self.validate_compressor_band(&self.audio_processing.band2_compressor, "Band 2")?;
-        self.validate_compressor_band(&self.audio_processing.band3_compressor, "Band 3")?;
+        self.validate_compressor_band(&audio.band1_compressor, "Band 1")?;
+        self.validate_compressor_band(&audio.band2_compressor, "Band 2")?;
+        self.validate_compressor_band(&audio.band3_compressor, "Band 3")?;
+
+        self.validate_stage(&StageKind::NoiseGate(audio.noise_gate.clone()))?;
+        self.validate_stage(&audio.limiter_stage())
+    }
+
+    fn validate_stage(&self, stage: &StageKind) -> Result<()> {
+        let nyquist = self.media.audio_sample_rate as f32 / 2.0;
+
+        match stage {
+            StageKind::Preemphasis { alpha } => {
+                if !(0.0..=1.0).contains(alpha) {
+                    return Err(anyhow::anyhow!("Invalid preemphasis alpha: {} (must be between 0.0 and 1.0)", alpha));
+                }
+            }
+            StageKind::Bandpass { low_freq, high_freq } => {
+                if *low_freq <= 0.0 || low_freq >= high_freq {
+                    return Err(anyhow::anyhow!("Invalid bandpass filter frequencies: low {} >= high {}", 
+                        low_freq, high_freq));
+                }
+            }
+            StageKind::Compressor { split_freqs, bands } => {
+                if bands.len() != split_freqs.len() + 1 {
+                    return Err(anyhow::anyhow!("Invalid compressor: {} bands need {} split frequencies, got {}", 
+                        bands.len(), bands.len().saturating_sub(1), split_freqs.len()));
+                }
+
+                let mut previous = 0.0;
+                for &freq in split_freqs {
+                    if freq <= previous || freq >= nyquist {
+                        return Err(anyhow::anyhow!("Invalid compressor split frequency: {} (must be ascending and between 0 and {})", 
+                            freq, nyquist));
+                    }
+                    previous = freq;
+                }
+
+                for (i, band) in bands.iter().enumerate() {
+                    self.validate_compressor_band(band, &format!("Band {}", i + 1))?;
+                }
+            }
+            StageKind::NoiseGate(gate) => {
+                if let Some(threshold) = gate.threshold {
+                    return Err(anyhow::anyhow!("Invalid noise gate threshold: {} (removed, set open_threshold and close_threshold instead)",
+                        threshold));
+                }
+                if !(0.0..=1.0).contains(&gate.open_threshold) || !(0.0..=1.0).contains(&gate.ratio) {
+                    return Err(anyhow::anyhow!("Invalid noise gate: open threshold {} ratio {} (must be between 0.0 and 1.0)", 
+                        gate.open_threshold, gate.ratio));
+                }
+                if gate.close_threshold < 0.0 || gate.close_threshold > gate.open_threshold {
+                    return Err(anyhow::anyhow!("Invalid noise gate close threshold: {} (must be between 0.0 and the open threshold {})", 
+                        gate.close_threshold, gate.open_threshold));
+                }
+                for (name, time) in [("attack", gate.attack_time), ("hold", gate.hold_time), ("release", gate.release_time)] {
+                    if !(0.0..=5.0).contains(&time) {
+                        return Err(anyhow::anyhow!("Invalid noise gate {} time: {} (must be between 0.0 and 5.0)", name, time));
+                    }
+                }
+            }
+            StageKind::SoftLimiter { threshold } => {
+                if *threshold <= 0.0 || *threshold > 1.0 {
+                    return Err(anyhow::anyhow!("Invalid soft limiter threshold: {} (must be between 0.0 and 1.0)", threshold));
+                }
+            }
+            StageKind::Limiter { ceiling_dbfs, release_time, lookahead_time } => {
+                // Above the G.711 full scale the encoder itself would clip
+                let max_ceiling = crate::g711::full_scale_dbfs();
+                if !(-20.0..=max_ceiling).contains(ceiling_dbfs) {
+                    return Err(anyhow::anyhow!("Invalid limiter ceiling: {}dBFS (must be between -20.0 and {:.2})", 
+                        ceiling_dbfs, max_ceiling));
+                }
+                if *release_time <= 0.0 || *release_time > 5.0 {
+                    return Err(anyhow::anyhow!("Invalid limiter release time: {} (must be between 0.0 and 5.0)", release_time));
+                }
+                if *lookahead_time <= 0.0 || *lookahead_time > 0.05 {
+                    return Err(anyhow::anyhow!("Invalid limiter lookahead time: {} (must be between 0.0 and 0.05)", lookahead_time));
+                }
+            }
+        }
 
-        log::info!("Configuration validation passed");
         Ok(())
     }
 
@@ -366,4 +1203,113 @@ impl ServerConfig {
 
         Ok(())
     }
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The default config file written by the first release, as existing installs have it
+    const BASELINE_CONFIG: &str = r#"
+[sip]
+bind_address = "0.0.0.0"
+port = 5060
+domain = "localhost"
+user_agent = "rvoip-sip-server/0.1.0"
+transport = "udp"
+
+[behavior]
+auto_answer = true
+auto_answer_delay_ms = 1000
+tone_duration_seconds = 30
+tone_frequency = 440.0
+call_timeout_seconds = 300
+max_concurrent_calls = 100
+
+[media]
+rtp_port_range_start = 10000
+rtp_port_range_end = 20000
+preferred_codecs = [
+    "PCMU",
+    "PCMA",
+]
+enable_dtmf = true
+audio_sample_rate = 8000
+
+[logging]
+level = "info"
+enable_file_logging = true
+enable_syslog = true
+log_file_path = "/var/log/rvoip-sip-server/server.log"
+max_log_size_mb = 100
+max_log_files = 10
+
+[health]
+enable_health_check = true
+health_check_port = 8080
+health_check_interval_seconds = 30
+restart_on_failure = true
+max_restart_attempts = 3
+
+[audio_processing]
+preemphasis_alpha = 0.95
+bandpass_low_freq = 300.0
+bandpass_high_freq = 3400.0
+band_split_freq_1 = 800.0
+band_split_freq_2 = 2500.0
+noise_gate_threshold = 0.01
+noise_gate_ratio = 0.1
+soft_limiter_threshold = 0.9
+
+[audio_processing.band1_compressor]
+target_level = 0.4
+attack_time = 0.01
+release_time = 0.15
+ratio = 4.0
+threshold_factor = 0.6
+knee_width = 0.15
+enabled = true
+
+[audio_processing.band2_compressor]
+target_level = 0.6
+attack_time = 0.02
+release_time = 0.08
+ratio = 2.5
+threshold_factor = 0.75
+knee_width = 0.2
+enabled = true
+
+[audio_processing.band3_compressor]
+target_level = 0.7
+attack_time = 0.005
+release_time = 0.05
+ratio = 2.0
+threshold_factor = 0.8
+knee_width = 0.1
+enabled = true
+"#;
+
+    #[test]
+    fn test_baseline_soft_limiter_threshold_becomes_the_ceiling() {
+        let config = ServerConfig::from_toml_str(BASELINE_CONFIG).unwrap();
+        let audio = &config.audio_processing;
+        assert!((audio.limiter_ceiling_dbfs - 20.0 * 0.9f32.log10()).abs() < 1e-4);
+        assert_eq!(audio.soft_limiter_threshold, None);
+
+        // Setting both is ambiguous
+        let both = BASELINE_CONFIG.replace("soft_limiter_threshold = 0.9", "soft_limiter_threshold = 0.9\nlimiter_ceiling_dbfs = -1.0");
+        assert!(ServerConfig::from_toml_str(&both).is_err());
+    }
+
+    #[test]
+    fn test_baseline_noise_gate_settings_move_to_the_gate() {
+        let config = ServerConfig::from_toml_str(BASELINE_CONFIG).unwrap();
+        let gate = &config.audio_processing.noise_gate;
+        assert_eq!((gate.open_threshold, gate.close_threshold, gate.ratio), (0.01, 0.01, 0.1));
+        assert_eq!(config.audio_processing.noise_gate_threshold, None);
+        config.validate().unwrap();
+
+        let both = format!("{}\n[audio_processing.noise_gate]\nopen_threshold = 0.02\n", BASELINE_CONFIG);
+        assert!(ServerConfig::from_toml_str(&both).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use crate::biquad::{Biquad, BiquadCoefficients};
+
+/// 4th order Linkwitz-Riley filter: two identical Butterworth sections in series
+#[derive(Debug, Clone)]
+struct LinkwitzRiley {
+    sections: [Biquad; 2],
+}
+
+impl LinkwitzRiley {
+    fn new(coefficients: BiquadCoefficients) -> Self {
+        Self { sections: [Biquad::new(coefficients), Biquad::new(coefficients)] }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let [first, second] = &mut self.sections;
+        second.process(first.process(input))
+    }
+
+    fn process_block(&mut self, block: &mut [f32]) {
+        for section in &mut self.sections {
+            section.process_block(block);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.sections.iter_mut().for_each(Biquad::reset);
+    }
+}
+
+/// One crossover point of the tree
+#[derive(Debug, Clone)]
+struct Split {
+    lowpass: LinkwitzRiley,
+    highpass: LinkwitzRiley,
+}
+
+/// Phase-coherent Linkwitz-Riley (LR4) crossover network with any number of bands
+///
+/// The signal is split at the lowest frequency first; the high side is split again at
+/// the next frequency, and so on. Each band that leaves the tree early also passes
+/// through the all-pass equivalent of every later split, so all bands share the same
+/// phase response and their sum has a flat magnitude response.
+#[derive(Debug, Clone)]
+pub struct Crossover {
+    splits: Vec<Split>,
+    /// Phase compensation of band `i`: all-passes of splits `i + 1..`
+    compensation: Vec<Vec<Biquad>>,
+}
+
+impl Crossover {
+    /// `split_freqs` must be ascending and below nyquist; yields one more band than splits
+    pub fn new(split_freqs: &[f32], sample_rate: f32) -> Self {
+        let splits = split_freqs
+            .iter()
+            .map(|&freq| Split {
+                lowpass: LinkwitzRiley::new(BiquadCoefficients::lowpass(freq, sample_rate)),
+                highpass: LinkwitzRiley::new(BiquadCoefficients::highpass(freq, sample_rate)),
+            })
+            .collect();
+
+        let compensation = (0..split_freqs.len())
+            .map(|band| {
+                split_freqs[band + 1..]
+                    .iter()
+                    .map(|&freq| Biquad::new(BiquadCoefficients::allpass(freq, sample_rate)))
+                    .collect()
+            })
+            .collect();
+
+        Self { splits, compensation }
+    }
+
+    pub fn band_count(&self) -> usize {
+        self.splits.len() + 1
+    }
+
+    /// Split one sample; `bands` must hold `band_count()` entries
+    pub fn split(&mut self, input: f32, bands: &mut [f32]) {
+        let mut rest = input;
+        for (i, split) in self.splits.iter_mut().enumerate() {
+            let low = split.lowpass.process(rest);
+            rest = split.highpass.process(rest);
+            bands[i] = self.compensation[i].iter_mut().fold(low, |sample, allpass| allpass.process(sample));
+        }
+        bands[self.splits.len()] = rest;
+    }
+
+    /// Split a block; each of the `band_count()` buffers is replaced by its band of `input`
+    pub fn split_block(&mut self, input: &[f32], bands: &mut [Vec<f32>]) {
+        let last = self.splits.len();
+        bands[last].clear();
+        bands[last].extend_from_slice(input);
+
+        for (i, split) in self.splits.iter_mut().enumerate() {
+            // bands[last] carries the not yet split remainder down the tree
+            let (low_bands, rest) = bands.split_at_mut(last);
+            let low = &mut low_bands[i];
+            let rest = &mut rest[0];
+
+            low.clear();
+            low.extend_from_slice(rest);
+            split.lowpass.process_block(low);
+            split.highpass.process_block(rest);
+            for allpass in &mut self.compensation[i] {
+                allpass.process_block(low);
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for split in &mut self.splits {
+            split.lowpass.reset();
+            split.highpass.reset();
+        }
+        self.compensation.iter_mut().flatten().for_each(Biquad::reset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, samples: usize) -> Vec<f32> {
+        (0..samples)
+            .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / 8000.0).sin())
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_bands_sum_flat() {
+        for splits in [vec![1000.0], vec![800.0, 2500.0], vec![500.0, 1000.0, 2000.0, 3000.0]] {
+            let mut crossover = Crossover::new(&splits, 8000.0);
+            let mut bands = vec![0.0; crossover.band_count()];
+
+            for freq in (1..20).map(|i| i as f32 * 200.0) {
+                crossover.reset();
+                let input = sine(freq, 8000);
+                let summed: Vec<f32> = input.iter()
+                    .map(|&sample| {
+                        crossover.split(sample, &mut bands);
+                        bands.iter().sum()
+                    })
+                    .collect();
+
+                // Skip the settling time, then compare levels
+                let gain_db = 20.0 * (rms(&summed[2000..]) / rms(&input[2000..])).log10();
+                assert!(gain_db.abs() < 0.05, "splits {:?}: {}Hz summed to {:.3} dB", splits, freq, gain_db);
+            }
+        }
+    }
+
+    #[test]
+    fn test_block_split_matches_per_sample() {
+        let input: Vec<f32> = sine(700.0, 1000).iter().zip(sine(2900.0, 1000)).map(|(a, b)| a + b).collect();
+
+        let mut per_sample = Crossover::new(&[800.0, 2500.0], 8000.0);
+        let mut bands = vec![0.0; 3];
+        let mut expected = vec![Vec::new(); 3];
+        for &sample in &input {
+            per_sample.split(sample, &mut bands);
+            for (band, &value) in expected.iter_mut().zip(&bands) {
+                band.push(value);
+            }
+        }
+
+        let mut block = Crossover::new(&[800.0, 2500.0], 8000.0);
+        let mut buffers = vec![Vec::new(); 3];
+        let mut actual = vec![Vec::new(); 3];
+        for chunk in input.chunks(160) {
+            block.split_block(chunk, &mut buffers);
+            for (band, buffer) in actual.iter_mut().zip(&buffers) {
+                band.extend_from_slice(buffer);
+            }
+        }
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_single_band_passes_through() {
+        let mut crossover = Crossover::new(&[], 8000.0);
+        let mut bands = [0.0];
+        crossover.split(0.3, &mut bands);
+        assert_eq!(bands, [0.3]);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::config::{DialplanConfig, DialplanRule};
+
+impl DialplanConfig {
+    /// Find the first rule whose callee pattern matches the given URI
+    pub fn find_rule(&self, callee_uri: &str) -> Option<&DialplanRule> {
+        self.rules.iter().find(|rule| match &rule.match_callee {
+            Some(pattern) => callee_uri.contains(pattern.as_str()),
+            None => true,
+        })
+    }
+
+    /// Rule with the given name
+    pub fn rule_named(&self, name: &str) -> Option<&DialplanRule> {
+        self.rules.iter().find(|rule| rule.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{DialplanAction, DtmfMode};
+
+    fn rule(name: &str, match_callee: Option<&str>) -> DialplanRule {
+        DialplanRule {
+            name: name.to_string(),
+            match_callee: match_callee.map(str::to_string),
+            actions: vec![DialplanAction::Hangup],
+            impairment: None,
+        }
+    }
+
+    #[test]
+    fn test_find_rule() {
+        let config = DialplanConfig {
+            rules: vec![rule("ivr", Some("ivr-test")), rule("default", None)],
+        };
+
+        assert_eq!(config.find_rule("sip:ivr-test@example.com").unwrap().name, "ivr");
+        assert_eq!(config.find_rule("sip:other@example.com").unwrap().name, "default");
+        assert!(DialplanConfig::default().find_rule("sip:other@example.com").is_none());
+        assert_eq!(config.rule_named("default").unwrap().match_callee, None);
+        assert!(config.rule_named("ivr-test").is_none());
+    }
+
+    #[test]
+    fn test_parse_send_dtmf_action() {
+        let config: DialplanConfig = toml::from_str(r#"
+            [[rules]]
+            name = "digits"
+            actions = [
+                { action = "send_dtmf", digits = "12#", mode = "inband", on_time_ms = 60 },
+                { action = "hangup" },
+            ]
+        "#).unwrap();
+
+        match &config.rules[0].actions[0] {
+            DialplanAction::SendDtmf { digits, mode, dtmf } => {
+                assert_eq!(digits, "12#");
+                assert_eq!(*mode, DtmfMode::Inband);
+                assert_eq!(dtmf.on_time_ms, 60);
+                assert_eq!(dtmf.off_time_ms, 100);
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+
+        // Out-of-band modes are not available and must not fall back to in-band tones
+        for mode in ["rfc4733", "sip_info"] {
+            assert!(toml::from_str::<DialplanConfig>(&format!(r#"
+                [[rules]]
+                name = "digits"
+                actions = [{{ action = "send_dtmf", digits = "1", mode = "{}" }}]
+            "#, mode)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_loopback_test_action() {
+        let config: DialplanConfig = toml::from_str(r#"
+            [[rules]]
+            name = "loopback"
+            actions = [
+                { action = "loopback_test", sweep_end_hz = 3400.0, max_delay_ms = 500 },
+                { action = "loopback_test", reference_file = "speech.wav" },
+            ]
+        "#).unwrap();
+
+        match &config.rules[0].actions[..] {
+            [DialplanAction::LoopbackTest(sweep), DialplanAction::LoopbackTest(speech)] => {
+                assert_eq!((sweep.sweep_start_hz, sweep.sweep_end_hz, sweep.max_delay_ms), (100.0, 3400.0, 500));
+                assert!(sweep.reference_file.is_none());
+                assert_eq!(speech.reference_file.as_deref(), Some("speech.wav"));
+            }
+            other => panic!("unexpected actions {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_hold_action() {
+        let config: DialplanConfig = toml::from_str(r#"
+            [[rules]]
+            name = "hold"
+            actions = [
+                { action = "hold", after_ms = 5000, duration_ms = 10000 },
+                { action = "hold" },
+            ]
+        "#).unwrap();
+
+        match &config.rules[0].actions[..] {
+            [DialplanAction::Hold { after_ms: 5000, duration_ms: Some(10000) }, DialplanAction::Hold { after_ms: 0, duration_ms: None }] => {}
+            other => panic!("unexpected actions {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_transfer_action() {
+        let config: DialplanConfig = toml::from_str(r#"
+            [[rules]]
+            name = "transfer"
+            actions = [
+                { action = "play_audio" },
+                { action = "transfer", target = "sip:queue@pbx" },
+            ]
+        "#).unwrap();
+
+        match &config.rules[0].actions[1..] {
+            [DialplanAction::Transfer(transfer)] => assert_eq!(transfer.target, "sip:queue@pbx"),
+            other => panic!("unexpected actions {:?}", other),
+        }
+
+        // Asking for an attended transfer must fail rather than fall back to a blind one
+        assert!(toml::from_str::<DialplanConfig>(r#"
+            [[rules]]
+            name = "transfer"
+            actions = [{ action = "transfer", target = "sip:agent@pbx", attended = true }]
+        "#).is_err());
+    }
+
+    #[test]
+    fn test_parse_rule_impairment() {
+        let config: DialplanConfig = toml::from_str(r#"
+            [[rules]]
+            name = "lossy"
+            actions = [{ action = "play_audio" }]
+
+            [rules.impairment]
+            seed = 42
+            loss_percent = 1.0
+            burst_enter_percent = 2.0
+            jitter_ms = 30
+        "#).unwrap();
+
+        let impairment = config.rules[0].impairment.as_ref().unwrap();
+        assert_eq!((impairment.seed, impairment.jitter_ms), (42, 30));
+        assert_eq!((impairment.burst_enter_percent, impairment.burst_exit_percent), (2.0, 25.0));
+        assert!(impairment.validate().is_ok());
+        assert!(rule("plain", None).impairment.is_none());
+
+        // Packet reordering is not available and must not be silently ignored
+        assert!(toml::from_str::<DialplanConfig>(r#"
+            [[rules]]
+            name = "lossy"
+            actions = [{ action = "play_audio" }]
+
+            [rules.impairment]
+            reorder_percent = 1.0
+        "#).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use std::f64::consts::PI;
+
+/// In-place radix-2 FFT; both slices must have the same power-of-two length
+pub fn fft(re: &mut [f64], im: &mut [f64]) {
+    transform(re, im, -1.0);
+}
+
+/// In-place inverse of [`fft`], including the 1/n scaling
+pub fn inverse_fft(re: &mut [f64], im: &mut [f64]) {
+    transform(re, im, 1.0);
+    let scale = 1.0 / re.len() as f64;
+    re.iter_mut().chain(im.iter_mut()).for_each(|value| *value *= scale);
+}
+
+fn transform(re: &mut [f64], im: &mut [f64], sign: f64) {
+    let n = re.len();
+    assert!(n.is_power_of_two() && im.len() == n, "FFT length must be a power of two");
+    if n < 2 {
+        return;
+    }
+
+    // Bit-reversal permutation, then butterflies
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len *= 2;
+    }
+}
+
+/// One-sided power spectrum |X[k]|², k = 0..=n/2, of a power-of-two length signal
+pub fn power_spectrum(signal: &[f32]) -> Vec<f64> {
+    let mut re: Vec<f64> = signal.iter().map(|&x| x as f64).collect();
+    let mut im = vec![0.0; signal.len()];
+    fft(&mut re, &mut im);
+    (0..=signal.len() / 2).map(|k| re[k] * re[k] + im[k] * im[k]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_bin_placement() {
+        let signal: Vec<f64> = (0..64).map(|i| (2.0 * PI * 5.0 * i as f64 / 64.0).cos()).collect();
+        let (mut re, mut im) = (signal.clone(), vec![0.0; 64]);
+
+        fft(&mut re, &mut im);
+        // A cosine of 5 cycles lands in bins 5 and 64 - 5 with half the length each
+        assert!((re[5] - 32.0).abs() < 1e-9 && (re[59] - 32.0).abs() < 1e-9);
+        assert!(re.iter().zip(&im).enumerate()
+            .filter(|(k, _)| *k != 5 && *k != 59)
+            .all(|(_, (r, i))| r.abs() < 1e-9 && i.abs() < 1e-9));
+
+        inverse_fft(&mut re, &mut im);
+        assert!(re.iter().zip(&signal).all(|(a, b)| (a - b).abs() < 1e-12));
+        assert!(im.iter().all(|value| value.abs() < 1e-12));
+    }
+}
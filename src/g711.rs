@@ -0,0 +1,169 @@
+const MULAW_BIAS: i32 = 0x84;
+const MULAW_CLIP: i32 = 32635;
+
+/// Largest magnitude a μ-law code word decodes to
+pub const MULAW_MAX: i16 = 32124;
+/// Largest magnitude an A-law code word decodes to
+pub const ALAW_MAX: i16 = 32256;
+/// Largest magnitude both codecs reproduce without clipping
+pub const FULL_SCALE: i16 = if MULAW_MAX < ALAW_MAX { MULAW_MAX } else { ALAW_MAX };
+
+/// `FULL_SCALE` in dB relative to 16-bit full scale
+pub fn full_scale_dbfs() -> f32 {
+    20.0 * (FULL_SCALE as f32 / 32768.0).log10()
+}
+
+/// Encode a 16-bit linear PCM sample as μ-law
+pub fn linear_to_mulaw(pcm: i16) -> u8 {
+    let mut sample = pcm as i32;
+    let sign = if sample < 0 {
+        sample = -sample;
+        0x80
+    } else {
+        0
+    };
+    sample = sample.min(MULAW_CLIP) + MULAW_BIAS;
+
+    // Segment is the position of the highest set bit above bit 7
+    let mut exponent = 7;
+    let mut mask = 0x4000;
+    while exponent > 0 && sample & mask == 0 {
+        exponent -= 1;
+        mask >>= 1;
+    }
+
+    let mantissa = (sample >> (exponent + 3)) & 0x0F;
+    !(sign | (exponent << 4) | mantissa) as u8
+}
+
+/// Decode a μ-law code word to 16-bit linear PCM
+pub fn mulaw_to_linear(code: u8) -> i16 {
+    let code = !code;
+    let exponent = (code >> 4) & 0x07;
+    let mantissa = (code & 0x0F) as i32;
+    let magnitude = (((mantissa << 3) + MULAW_BIAS) << exponent) - MULAW_BIAS;
+
+    if code & 0x80 != 0 {
+        -magnitude as i16
+    } else {
+        magnitude as i16
+    }
+}
+
+/// Encode a 16-bit linear PCM sample as A-law
+pub fn linear_to_alaw(pcm: i16) -> u8 {
+    let mut sample = (pcm as i32) >> 3;
+    let mask = if sample >= 0 {
+        0xD5
+    } else {
+        sample = -sample - 1;
+        0x55
+    };
+
+    let segment = [0x1F, 0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF]
+        .iter()
+        .position(|&end| sample <= end);
+
+    match segment {
+        None => 0x7F ^ mask,
+        Some(segment) => {
+            let mantissa = if segment < 2 {
+                (sample >> 1) & 0x0F
+            } else {
+                (sample >> segment) & 0x0F
+            };
+            (((segment as i32) << 4) | mantissa) as u8 ^ mask
+        }
+    }
+}
+
+/// Decode an A-law code word to 16-bit linear PCM
+pub fn alaw_to_linear(code: u8) -> i16 {
+    let code = code ^ 0x55;
+    let segment = (code & 0x70) >> 4;
+    let mut magnitude = ((code & 0x0F) as i32) << 4;
+
+    magnitude = match segment {
+        0 => magnitude + 8,
+        1 => magnitude + 0x108,
+        _ => (magnitude + 0x108) << (segment - 1),
+    };
+
+    if code & 0x80 != 0 {
+        magnitude as i16
+    } else {
+        -magnitude as i16
+    }
+}
+
+/// Encode a block of PCM samples as μ-law
+pub fn pcm_to_mulaw(pcm_samples: &[i16]) -> Vec<u8> {
+    pcm_samples.iter().map(|&sample| linear_to_mulaw(sample)).collect()
+}
+
+/// Decode a block of μ-law code words to PCM
+pub fn mulaw_to_pcm(mulaw_samples: &[u8]) -> Vec<i16> {
+    mulaw_samples.iter().map(|&code| mulaw_to_linear(code)).collect()
+}
+
+/// Encode a block of PCM samples as A-law
+pub fn pcm_to_alaw(pcm_samples: &[i16]) -> Vec<u8> {
+    pcm_samples.iter().map(|&sample| linear_to_alaw(sample)).collect()
+}
+
+/// Decode a block of A-law code words to PCM
+pub fn alaw_to_pcm(alaw_samples: &[u8]) -> Vec<i16> {
+    alaw_samples.iter().map(|&code| alaw_to_linear(code)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mulaw_reference_values() {
+        assert_eq!(linear_to_mulaw(0), 0xFF);
+        assert_eq!(linear_to_mulaw(1000), 0xCE);
+        assert_eq!(linear_to_mulaw(-1000), 0x4E);
+        assert_eq!(linear_to_mulaw(i16::MAX), 0x80);
+        assert_eq!(linear_to_mulaw(i16::MIN), 0x00);
+        assert_eq!(mulaw_to_linear(0x80), MULAW_MAX);
+        assert_eq!(mulaw_to_linear(0x00), -MULAW_MAX);
+    }
+
+    #[test]
+    fn test_alaw_reference_values() {
+        assert_eq!(linear_to_alaw(0), 0xD5);
+        assert_eq!(linear_to_alaw(-8), 0x55);
+        assert_eq!(linear_to_alaw(i16::MAX), 0xAA);
+        assert_eq!(linear_to_alaw(i16::MIN), 0x2A);
+        assert_eq!(alaw_to_linear(0xAA), ALAW_MAX);
+        assert_eq!(alaw_to_linear(0x2A), -ALAW_MAX);
+    }
+
+    #[test]
+    fn test_round_trip_is_idempotent() {
+        // Every code word must survive decode -> encode unchanged
+        for code in 0..=255u8 {
+            // 0x7F and 0xFF both decode to zero in μ-law
+            if code != 0x7F {
+                assert_eq!(linear_to_mulaw(mulaw_to_linear(code)), code, "μ-law {:#04x}", code);
+            }
+            assert_eq!(linear_to_alaw(alaw_to_linear(code)), code, "A-law {:#04x}", code);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_error_is_bounded() {
+        for pcm in (-32768..=32767).step_by(7) {
+            let pcm = pcm as i16;
+            let mulaw_error = (mulaw_to_linear(linear_to_mulaw(pcm)) as i32 - pcm as i32).abs();
+            let alaw_error = (alaw_to_linear(linear_to_alaw(pcm)) as i32 - pcm as i32).abs();
+
+            // Quantisation steps double with each segment, so the error scales with the magnitude
+            let bound = (pcm as i32).abs() / 16 + 16;
+            assert!(mulaw_error <= bound, "μ-law {} -> error {}", pcm, mulaw_error);
+            assert!(alaw_error <= bound, "A-law {} -> error {}", pcm, alaw_error);
+        }
+    }
+}
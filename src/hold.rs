@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+/// Time a call spends on hold
+#[derive(Debug, Clone, Default)]
+pub struct HoldTracker {
+    held_since: Option<Duration>,
+    total: Duration,
+    count: u32,
+}
+
+impl HoldTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record whether the call is held as of `now` (time since the call started)
+    pub fn update(&mut self, held: bool, now: Duration) {
+        match (self.held_since, held) {
+            (None, true) => {
+                self.held_since = Some(now);
+                self.count += 1;
+            }
+            (Some(since), false) => {
+                self.total += now.saturating_sub(since);
+                self.held_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.held_since.is_some()
+    }
+
+    /// Number of holds and total hold time up to `now`
+    pub fn summary(&self, now: Duration) -> (u32, Duration) {
+        let current = self.held_since.map_or(Duration::ZERO, |since| now.saturating_sub(since));
+        (self.count, self.total + current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hold_time() {
+        let at = Duration::from_secs;
+        let mut tracker = HoldTracker::new();
+        tracker.update(false, at(1));
+        tracker.update(true, at(5));
+        tracker.update(true, at(6));
+        tracker.update(false, at(8));
+        tracker.update(true, at(20));
+
+        assert!(tracker.is_held());
+        // The hold still in progress counts up to the end of the call
+        assert_eq!(tracker.summary(at(25)), (2, at(8)));
+    }
+}
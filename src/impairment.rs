@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::config::ImpairmentConfig;
+
+/// SplitMix64: small, and the stream for a given seed never changes
+#[derive(Debug, Clone)]
+pub struct SeededRng(u64);
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// What the impairments did to a call's outbound audio
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImpairmentStats {
+    pub seed: u64,
+    pub frames: u64,
+    pub lost: u64,
+    /// Largest extra delay applied by jitter
+    pub max_delay_ms: u64,
+}
+
+#[derive(Debug)]
+struct Scheduled<T> {
+    due: Duration,
+    frame: T,
+}
+
+/// Applies loss and jitter to a stream of frames
+///
+/// Frames are submitted as they are produced and taken back out once due. Every
+/// frame consumes the same number of random draws, so changing one impairment
+/// leaves the pattern of the others unchanged for a given seed.
+#[derive(Debug)]
+pub struct NetworkImpairment<T> {
+    config: ImpairmentConfig,
+    rng: SeededRng,
+    burst: bool,
+    queue: Vec<Scheduled<T>>,
+    stats: ImpairmentStats,
+}
+
+impl<T> NetworkImpairment<T> {
+    pub fn new(config: &ImpairmentConfig) -> Self {
+        Self {
+            config: config.clone(),
+            rng: SeededRng::new(config.seed),
+            burst: false,
+            queue: Vec::new(),
+            stats: ImpairmentStats { seed: config.seed, ..ImpairmentStats::default() },
+        }
+    }
+
+    /// Change the impairments without dropping frames already in flight
+    pub fn reconfigure(&mut self, config: &ImpairmentConfig) {
+        if config.seed != self.config.seed {
+            self.rng = SeededRng::new(config.seed);
+            self.stats.seed = config.seed;
+        }
+        self.config = config.clone();
+    }
+
+    pub fn config(&self) -> &ImpairmentConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> &ImpairmentStats {
+        &self.stats
+    }
+
+    /// Spacing of frames nominally `interval` apart, after clock drift
+    pub fn frame_interval(&self, interval: Duration) -> Duration {
+        interval.mul_f64(1e6 / (1e6 + self.config.clock_drift_ppm))
+    }
+
+    /// Submit a frame produced `now` after the stream started
+    pub fn push(&mut self, frame: T, now: Duration) {
+        let [transition, loss, jitter] = [(); 3].map(|_| self.rng.next_f64());
+        let config = &self.config;
+        self.stats.frames += 1;
+
+        self.burst = if self.burst {
+            transition >= config.burst_exit_percent / 100.0
+        } else {
+            transition < config.burst_enter_percent / 100.0
+        };
+        let loss_percent = if self.burst { config.burst_loss_percent } else { config.loss_percent };
+        if loss < loss_percent / 100.0 {
+            self.stats.lost += 1;
+            return;
+        }
+
+        let delay = Duration::from_secs_f64(jitter * config.jitter_ms as f64 / 1000.0);
+        self.stats.max_delay_ms = self.stats.max_delay_ms.max(delay.as_millis() as u64);
+        let due = now + delay;
+
+        // After frames due at the same time, keeping them in submission order
+        let index = self.queue.partition_point(|scheduled| scheduled.due <= due);
+        self.queue.insert(index, Scheduled { due, frame });
+    }
+
+    /// When the next queued frame is due
+    pub fn next_due(&self) -> Option<Duration> {
+        self.queue.first().map(|scheduled| scheduled.due)
+    }
+
+    /// Frames due by `now`, in send order
+    pub fn due(&mut self, now: Duration) -> Vec<T> {
+        let count = self.queue.partition_point(|scheduled| scheduled.due <= now);
+        self.queue.drain(..count).map(|scheduled| scheduled.frame).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(config: &ImpairmentConfig, frames: u32) -> (Vec<u32>, ImpairmentStats) {
+        let mut impairment = NetworkImpairment::new(config);
+        let mut sent = Vec::new();
+        for frame in 0..frames {
+            let now = Duration::from_millis(20 * frame as u64);
+            impairment.push(frame, now);
+            sent.extend(impairment.due(now));
+        }
+        sent.extend(impairment.due(Duration::MAX));
+        assert!(impairment.next_due().is_none());
+        (sent, impairment.stats().clone())
+    }
+
+    #[test]
+    fn test_no_impairment_passes_frames_through() {
+        let (sent, stats) = run(&ImpairmentConfig::default(), 100);
+        assert_eq!(sent, (0..100).collect::<Vec<_>>());
+        assert_eq!((stats.frames, stats.lost), (100, 0));
+    }
+
+    #[test]
+    fn test_same_seed_same_impairments() {
+        let config = ImpairmentConfig {
+            seed: 7,
+            loss_percent: 5.0,
+            jitter_ms: 50,
+            ..ImpairmentConfig::default()
+        };
+        let first = run(&config, 2000);
+        assert_eq!(first, run(&config, 2000));
+        assert_ne!(first.0, run(&ImpairmentConfig { seed: 8, ..config.clone() }, 2000).0);
+
+        let (sent, stats) = first;
+        assert_eq!(sent.len() as u64, stats.frames - stats.lost);
+        assert!((60..140).contains(&stats.lost), "{}", stats.lost);
+        assert!(stats.max_delay_ms <= 50);
+        // Jitter beyond the frame spacing reorders frames
+        assert!(sent.windows(2).any(|pair| pair[1] < pair[0]));
+    }
+
+    #[test]
+    fn test_burst_loss() {
+        let config = ImpairmentConfig {
+            burst_enter_percent: 1.0,
+            burst_exit_percent: 25.0,
+            ..ImpairmentConfig::default()
+        };
+        let (sent, stats) = run(&config, 20_000);
+
+        // Bad state lasts 4 packets on average, entered once every ~100
+        let loss_ratio = stats.lost as f64 / stats.frames as f64;
+        assert!((0.03..0.05).contains(&loss_ratio), "{}", loss_ratio);
+        let gaps: Vec<u32> = sent.windows(2).map(|pair| pair[1] - pair[0] - 1).filter(|&gap| gap > 0).collect();
+        let mean_burst = gaps.iter().sum::<u32>() as f64 / gaps.len() as f64;
+        assert!((3.0..5.0).contains(&mean_burst), "{}", mean_burst);
+    }
+
+    #[test]
+    fn test_clock_drift() {
+        let fast = NetworkImpairment::<u32>::new(&ImpairmentConfig { clock_drift_ppm: 1000.0, ..ImpairmentConfig::default() });
+        let interval = fast.frame_interval(Duration::from_millis(20));
+        assert!((interval.as_secs_f64() - 0.02 / 1.001).abs() < 1e-9);
+    }
+}
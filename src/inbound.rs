@@ -0,0 +1,184 @@
+use log::info;
+
+use crate::config::AudioProcessingConfig;
+use crate::mp3_handler::{ProcessorStats, TelephonyAudioProcessor};
+
+/// Consumer of processed caller audio (recording, echo, analysis, ...)
+///
+/// Sinks see the audio after the `[inbound_processing]` chain, one frame at a time,
+/// as mono 16-bit PCM.
+pub trait InboundSink: Send {
+    /// Short identifier used in logs
+    fn name(&self) -> &'static str;
+
+    fn on_frame(&mut self, samples: &[i16], sample_rate: u32);
+
+    /// Called once the call's media has ended
+    fn finish(&mut self) {}
+}
+
+/// Per-call processing of the audio received from the far end
+pub struct InboundAudioPipeline {
+    /// Chain to apply; `None` hands the audio to the sinks unprocessed
+    profile: Option<AudioProcessingConfig>,
+    /// Processor built for the sample rate of the current frames
+    processor: Option<(u32, TelephonyAudioProcessor)>,
+    sinks: Vec<Box<dyn InboundSink>>,
+    block: Vec<f32>,
+    output: Vec<i16>,
+    frames: u64,
+}
+
+impl InboundAudioPipeline {
+    pub fn new(profile: Option<AudioProcessingConfig>) -> Self {
+        Self {
+            profile,
+            processor: None,
+            sinks: Vec::new(),
+            block: Vec::new(),
+            output: Vec::new(),
+            frames: 0,
+        }
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn InboundSink>) {
+        self.sinks.push(sink);
+    }
+
+    /// Run one received frame (interleaved if `channels` > 1) through the chain and
+    /// hand the result to every sink; returns the processed mono samples
+    pub fn process_frame(&mut self, samples: &[i16], sample_rate: u32, channels: u8) -> &[i16] {
+        let channels = channels.max(1) as usize;
+        self.frames += 1;
+
+        self.block.clear();
+        self.block.extend(samples.chunks(channels).map(|frame| {
+            frame.iter().map(|&s| s as f32 / 32768.0).sum::<f32>() / frame.len() as f32
+        }));
+
+        let processor = match &mut self.processor {
+            Some((rate, processor)) if *rate == sample_rate => processor,
+            slot => {
+                if let Some((rate, _)) = slot {
+                    info!("🎙️ Inbound sample rate changed from {}Hz to {}Hz, rebuilding processing chain", rate, sample_rate);
+                }
+                let processor = match &self.profile {
+                    Some(profile) => TelephonyAudioProcessor::new(sample_rate as f32, profile.clone()),
+                    None => TelephonyAudioProcessor::with_stages(Vec::new()),
+                };
+                &mut slot.insert((sample_rate, processor)).1
+            }
+        };
+        processor.process_block(&mut self.block);
+
+        self.output.clear();
+        self.output.extend(self.block.iter().map(|&s| (s * 32768.0).round().clamp(-32768.0, 32767.0) as i16));
+
+        for sink in &mut self.sinks {
+            sink.on_frame(&self.output, sample_rate);
+        }
+        &self.output
+    }
+
+    /// Frames received so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Processing statistics, once audio has been received
+    pub fn stats(&self) -> Option<ProcessorStats> {
+        self.processor.as_ref().map(|(_, processor)| processor.stats())
+    }
+
+    /// Notify the sinks that the call's media has ended
+    pub fn finish(&mut self) {
+        for sink in &mut self.sinks {
+            sink.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Captured {
+        samples: Vec<i16>,
+        sample_rates: Vec<u32>,
+        finished: bool,
+    }
+
+    /// Collects everything it is given
+    struct Capture(Arc<Mutex<Captured>>);
+
+    impl InboundSink for Capture {
+        fn name(&self) -> &'static str {
+            "capture"
+        }
+
+        fn on_frame(&mut self, samples: &[i16], sample_rate: u32) {
+            let mut captured = self.0.lock().unwrap();
+            captured.samples.extend_from_slice(samples);
+            captured.sample_rates.push(sample_rate);
+        }
+
+        fn finish(&mut self) {
+            self.0.lock().unwrap().finished = true;
+        }
+    }
+
+    fn profile(stages: &str) -> Option<AudioProcessingConfig> {
+        Some(toml::from_str(stages).unwrap())
+    }
+
+    #[test]
+    fn test_sinks_receive_processed_audio() {
+        // A gate that stays closed halves everything
+        let mut pipeline = InboundAudioPipeline::new(profile(r#"
+            [[stages]]
+            stage = "noise_gate"
+            open_threshold = 1.0
+            close_threshold = 1.0
+            ratio = 0.5
+            release_time = 0.0
+        "#));
+        let captured = Arc::new(Mutex::new(Captured::default()));
+        pipeline.add_sink(Box::new(Capture(Arc::clone(&captured))));
+
+        let output = pipeline.process_frame(&[1000; 160], 8000, 1).to_vec();
+        assert_eq!(output.len(), 160);
+        assert!(output.iter().all(|&s| (s - 500).abs() <= 1));
+        pipeline.finish();
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.samples, output);
+        assert_eq!(captured.sample_rates, vec![8000]);
+        assert!(captured.finished);
+        assert_eq!(pipeline.stats().unwrap().samples, 160);
+    }
+
+    #[test]
+    fn test_stereo_downmix_and_rate_change() {
+        let mut pipeline = InboundAudioPipeline::new(profile("[[stages]]\nstage = \"preemphasis\"\nalpha = 0.0"));
+
+        // Interleaved L/R pairs are averaged into one mono sample each
+        let output = pipeline.process_frame(&[1000, 3000, -2000, 0], 8000, 2).to_vec();
+        assert_eq!(output.len(), 2);
+        assert!((output[0] - 2000).abs() <= 1 && (output[1] + 1000).abs() <= 1);
+
+        // A new sample rate starts a fresh chain
+        pipeline.process_frame(&[0; 320], 16000, 1);
+        assert_eq!(pipeline.stats().unwrap().samples, 320);
+        assert_eq!(pipeline.frames(), 2);
+    }
+
+    #[test]
+    fn test_without_profile_passes_audio_through() {
+        let mut pipeline = InboundAudioPipeline::new(None);
+        let output = pipeline.process_frame(&[1000, -2000, 0], 8000, 1).to_vec();
+        assert_eq!(output, vec![1000, -2000, 0]);
+        assert!(pipeline.stats().unwrap().stages.is_empty());
+    }
+}
@@ -1 +1,23 @@
-pub mod config; 
\ No newline at end of file
+pub mod analysis;
+pub mod audio_source;
+pub mod audio_stages;
+pub mod biquad;
+pub mod cdr;
+pub mod config;
+pub mod crossover;
+pub mod dialplan;
+pub mod fft;
+pub mod g711;
+pub mod hold;
+pub mod impairment;
+pub mod inbound;
+pub mod load;
+pub mod loopback;
+pub mod loudness;
+pub mod metrics;
+pub mod mp3_handler;
+pub mod originate;
+pub mod resampler;
+pub mod rtp_stats;
+pub mod tone_generator;
+pub mod transfer;
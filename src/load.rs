@@ -0,0 +1,230 @@
+//! Load generation: call rate and concurrency ramps, and the report of a run
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::config::LoadPhase;
+use crate::originate::CallOutcome;
+
+/// Ramp up to a peak, hold it, and ramp back down; empty phases are left out
+pub fn ramp_profile(ramp_up: Duration, hold: Duration, ramp_down: Duration, calls_per_second: f64, max_concurrent: u32) -> Vec<LoadPhase> {
+    [(ramp_up, calls_per_second, max_concurrent), (hold, calls_per_second, max_concurrent), (ramp_down, 0.0, 0)]
+        .into_iter()
+        .filter(|(duration, _, _)| !duration.is_zero())
+        .map(|(duration, calls_per_second, max_concurrent)| LoadPhase {
+            duration_ms: duration.as_millis() as u64,
+            calls_per_second,
+            max_concurrent,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    start: f64,
+    end: f64,
+    rate: (f64, f64),
+    concurrency: (f64, f64),
+}
+
+impl Segment {
+    fn fraction(&self, t: f64) -> f64 {
+        ((t - self.start) / (self.end - self.start)).clamp(0.0, 1.0)
+    }
+}
+
+/// Piecewise linear call rate and concurrency limit over time
+#[derive(Debug, Clone)]
+pub struct LoadProfile {
+    segments: Vec<Segment>,
+}
+
+impl LoadProfile {
+    /// Ramps start from zero, then from where the previous phase ended
+    pub fn new(phases: &[LoadPhase]) -> Self {
+        let mut segments = Vec::with_capacity(phases.len());
+        let (mut start, mut rate, mut concurrency) = (0.0, 0.0, 0.0);
+        for phase in phases {
+            let end = start + phase.duration_ms as f64 / 1000.0;
+            let segment = Segment {
+                start,
+                end,
+                rate: (rate, phase.calls_per_second),
+                concurrency: (concurrency, phase.max_concurrent as f64),
+            };
+            (start, rate, concurrency) = (end, segment.rate.1, segment.concurrency.1);
+            segments.push(segment);
+        }
+        Self { segments }
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.segments.last().map_or(0.0, |segment| segment.end))
+    }
+
+    fn segment_at(&self, t: f64) -> Option<&Segment> {
+        self.segments.iter().find(|segment| t < segment.end)
+    }
+
+    /// Target call rate at `t`
+    pub fn calls_per_second(&self, t: Duration) -> f64 {
+        self.segment_at(t.as_secs_f64()).map_or(0.0, |segment| {
+            let (from, to) = segment.rate;
+            from + (to - from) * segment.fraction(t.as_secs_f64())
+        })
+    }
+
+    /// Most calls allowed up at `t`
+    pub fn max_concurrent(&self, t: Duration) -> u32 {
+        self.segment_at(t.as_secs_f64()).map_or(0, |segment| {
+            let (from, to) = segment.concurrency;
+            (from + (to - from) * segment.fraction(t.as_secs_f64())).round() as u32
+        })
+    }
+
+    /// Calls that should have been attempted by `t`: the call rate integrated up to it
+    pub fn calls_due(&self, t: Duration) -> u64 {
+        let t = t.as_secs_f64();
+        let calls: f64 = self.segments.iter()
+            .take_while(|segment| segment.start < t)
+            .map(|segment| {
+                let (from, to) = segment.rate;
+                let end = segment.fraction(t);
+                let length = segment.end - segment.start;
+                // Area under the ramp from its start to `end` of the way through
+                length * end * (from + (from + (to - from) * end)) / 2.0
+            })
+            .sum();
+        (calls + 1e-9).floor() as u64
+    }
+}
+
+/// Status code of a failure reason such as "486 Busy Here"
+pub fn status_code(reason: &str) -> Option<u16> {
+    reason.split_whitespace().next()?.parse().ok().filter(|code| (300..700).contains(code))
+}
+
+/// Nearest-rank percentiles of a set of measurements
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub samples: usize,
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Percentiles {
+    pub fn new(mut samples: Vec<f64>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_by(f64::total_cmp);
+        let rank = |percent: f64| samples[((percent / 100.0 * samples.len() as f64).ceil() as usize).clamp(1, samples.len()) - 1];
+        Self {
+            samples: samples.len(),
+            p50: rank(50.0),
+            p90: rank(90.0),
+            p95: rank(95.0),
+            p99: rank(99.0),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+/// Results of a load run
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LoadReport {
+    pub duration_seconds: f64,
+    pub attempts: u32,
+    pub answered: u32,
+    /// Answer-seizure ratio: answered calls per attempt, in percent
+    pub asr_percent: f64,
+    /// Attempts not made because the concurrency limit was reached
+    pub skipped_at_limit: u32,
+    pub peak_concurrent: u32,
+    /// Time from the INVITE to ringing, or to the answer when there was no ringing
+    pub post_dial_delay_ms: Percentiles,
+    /// Failed calls by final status code, or by reason when there was none
+    pub failures: BTreeMap<String, u32>,
+}
+
+impl LoadReport {
+    pub fn new(outcomes: &[CallOutcome], duration: Duration, skipped_at_limit: u32, peak_concurrent: u32) -> Self {
+        let answered = outcomes.iter().filter(|outcome| outcome.answer_time.is_some()).count() as u32;
+        let mut failures = BTreeMap::new();
+        for outcome in outcomes.iter().filter(|outcome| outcome.answer_time.is_none()) {
+            let reason = outcome.failure.as_deref().unwrap_or("unknown");
+            let key = status_code(reason).map_or_else(|| reason.to_string(), |code| code.to_string());
+            *failures.entry(key).or_default() += 1;
+        }
+
+        Self {
+            duration_seconds: duration.as_secs_f64(),
+            attempts: outcomes.len() as u32,
+            answered,
+            asr_percent: if outcomes.is_empty() { 0.0 } else { answered as f64 * 100.0 / outcomes.len() as f64 },
+            skipped_at_limit,
+            peak_concurrent,
+            post_dial_delay_ms: Percentiles::new(outcomes.iter()
+                .filter_map(|outcome| outcome.post_dial_delay)
+                .map(|delay| delay.as_secs_f64() * 1000.0)
+                .collect()),
+            failures,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(seconds: f64) -> Duration {
+        Duration::from_secs_f64(seconds)
+    }
+
+    #[test]
+    fn test_ramp_profile() {
+        let profile = LoadProfile::new(&ramp_profile(secs(10.0), secs(20.0), secs(10.0), 10.0, 100));
+        assert_eq!(profile.duration(), secs(40.0));
+
+        assert_eq!(profile.calls_per_second(secs(5.0)), 5.0);
+        assert_eq!(profile.max_concurrent(secs(5.0)), 50);
+        assert_eq!((profile.calls_per_second(secs(15.0)), profile.max_concurrent(secs(15.0))), (10.0, 100));
+        assert_eq!(profile.max_concurrent(secs(35.0)), 50);
+        assert_eq!(profile.max_concurrent(secs(41.0)), 0);
+
+        // 50 calls in each ramp, 200 at the peak
+        assert_eq!(profile.calls_due(secs(0.0)), 0);
+        assert_eq!(profile.calls_due(secs(5.0)), 12);
+        assert_eq!(profile.calls_due(secs(10.0)), 50);
+        assert_eq!(profile.calls_due(secs(30.0)), 250);
+        assert_eq!(profile.calls_due(secs(40.0)), 300);
+        assert_eq!(profile.calls_due(secs(100.0)), 300);
+
+        assert_eq!(ramp_profile(Duration::ZERO, secs(60.0), Duration::ZERO, 1.0, 5).len(), 1);
+    }
+
+    #[test]
+    fn test_report() {
+        let answered = |pdd_ms| CallOutcome {
+            answer_time: Some(Duration::from_millis(pdd_ms + 2000)),
+            post_dial_delay: Some(Duration::from_millis(pdd_ms)),
+            ..CallOutcome::default()
+        };
+        let mut outcomes: Vec<CallOutcome> = (1..=8).map(|i| answered(i * 100)).collect();
+        outcomes.push(CallOutcome { post_dial_delay: Some(Duration::from_millis(900)), ..CallOutcome::failed("486 Busy Here") });
+        outcomes.push(CallOutcome::failed("No answer"));
+
+        let report = LoadReport::new(&outcomes, secs(60.0), 3, 7);
+        assert_eq!((report.attempts, report.answered, report.skipped_at_limit), (10, 8, 3));
+        assert_eq!(report.asr_percent, 80.0);
+        assert_eq!(report.post_dial_delay_ms.samples, 9);
+        assert_eq!((report.post_dial_delay_ms.p50, report.post_dial_delay_ms.p90), (500.0, 900.0));
+        assert_eq!(report.failures.get("486"), Some(&1));
+        assert_eq!(report.failures.get("No answer"), Some(&1));
+        assert_eq!(status_code("200 OK"), None);
+    }
+}
@@ -128,7 +128,6 @@ fn parse_log_level(level: &str) -> log::LevelFilter {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::TempDir;
     
     #[test]
     fn test_parse_log_level() {
@@ -0,0 +1,471 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::config::LoopbackTestConfig;
+use crate::fft::{fft, inverse_fft};
+use crate::inbound::InboundSink;
+use crate::resampler::{Resampler, ResamplerQuality};
+
+/// Frequency range the response and noise are judged over (narrowband telephony)
+const EVALUATION_LOW_HZ: f32 = 200.0;
+const EVALUATION_HIGH_HZ: f32 = 3400.0;
+/// Length of the Welch segments used for the cross spectra
+const WELCH_SEGMENT_SECONDS: f64 = 0.064;
+/// Bands with less reference energy than this below the strongest one are not judged
+const EXCITATION_RANGE_DB: f64 = 30.0;
+/// Normalised cross-correlation below which the reference counts as not looped back
+const MIN_CORRELATION: f64 = 0.1;
+/// SNR above which noise costs nothing in the MOS estimate
+const NOISE_FREE_SNR_DB: f32 = 35.0;
+/// Fade in and out of the sweep, against clicks
+const SWEEP_FADE_SECONDS: f32 = 0.01;
+
+/// Objective comparison of a looped-back recording with the reference that was played
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoopbackScore {
+    /// Round-trip delay
+    pub delay_ms: f64,
+    /// Level of the returned signal relative to the reference
+    pub gain_db: f32,
+    /// Returned signal against everything not linearly related to the reference
+    pub snr_db: f32,
+    /// Third-octave response relative to its mean
+    pub response: Vec<BandResponse>,
+    pub max_deviation_db: f32,
+    pub rms_deviation_db: f32,
+    /// E-model style transmission rating derived from the metrics above
+    pub r_factor: f32,
+    /// Estimated listening quality on the 1..4.5 scale; a stand-in, not a POLQA score
+    pub mos: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BandResponse {
+    pub center_hz: f32,
+    pub deviation_db: f32,
+}
+
+/// Exponential sine sweep, so every octave gets the same time
+pub fn log_sweep(start_hz: f32, end_hz: f32, seconds: f32, level_dbfs: f32, sample_rate: u32) -> Vec<f32> {
+    let length = (seconds * sample_rate as f32) as usize;
+    let amplitude = 10f64.powf(level_dbfs as f64 / 20.0);
+    let rate = (end_hz as f64 / start_hz as f64).ln() / seconds as f64;
+    let fade = ((SWEEP_FADE_SECONDS * sample_rate as f32) as usize).max(1);
+
+    (0..length)
+        .map(|i| {
+            let t = i as f64 / sample_rate as f64;
+            let phase = 2.0 * std::f64::consts::PI * start_hz as f64 * ((rate * t).exp() - 1.0) / rate;
+            let edge = i.min(length - 1 - i);
+            let envelope = if edge < fade { edge as f64 / fade as f64 } else { 1.0 };
+            (amplitude * envelope * phase.sin()) as f32
+        })
+        .collect()
+}
+
+/// Mono samples of a WAV file at the given sample rate
+pub fn load_reference<P: AsRef<Path>>(path: P, sample_rate: u32) -> Result<Vec<f32>> {
+    let path = path.as_ref();
+    let mut reader = hound::WavReader::open(path)
+        .with_context(|| format!("Failed to open loopback reference {}", path.display()))?;
+    let spec = reader.spec();
+
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader.samples::<i32>().map(|s| s.map(|s| s as f32 * scale)).collect::<Result<_, _>>()?
+        }
+    };
+
+    let channels = spec.channels.max(1) as usize;
+    let mono: Vec<f32> = samples.chunks(channels).map(|frame| frame.iter().sum::<f32>() / frame.len() as f32).collect();
+    Ok(resample(&mono, spec.sample_rate, sample_rate))
+}
+
+/// The reference a loopback test plays: its file, or the configured sweep
+pub fn reference_signal(test: &LoopbackTestConfig, sample_rate: u32) -> Result<Vec<f32>> {
+    match &test.reference_file {
+        Some(path) => load_reference(path, sample_rate),
+        None => Ok(log_sweep(
+            test.sweep_start_hz,
+            test.sweep_end_hz,
+            test.sweep_duration_ms as f32 / 1000.0,
+            test.level_dbfs,
+            sample_rate,
+        )),
+    }
+}
+
+pub fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to {
+        return samples.to_vec();
+    }
+    let mut resampler = Resampler::new(from, to, ResamplerQuality::High);
+    let mut output = resampler.process(samples);
+    output.extend(resampler.flush());
+    output
+}
+
+/// Align `recorded` with `reference` and score the round trip
+///
+/// The delay is the lag (up to `max_delay` samples) with the strongest cross-correlation.
+/// Over the aligned part, Welch cross spectra split the returned signal into the part
+/// linearly related to the reference, which gives the gain and frequency response, and
+/// the rest, which counts as noise and distortion.
+pub fn compare(reference: &[f32], recorded: &[f32], sample_rate: u32, max_delay: usize) -> Result<LoopbackScore> {
+    let segment = ((sample_rate as f64 * WELCH_SEGMENT_SECONDS) as usize).next_power_of_two();
+    if reference.len() < 2 * segment {
+        return Err(anyhow::anyhow!("Loopback reference too short: {} samples", reference.len()));
+    }
+
+    let delay = find_delay(reference, recorded, max_delay)
+        .context("Reference signal not found in the loopback recording")?;
+    let overlap = reference.len().min(recorded.len() - delay);
+    if overlap < 2 * segment {
+        return Err(anyhow::anyhow!("Loopback recording ends {} samples after the reference started", overlap));
+    }
+
+    let spectra = CrossSpectra::measure(&reference[..overlap], &recorded[delay..delay + overlap], segment);
+    let bands = spectra.third_octave_bands(sample_rate);
+    if bands.is_empty() {
+        return Err(anyhow::anyhow!("Loopback reference has no energy between {}Hz and {}Hz", EVALUATION_LOW_HZ, EVALUATION_HIGH_HZ));
+    }
+
+    let sum = |value: fn(&BandPower) -> f64| bands.iter().map(value).sum::<f64>();
+    let gain_db = 10.0 * (sum(|b| b.coherent) / sum(|b| b.reference)).max(1e-20).log10();
+    let snr_db = 10.0 * (sum(|b| b.coherent) / sum(|b| b.noise).max(1e-20)).max(1e-20).log10();
+
+    let levels: Vec<f64> = bands.iter().map(|b| 10.0 * (b.coherent / b.reference).max(1e-20).log10()).collect();
+    let mean = levels.iter().sum::<f64>() / levels.len() as f64;
+    let response: Vec<BandResponse> = bands.iter().zip(&levels)
+        .map(|(band, level)| BandResponse { center_hz: band.center_hz, deviation_db: (level - mean) as f32 })
+        .collect();
+    let max_deviation_db = response.iter().map(|b| b.deviation_db.abs()).fold(0.0, f32::max);
+    let rms_deviation_db = (response.iter().map(|b| b.deviation_db.powi(2)).sum::<f32>() / response.len() as f32).sqrt();
+
+    let delay_ms = delay as f64 * 1000.0 / sample_rate as f64;
+    let r_factor = r_factor(delay_ms as f32, snr_db as f32, rms_deviation_db);
+
+    Ok(LoopbackScore {
+        delay_ms,
+        gain_db: gain_db as f32,
+        snr_db: snr_db as f32,
+        response,
+        max_deviation_db,
+        rms_deviation_db,
+        r_factor,
+        mos: mos_from_r(r_factor),
+    })
+}
+
+/// Lag of `reference` within `recorded` with the strongest (either polarity) correlation
+fn find_delay(reference: &[f32], recorded: &[f32], max_delay: usize) -> Option<usize> {
+    if recorded.is_empty() {
+        return None;
+    }
+
+    let n = (reference.len() + recorded.len()).next_power_of_two();
+    let padded = |signal: &[f32]| {
+        let mut re: Vec<f64> = signal.iter().map(|&x| x as f64).collect();
+        re.resize(n, 0.0);
+        re
+    };
+    let (mut y_re, mut y_im) = (padded(recorded), vec![0.0; n]);
+    let (mut r_re, mut r_im) = (padded(reference), vec![0.0; n]);
+    fft(&mut y_re, &mut y_im);
+    fft(&mut r_re, &mut r_im);
+
+    // Y · conj(R) transforms back to the correlation at each lag
+    for k in 0..n {
+        let (a, b, c, d) = (y_re[k], y_im[k], r_re[k], r_im[k]);
+        y_re[k] = a * c + b * d;
+        y_im[k] = b * c - a * d;
+    }
+    inverse_fft(&mut y_re, &mut y_im);
+
+    let delay = (0..=max_delay.min(recorded.len() - 1))
+        .max_by(|&a, &b| y_re[a].abs().total_cmp(&y_re[b].abs()))?;
+
+    let energy = |signal: &[f32]| signal.iter().map(|&x| x as f64 * x as f64).sum::<f64>();
+    let window = &recorded[delay..recorded.len().min(delay + reference.len())];
+    let normalized = y_re[delay].abs() / (energy(reference) * energy(window)).sqrt().max(1e-20);
+    (normalized >= MIN_CORRELATION).then_some(delay)
+}
+
+/// Welch-averaged auto and cross spectra of the reference and the aligned recording
+struct CrossSpectra {
+    segment: usize,
+    reference: Vec<f64>,
+    recorded: Vec<f64>,
+    cross_re: Vec<f64>,
+    cross_im: Vec<f64>,
+}
+
+/// Powers within one third-octave band
+struct BandPower {
+    center_hz: f32,
+    reference: f64,
+    /// Recorded power explained by the reference (coherent output power)
+    coherent: f64,
+    noise: f64,
+}
+
+impl CrossSpectra {
+    fn measure(reference: &[f32], recorded: &[f32], segment: usize) -> Self {
+        let bins = segment / 2 + 1;
+        let hann: Vec<f64> = (0..segment)
+            .map(|n| 0.5 - 0.5 * (2.0 * std::f64::consts::PI * n as f64 / segment as f64).cos())
+            .collect();
+        let mut spectra = Self {
+            segment,
+            reference: vec![0.0; bins],
+            recorded: vec![0.0; bins],
+            cross_re: vec![0.0; bins],
+            cross_im: vec![0.0; bins],
+        };
+
+        let transform = |signal: &[f32]| {
+            let mut re: Vec<f64> = signal.iter().zip(&hann).map(|(&x, w)| x as f64 * w).collect();
+            let mut im = vec![0.0; segment];
+            fft(&mut re, &mut im);
+            (re, im)
+        };
+
+        // Half-overlapping segments
+        for start in (0..=reference.len() - segment).step_by(segment / 2) {
+            let (r_re, r_im) = transform(&reference[start..start + segment]);
+            let (y_re, y_im) = transform(&recorded[start..start + segment]);
+            for k in 0..bins {
+                spectra.reference[k] += r_re[k] * r_re[k] + r_im[k] * r_im[k];
+                spectra.recorded[k] += y_re[k] * y_re[k] + y_im[k] * y_im[k];
+                // conj(R) · Y
+                spectra.cross_re[k] += r_re[k] * y_re[k] + r_im[k] * y_im[k];
+                spectra.cross_im[k] += r_re[k] * y_im[k] - r_im[k] * y_re[k];
+            }
+        }
+        spectra
+    }
+
+    /// Third-octave bands inside the evaluation range that the reference excites
+    fn third_octave_bands(&self, sample_rate: u32) -> Vec<BandPower> {
+        let bin_hz = sample_rate as f32 / self.segment as f32;
+        let nyquist = sample_rate as f32 / 2.0;
+
+        let mut bands: Vec<(BandPower, usize)> = (-10..=10)
+            .map(|k| 1000.0 * 2f32.powf(k as f32 / 3.0))
+            .filter(|&center| (EVALUATION_LOW_HZ..=EVALUATION_HIGH_HZ).contains(&center))
+            .filter(|&center| center * 2f32.powf(1.0 / 6.0) < nyquist)
+            .map(|center_hz| {
+                let (low, high) = (center_hz * 2f32.powf(-1.0 / 6.0), center_hz * 2f32.powf(1.0 / 6.0));
+                let mut band = BandPower { center_hz, reference: 0.0, coherent: 0.0, noise: 0.0 };
+                let mut bins = 0;
+                for k in (0..self.reference.len()).filter(|&k| (low..high).contains(&(k as f32 * bin_hz))) {
+                    let cross = self.cross_re[k].powi(2) + self.cross_im[k].powi(2);
+                    let coherent = if self.reference[k] > 0.0 { cross / self.reference[k] } else { 0.0 };
+                    band.reference += self.reference[k];
+                    band.coherent += coherent;
+                    band.noise += (self.recorded[k] - coherent).max(0.0);
+                    bins += 1;
+                }
+                (band, bins)
+            })
+            .filter(|(_, bins)| *bins > 0)
+            .collect();
+
+        // Only judge bands the reference actually drives
+        let density = |(band, bins): &(BandPower, usize)| band.reference / *bins as f64;
+        let strongest = bands.iter().map(density).fold(0.0, f64::max);
+        let floor = strongest * 10f64.powf(-EXCITATION_RANGE_DB / 10.0);
+        bands.retain(|entry| strongest > 0.0 && density(entry) >= floor);
+        bands.into_iter().map(|(band, _)| band).collect()
+    }
+}
+
+/// Transmission rating: the G.711 default of 93.2, less delay (simplified G.107
+/// delay impairment on the one-way delay), noise and response impairments
+fn r_factor(round_trip_ms: f32, snr_db: f32, rms_deviation_db: f32) -> f32 {
+    let one_way = round_trip_ms / 2.0;
+    let delay = 0.024 * one_way + if one_way > 177.3 { 0.11 * (one_way - 177.3) } else { 0.0 };
+    let noise = 2.0 * (NOISE_FREE_SNR_DB - snr_db).max(0.0);
+    let response = 3.0 * (rms_deviation_db - 1.0).max(0.0);
+    (93.2 - delay - noise - response).clamp(0.0, 100.0)
+}
+
+/// G.107 mapping of the R factor to MOS
+fn mos_from_r(r: f32) -> f32 {
+    if r <= 0.0 {
+        1.0
+    } else if r >= 100.0 {
+        4.5
+    } else {
+        1.0 + 0.035 * r + r * (r - 60.0) * (100.0 - r) * 7e-6
+    }
+}
+
+#[derive(Default)]
+struct Recording {
+    armed: bool,
+    sample_rate: u32,
+    samples: Vec<f32>,
+}
+
+/// Inbound sink that keeps the received audio while a loopback test is running
+#[derive(Clone, Default)]
+pub struct LoopbackRecorder(Arc<Mutex<Recording>>);
+
+impl LoopbackRecorder {
+    fn recording(&self) -> std::sync::MutexGuard<'_, Recording> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Discard anything recorded so far and start recording
+    pub fn start(&self) {
+        let mut recording = self.recording();
+        recording.armed = true;
+        recording.samples.clear();
+    }
+
+    /// Stop recording, returning the samples and their sample rate
+    pub fn stop(&self) -> (Vec<f32>, u32) {
+        let mut recording = self.recording();
+        recording.armed = false;
+        (std::mem::take(&mut recording.samples), recording.sample_rate)
+    }
+}
+
+impl InboundSink for LoopbackRecorder {
+    fn name(&self) -> &'static str {
+        "loopback"
+    }
+
+    fn on_frame(&mut self, samples: &[i16], sample_rate: u32) {
+        let mut recording = self.recording();
+        if !recording.armed {
+            return;
+        }
+        if recording.sample_rate != sample_rate {
+            // A rate change mid-test leaves nothing comparable; keep the newest audio
+            recording.samples.clear();
+            recording.sample_rate = sample_rate;
+        }
+        recording.samples.extend(samples.iter().map(|&s| s as f32 / 32768.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biquad::{Biquad, BiquadCoefficients};
+    use crate::g711;
+
+    const RATE: u32 = 8000;
+
+    fn sweep() -> Vec<f32> {
+        log_sweep(100.0, 3800.0, 4.0, -12.0, RATE)
+    }
+
+    /// Delay, scale and pad the reference like a device looping it back
+    fn loop_back(reference: &[f32], delay: usize, gain_db: f32) -> Vec<f32> {
+        let gain = 10f32.powf(gain_db / 20.0);
+        let mut recorded = vec![0.0; delay];
+        recorded.extend(reference.iter().map(|&x| x * gain));
+        recorded.extend(std::iter::repeat_n(0.0, 2000));
+        recorded
+    }
+
+    fn add_noise(signal: &mut [f32], rms: f32) {
+        // Deterministic uniform noise from an LCG, scaled to the wanted RMS
+        let mut state = 42u32;
+        for sample in signal {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let uniform = (state >> 8) as f32 / (1u32 << 24) as f32 * 2.0 - 1.0;
+            *sample += uniform * rms * 3f32.sqrt();
+        }
+    }
+
+    fn rms(signal: &[f32]) -> f32 {
+        (signal.iter().map(|x| x * x).sum::<f32>() / signal.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_clean_loop_scores_high() {
+        let reference = sweep();
+        let score = compare(&reference, &loop_back(&reference, 400, -6.0), RATE, 8000).unwrap();
+
+        assert_eq!(score.delay_ms, 50.0);
+        assert!((score.gain_db + 6.0).abs() < 0.1, "gain {}", score.gain_db);
+        assert!(score.snr_db > 50.0, "snr {}", score.snr_db);
+        assert!(score.max_deviation_db < 0.5, "deviation {}", score.max_deviation_db);
+        assert_eq!(score.response.len(), 12);
+        assert!(score.mos > 4.35, "mos {}", score.mos);
+    }
+
+    #[test]
+    fn test_noise_lowers_snr_and_mos() {
+        let reference = sweep();
+        let mut recorded = loop_back(&reference, 123, 0.0);
+        // White noise 20dB below the sweep; the evaluated band holds a similar share of each
+        add_noise(&mut recorded, rms(&reference) / 10.0);
+
+        let score = compare(&reference, &recorded, RATE, 8000).unwrap();
+        assert_eq!(score.delay_ms, 123.0 * 1000.0 / 8000.0);
+        assert!((score.snr_db - 20.0).abs() < 1.5, "snr {}", score.snr_db);
+        assert!(score.max_deviation_db < 1.0, "deviation {}", score.max_deviation_db);
+        assert!((3.0..3.8).contains(&score.mos), "mos {}", score.mos);
+    }
+
+    #[test]
+    fn test_filtering_shows_in_response_not_snr() {
+        let reference = sweep();
+        let mut recorded = loop_back(&reference, 200, 0.0);
+        Biquad::new(BiquadCoefficients::highpass(600.0, RATE as f32)).process_block(&mut recorded);
+
+        let score = compare(&reference, &recorded, RATE, 8000).unwrap();
+        let lowest = &score.response[0];
+        assert_eq!(lowest.center_hz.round(), 250.0);
+        assert!(lowest.deviation_db < -8.0, "250Hz {}", lowest.deviation_db);
+        assert!(score.max_deviation_db > 8.0);
+        assert!(score.snr_db > 30.0, "snr {}", score.snr_db);
+        assert!(score.mos < 4.2, "mos {}", score.mos);
+    }
+
+    #[test]
+    fn test_g711_round_trip_and_long_delay() {
+        let reference = sweep();
+        let pcm: Vec<i16> = loop_back(&reference, 4000, 0.0).iter().map(|&x| (x * 32767.0) as i16).collect();
+        let decoded: Vec<f32> = g711::mulaw_to_pcm(&g711::pcm_to_mulaw(&pcm)).iter().map(|&s| s as f32 / 32768.0).collect();
+
+        let score = compare(&reference, &decoded, RATE, 8000).unwrap();
+        assert_eq!(score.delay_ms, 500.0);
+        assert!((30.0..45.0).contains(&score.snr_db), "snr {}", score.snr_db);
+        // 250ms one way is past the E-model's delay knee; the codec noise costs little
+        assert!((75.0..85.0).contains(&score.r_factor), "r {}", score.r_factor);
+        assert!((3.8..4.2).contains(&score.mos), "mos {}", score.mos);
+    }
+
+    #[test]
+    fn test_missing_loopback_is_an_error() {
+        let reference = sweep();
+        let mut silence = vec![0.0; reference.len() + 4000];
+        add_noise(&mut silence, 0.001);
+        assert!(compare(&reference, &silence, RATE, 4000).is_err());
+        assert!(compare(&reference, &[], RATE, 4000).is_err());
+    }
+
+    #[test]
+    fn test_recorder_only_keeps_armed_audio() {
+        let mut recorder = LoopbackRecorder::default();
+        recorder.on_frame(&[1000; 160], RATE);
+        recorder.start();
+        recorder.on_frame(&[16384; 160], RATE);
+        let (samples, rate) = recorder.stop();
+        recorder.on_frame(&[1000; 160], RATE);
+
+        assert_eq!(rate, RATE);
+        assert_eq!(samples, vec![0.5; 160]);
+        assert!(recorder.stop().0.is_empty());
+    }
+}
@@ -0,0 +1,451 @@
+use anyhow::{Context, Result};
+use hound::{WavReader, WavWriter};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::biquad::{Biquad, BiquadCoefficients};
+use crate::config::LoudnessConfig;
+use crate::g711;
+
+/// Loudness measure used to normalize prompts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoudnessMethod {
+    /// ITU-T P.56 active speech level in dBov; ignores pauses between words
+    #[default]
+    P56,
+    /// EBU R128 / ITU-R BS.1770 gated integrated loudness in LUFS
+    R128,
+}
+
+impl LoudnessMethod {
+    pub fn unit(self) -> &'static str {
+        match self {
+            LoudnessMethod::P56 => "dBov",
+            LoudnessMethod::R128 => "LUFS",
+        }
+    }
+}
+
+/// Result of measuring one signal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessMeasurement {
+    /// Active speech level (dBov) or integrated loudness (LUFS)
+    pub level: f64,
+    /// Fraction of the signal counted as active (P.56) or passing the gates (R128)
+    pub activity: f64,
+}
+
+/// ITU-T P.56 method B active speech level meter
+///
+/// Levels are in dBov, relative to a full-scale mean square of 1, so a full-scale
+/// sine measures -3.01 dBov.
+#[derive(Debug, Clone)]
+pub struct ActiveSpeechLevelMeter {
+    envelope_coeff: f64,
+    hangover_samples: u64,
+    p: f64,
+    q: f64,
+    sum_squares: f64,
+    samples: u64,
+    active: [u64; P56_THRESHOLDS],
+    hangover: [u64; P56_THRESHOLDS],
+}
+
+const P56_THRESHOLDS: usize = 16;
+/// Envelope smoothing time constant
+const P56_TIME_CONSTANT: f64 = 0.03;
+/// Time a threshold stays active after the envelope drops below it
+const P56_HANGOVER: f64 = 0.2;
+/// Margin between the active level and the activity threshold
+const P56_MARGIN_DB: f64 = 15.9;
+
+impl ActiveSpeechLevelMeter {
+    pub fn new(sample_rate: u32) -> Self {
+        let sample_rate = sample_rate.max(1) as f64;
+        Self {
+            envelope_coeff: (-1.0 / (sample_rate * P56_TIME_CONSTANT)).exp(),
+            hangover_samples: (P56_HANGOVER * sample_rate).ceil() as u64,
+            p: 0.0,
+            q: 0.0,
+            sum_squares: 0.0,
+            samples: 0,
+            active: [0; P56_THRESHOLDS],
+            hangover: [0; P56_THRESHOLDS],
+        }
+    }
+
+    /// Activity thresholds 2^-15 .. 1
+    fn threshold(index: usize) -> f64 {
+        2f64.powi(index as i32 - (P56_THRESHOLDS as i32 - 1))
+    }
+
+    pub fn process(&mut self, sample: f32) {
+        let x = sample as f64;
+        let g = self.envelope_coeff;
+        self.p = g * self.p + (1.0 - g) * x.abs();
+        self.q = g * self.q + (1.0 - g) * self.p;
+        self.sum_squares += x * x;
+        self.samples += 1;
+
+        for j in 0..P56_THRESHOLDS {
+            if self.q >= Self::threshold(j) {
+                self.active[j] += 1;
+                self.hangover[j] = 0;
+            } else if self.hangover[j] < self.hangover_samples {
+                self.active[j] += 1;
+                self.hangover[j] += 1;
+            }
+        }
+    }
+
+    /// Active speech level, or None if the signal never rose above the lowest threshold
+    pub fn measurement(&self) -> Option<LoudnessMeasurement> {
+        if self.sum_squares <= 0.0 || self.active[0] == 0 {
+            return None;
+        }
+
+        let level = |j: usize| 10.0 * (self.sum_squares / self.active[j] as f64).log10();
+        let margin = |j: usize| level(j) - 20.0 * Self::threshold(j).log10();
+        let activity = |j: usize| self.active[j] as f64 / self.samples as f64;
+
+        if margin(0) <= P56_MARGIN_DB {
+            return Some(LoudnessMeasurement { level: level(0), activity: activity(0) });
+        }
+
+        for j in 1..P56_THRESHOLDS {
+            if self.active[j] == 0 {
+                // Envelope never reached this threshold; the previous one is the best estimate
+                return Some(LoudnessMeasurement { level: level(j - 1), activity: activity(j - 1) });
+            }
+            if margin(j) <= P56_MARGIN_DB {
+                // Interpolate where the level-to-threshold margin crosses 15.9 dB
+                let fraction = (margin(j - 1) - P56_MARGIN_DB) / (margin(j - 1) - margin(j));
+                return Some(LoudnessMeasurement {
+                    level: level(j - 1) + fraction * (level(j) - level(j - 1)),
+                    activity: activity(j - 1) + fraction * (activity(j) - activity(j - 1)),
+                });
+            }
+        }
+
+        let last = P56_THRESHOLDS - 1;
+        Some(LoudnessMeasurement { level: level(last), activity: activity(last) })
+    }
+}
+
+/// ITU-R BS.1770 integrated loudness meter (mono), as used by EBU R128
+#[derive(Debug, Clone)]
+pub struct IntegratedLoudnessMeter {
+    shelf: Biquad,
+    highpass: Biquad,
+    step_samples: u64,
+    step_sum: f64,
+    step_count: u64,
+    /// Mean square of the last four 100ms steps
+    recent_steps: Vec<f64>,
+    /// Mean square of each 400ms gating block
+    blocks: Vec<f64>,
+}
+
+const R128_STEP_SECONDS: f64 = 0.1;
+const R128_STEPS_PER_BLOCK: usize = 4;
+const R128_ABSOLUTE_GATE: f64 = -70.0;
+const R128_RELATIVE_GATE: f64 = -10.0;
+
+impl IntegratedLoudnessMeter {
+    pub fn new(sample_rate: u32) -> Self {
+        let fs = sample_rate.max(1) as f64;
+
+        // K-weighting, designed so that at 48kHz the coefficients match BS.1770 exactly
+        // Stage 1: high shelf modelling the acoustic effect of the head
+        let (gain_db, q, fc) = (3.999_843_853_973_347, 0.707_175_236_955_419_6, 1_681.974_450_955_533);
+        let k = (std::f64::consts::PI * fc / fs).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.499_666_774_154_541_6);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = BiquadCoefficients::normalized(
+            vh + vb * k / q + k * k,
+            2.0 * (k * k - vh),
+            vh - vb * k / q + k * k,
+            a0,
+            2.0 * (k * k - 1.0),
+            1.0 - k / q + k * k,
+        );
+
+        // Stage 2: RLB highpass
+        let (q, fc) = (0.500_327_037_323_877_3, 38.135_470_876_024_44);
+        let k = (std::f64::consts::PI * fc / fs).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let highpass = BiquadCoefficients::normalized(
+            a0,
+            -2.0 * a0,
+            a0,
+            a0,
+            2.0 * (k * k - 1.0),
+            1.0 - k / q + k * k,
+        );
+
+        Self {
+            shelf: Biquad::new(shelf),
+            highpass: Biquad::new(highpass),
+            step_samples: ((fs * R128_STEP_SECONDS).round() as u64).max(1),
+            step_sum: 0.0,
+            step_count: 0,
+            recent_steps: Vec::with_capacity(R128_STEPS_PER_BLOCK),
+            blocks: Vec::new(),
+        }
+    }
+
+    pub fn process(&mut self, sample: f32) {
+        let weighted = self.highpass.process(self.shelf.process(sample)) as f64;
+        self.step_sum += weighted * weighted;
+        self.step_count += 1;
+
+        if self.step_count == self.step_samples {
+            if self.recent_steps.len() == R128_STEPS_PER_BLOCK {
+                self.recent_steps.remove(0);
+            }
+            self.recent_steps.push(self.step_sum / self.step_count as f64);
+            self.step_sum = 0.0;
+            self.step_count = 0;
+
+            if self.recent_steps.len() == R128_STEPS_PER_BLOCK {
+                self.blocks.push(self.recent_steps.iter().sum::<f64>() / R128_STEPS_PER_BLOCK as f64);
+            }
+        }
+    }
+
+    /// Gated integrated loudness, or None if no block passes the absolute gate
+    pub fn measurement(&self) -> Option<LoudnessMeasurement> {
+        let loudness = |mean_square: f64| -0.691 + 10.0 * mean_square.log10();
+        let gated_mean = |gate: f64| {
+            let passing: Vec<f64> = self.blocks.iter().copied().filter(|&block| loudness(block) > gate).collect();
+            if passing.is_empty() {
+                None
+            } else {
+                Some((passing.iter().sum::<f64>() / passing.len() as f64, passing.len()))
+            }
+        };
+
+        let (absolute_mean, _) = gated_mean(R128_ABSOLUTE_GATE)?;
+        let relative_gate = loudness(absolute_mean) + R128_RELATIVE_GATE;
+        let (mean, count) = gated_mean(relative_gate.max(R128_ABSOLUTE_GATE))?;
+
+        Some(LoudnessMeasurement {
+            level: loudness(mean),
+            activity: count as f64 / self.blocks.len() as f64,
+        })
+    }
+}
+
+/// Measure a mono signal with the given method
+pub fn measure(samples: &[f32], sample_rate: u32, method: LoudnessMethod) -> Option<LoudnessMeasurement> {
+    match method {
+        LoudnessMethod::P56 => {
+            let mut meter = ActiveSpeechLevelMeter::new(sample_rate);
+            samples.iter().for_each(|&sample| meter.process(sample));
+            meter.measurement()
+        }
+        LoudnessMethod::R128 => {
+            let mut meter = IntegratedLoudnessMeter::new(sample_rate);
+            samples.iter().for_each(|&sample| meter.process(sample));
+            meter.measurement()
+        }
+    }
+}
+
+/// Outcome of normalizing one file
+#[derive(Debug, Clone)]
+pub struct LoudnessReport {
+    pub method: LoudnessMethod,
+    /// Level before normalization, in the method's unit
+    pub measured_level: f64,
+    pub target_level: f64,
+    pub applied_gain_db: f64,
+    /// Sample peak before normalization
+    pub peak_dbfs: f64,
+    /// True if the gain was reduced to keep the peak below `max_peak_dbfs`
+    pub peak_limited: bool,
+    pub activity: f64,
+}
+
+/// Measure a 16-bit PCM WAV file and rewrite it at the configured target level
+///
+/// The first pass streams the file through the meter, the second applies the gain
+/// into a temporary file that then replaces the original. Multi-channel files are
+/// measured on the mean of their channels. Returns None, leaving the file untouched,
+/// if it contains no measurable signal.
+pub fn normalize_wav(path: &Path, config: &LoudnessConfig) -> Result<Option<LoudnessReport>> {
+    let mut reader = WavReader::open(path)
+        .with_context(|| format!("Failed to open WAV file: {}", path.display()))?;
+    let spec = reader.spec();
+    let channels = spec.channels.max(1) as usize;
+
+    // Pass 1: measure
+    let mut p56 = ActiveSpeechLevelMeter::new(spec.sample_rate);
+    let mut r128 = IntegratedLoudnessMeter::new(spec.sample_rate);
+    let mut peak = 0i32;
+    let mut frame_sum = 0.0f32;
+    for (i, sample) in reader.samples::<i16>().enumerate() {
+        let sample = sample.context("Failed to read WAV sample")?;
+        peak = peak.max((sample as i32).abs());
+        frame_sum += sample as f32 / 32768.0;
+
+        if (i + 1) % channels == 0 {
+            let mono = frame_sum / channels as f32;
+            match config.method {
+                LoudnessMethod::P56 => p56.process(mono),
+                LoudnessMethod::R128 => r128.process(mono),
+            }
+            frame_sum = 0.0;
+        }
+    }
+
+    let measurement = match config.method {
+        LoudnessMethod::P56 => p56.measurement(),
+        LoudnessMethod::R128 => r128.measurement(),
+    };
+    let Some(measurement) = measurement else {
+        return Ok(None);
+    };
+
+    // Normalization runs after the chain's limiter, so it must not undo the G.711 ceiling
+    let max_peak_dbfs = config.max_peak_dbfs.min(g711::full_scale_dbfs() as f64);
+    let peak_dbfs = 20.0 * (peak as f64 / 32768.0).log10();
+    let mut gain_db = config.target_level - measurement.level;
+    let peak_limited = peak_dbfs + gain_db > max_peak_dbfs;
+    if peak_limited {
+        gain_db = max_peak_dbfs - peak_dbfs;
+    }
+
+    // Pass 2: apply the gain
+    let gain = 10f64.powf(gain_db / 20.0);
+    let temp_path = path.with_extension("normalizing.wav");
+    let mut reader = WavReader::open(path)
+        .with_context(|| format!("Failed to open WAV file: {}", path.display()))?;
+    let mut writer = WavWriter::create(&temp_path, spec)
+        .with_context(|| format!("Failed to create WAV file: {}", temp_path.display()))?;
+    for sample in reader.samples::<i16>() {
+        let sample = sample.context("Failed to read WAV sample")?;
+        let full_scale = g711::FULL_SCALE as f64;
+        let scaled = (sample as f64 * gain).round().clamp(-full_scale, full_scale) as i16;
+        writer.write_sample(scaled).context("Failed to write sample")?;
+    }
+    writer.finalize().context("Failed to finalize WAV file")?;
+    fs::rename(&temp_path, path)
+        .with_context(|| format!("Failed to replace {}", path.display()))?;
+
+    Ok(Some(LoudnessReport {
+        method: config.method,
+        measured_level: measurement.level,
+        target_level: config.target_level,
+        applied_gain_db: gain_db,
+        peak_dbfs,
+        peak_limited,
+        activity: measurement.activity,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::WavSpec;
+    use tempfile::TempDir;
+
+    fn sine(amplitude: f32, frequency: f32, sample_rate: u32, seconds: f32) -> Vec<f32> {
+        let count = (sample_rate as f32 * seconds) as usize;
+        (0..count)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_p56_level_ignores_pauses() {
+        let tone = sine(0.5, 440.0, 8000, 2.0);
+        let expected = 20.0 * (0.5 / 2f64.sqrt()).log10();
+
+        let continuous = measure(&tone, 8000, LoudnessMethod::P56).unwrap();
+        assert!((continuous.level - expected).abs() < 0.2, "level {}", continuous.level);
+        assert!(continuous.activity > 0.95);
+
+        // Alternating one second of tone and one of silence stays close to the active level
+        // (only the hangover after each burst counts as active) instead of dropping 3 dB
+        let mut gapped = Vec::new();
+        for _ in 0..3 {
+            gapped.extend_from_slice(&tone[..8000]);
+            gapped.extend(std::iter::repeat_n(0.0, 8000));
+        }
+        let gapped = measure(&gapped, 8000, LoudnessMethod::P56).unwrap();
+        assert!(gapped.level < expected && gapped.level > expected - 1.5, "level {}", gapped.level);
+        assert!(gapped.activity > 0.45 && gapped.activity < 0.7, "activity {}", gapped.activity);
+
+        assert!(measure(&[0.0; 8000], 8000, LoudnessMethod::P56).is_none());
+    }
+
+    #[test]
+    fn test_r128_reference_tone() {
+        // A 1kHz sine at -20 dBFS peak measures -23 LUFS; at 8kHz the K-weighting
+        // filters are redesigned for the lower rate and land within half a dB
+        for (sample_rate, tolerance) in [(48000, 0.1), (8000, 0.5)] {
+            let tone = sine(0.1, 1000.0, sample_rate, 5.0);
+            let loudness = measure(&tone, sample_rate, LoudnessMethod::R128).unwrap();
+            assert!((loudness.level + 23.0).abs() < tolerance, "{}Hz: {} LUFS", sample_rate, loudness.level);
+        }
+
+        // Silence is gated out rather than dragging the level down
+        let mut tone = sine(0.1, 1000.0, 48000, 3.0);
+        let continuous = measure(&tone, 48000, LoudnessMethod::R128).unwrap();
+        tone.extend(std::iter::repeat_n(0.0, 48000 * 3));
+        let gapped = measure(&tone, 48000, LoudnessMethod::R128).unwrap();
+        assert!((gapped.level - continuous.level).abs() < 0.3, "{} LUFS", gapped.level);
+        assert!(gapped.activity < 0.6);
+    }
+
+    #[test]
+    fn test_normalize_wav() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("prompt.wav");
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let write = |amplitude: f32| {
+            let mut writer = WavWriter::create(&path, spec).unwrap();
+            for sample in sine(amplitude, 440.0, 8000, 2.0) {
+                writer.write_sample((sample * 32767.0) as i16).unwrap();
+            }
+            writer.finalize().unwrap();
+        };
+
+        write(0.05);
+        let config = LoudnessConfig { enabled: true, ..LoudnessConfig::default() };
+        let report = normalize_wav(&path, &config).unwrap().unwrap();
+        assert!(!report.peak_limited);
+        let samples: Vec<f32> = WavReader::open(&path).unwrap()
+            .into_samples::<i16>().map(|s| s.unwrap() as f32 / 32768.0).collect();
+        let level = measure(&samples, 8000, LoudnessMethod::P56).unwrap().level;
+        assert!((level - config.target_level).abs() < 0.2, "normalized to {}", level);
+
+        // A target that would clip is capped by the peak ceiling
+        write(0.5);
+        let config = LoudnessConfig { enabled: true, target_level: -3.0, ..LoudnessConfig::default() };
+        let report = normalize_wav(&path, &config).unwrap().unwrap();
+        assert!(report.peak_limited);
+        let peak = WavReader::open(&path).unwrap()
+            .into_samples::<i16>().map(|s| (s.unwrap() as i32).abs()).max().unwrap();
+        let peak_dbfs = 20.0 * (peak as f64 / 32768.0).log10();
+        assert!((peak_dbfs - config.max_peak_dbfs).abs() < 0.1, "peak {}", peak_dbfs);
+
+        // A ceiling above the G.711 full scale is held to it
+        write(0.5);
+        let config = LoudnessConfig { enabled: true, target_level: -3.0, max_peak_dbfs: 0.0, ..LoudnessConfig::default() };
+        let report = normalize_wav(&path, &config).unwrap().unwrap();
+        assert!(report.peak_limited);
+        let peak = WavReader::open(&path).unwrap()
+            .into_samples::<i16>().map(|s| (s.unwrap() as i32).abs()).max().unwrap();
+        assert!(peak <= g711::FULL_SCALE as i32, "peak {}", peak);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::fs;
 use std::path::Path;
 use std::net::SocketAddr;
@@ -5,12 +6,14 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{Context, Result};
+use chrono::Utc;
 use clap::{Arg, Command};
 use daemonize::Daemonize;
-use log::{info, error};
+use log::{info, error, warn};
 use signal_hook::consts::SIGTERM;
 use signal_hook_tokio::Signals;
-use tokio::sync::{RwLock, Mutex};
+use tokio::sync::{mpsc, oneshot, RwLock, Mutex};
+use tokio::task::JoinHandle;
 use tokio::time::Instant;
 use tokio_stream::StreamExt;
 
@@ -18,30 +21,121 @@ use tokio_stream::StreamExt;
 use rvoip::client_core::{
     ClientBuilder, ClientManager, ClientEventHandler, 
     CallId, CallState, CallStatusInfo, RegistrationStatusInfo, MediaEventInfo,
-    CallAction, ClientError, IncomingCallInfo
+    CallAction, ClientError, IncomingCallInfo, AudioFrame
 };
 
-pub mod config;
 mod logger;
-mod mp3_handler;
 
-use config::ServerConfig;
-use mp3_handler::Mp3Handler;
+use rvoip_sip_server::analysis::{AudioAnalysis, SharedAnalyzer};
+use rvoip_sip_server::audio_source::PromptAudio;
+use rvoip_sip_server::cdr::{CallDetailRecord, CdrWriter};
+use rvoip_sip_server::config::{DialplanAction, DialplanRule, ImpairmentConfig, LoopbackTestConfig, OriginateConfig, ServerConfig, TransferActionConfig};
+use rvoip_sip_server::hold::HoldTracker;
+use rvoip_sip_server::impairment::NetworkImpairment;
+use rvoip_sip_server::inbound::InboundAudioPipeline;
+use rvoip_sip_server::loopback::{self, LoopbackRecorder};
+use rvoip_sip_server::metrics::CallQualityMetrics;
+use rvoip_sip_server::load::{self, LoadProfile, LoadReport};
+use rvoip_sip_server::originate::{CallOutcome, OriginationSummary};
+use rvoip_sip_server::transfer::TransferRecord;
+use rvoip_sip_server::mp3_handler::Mp3Handler;
+use rvoip_sip_server::rtp_stats::{RtpSample, RtpStatsCollector};
+use rvoip_sip_server::tone_generator::{DtmfConfig, ToneConfig, ToneGenerator};
 
 const DEFAULT_CONFIG_PATH: &str = "/etc/rvoip-sip-server/config.toml";
 const DEFAULT_LOG_PATH: &str = "/var/log/rvoip-sip-server/server.log";
 const DEFAULT_PID_PATH: &str = "/var/run/rvoip-sip-server.pid";
 
+/// Packetization interval used when pacing prompt audio onto a call
+const FRAME_DURATION: Duration = Duration::from_millis(20);
+/// How long a call's record waits for its inbound audio to drain after hangup
+const INBOUND_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Analyses of ended calls kept available on the API
+const RECENT_ANALYSES: usize = 100;
+/// Largest request body the health server accepts
+const MAX_REQUEST_BODY: usize = 64 * 1024;
+/// How often a load test starts the calls that have come due
+const LOAD_TICK: Duration = Duration::from_millis(10);
+/// How often a load test logs its progress
+const LOAD_PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+/// How long an originated call may take to end after we hang it up
+const HANGUP_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Auto-answering SIP server handler
 #[derive(Clone)]
 struct AutoAnswerHandler {
     client_manager: Arc<RwLock<Option<Arc<ClientManager>>>>,
     mp3_handler: Arc<Mp3Handler>,
+    tone_generator: Arc<ToneGenerator>,
     server_config: Arc<ServerConfig>,
     active_calls: Arc<Mutex<std::collections::HashMap<CallId, tokio::time::Instant>>>,
+    // Dialplan rule selected for each call at INVITE time
+    call_rules: Arc<Mutex<std::collections::HashMap<CallId, DialplanRule>>>,
     call_stats: Arc<Mutex<CallStats>>,
-    // Pre-converted μ-law samples for MP3 playback
-    audio_samples: Arc<Mutex<Option<Vec<u8>>>>,
+    // MP3 prompt shared by all calls (buffered once, or streamed per call)
+    prompt: Arc<RwLock<Option<PromptAudio>>>,
+    // Playback or dialplan task driving each connected call
+    call_tasks: Arc<Mutex<std::collections::HashMap<CallId, JoinHandle<()>>>>,
+    // Inbound audio task of each connected call, drained before its record is written
+    inbound_tasks: Arc<Mutex<std::collections::HashMap<CallId, JoinHandle<()>>>>,
+    // Analysers of calls in progress, and the results of recently ended ones
+    call_analyses: Arc<Mutex<std::collections::HashMap<CallId, SharedAnalyzer>>>,
+    finished_analyses: Arc<Mutex<VecDeque<(CallId, AudioAnalysis)>>>,
+    // Recorder of calls whose dialplan runs loopback tests
+    call_recorders: Arc<Mutex<std::collections::HashMap<CallId, LoopbackRecorder>>>,
+    // Detail record of each call, completed when it ends
+    call_records: Arc<Mutex<std::collections::HashMap<CallId, CallDetailRecord>>>,
+    cdr_writer: Option<Arc<CdrWriter>>,
+    // RTP statistics poller of each connected call
+    rtp_monitors: Arc<Mutex<std::collections::HashMap<CallId, RtpMonitor>>>,
+    quality_metrics: Arc<Mutex<CallQualityMetrics>>,
+    // Network impairments applied to the audio sent on each call, from its rule or the API
+    call_impairments: Arc<Mutex<std::collections::HashMap<CallId, NetworkImpairment<AudioFrame>>>>,
+    // Hold state of each connected call
+    call_holds: Arc<Mutex<std::collections::HashMap<CallId, CallHold>>>,
+    // Calls sent a REFER, told when they end, until the transfer times out
+    pending_transfers: Arc<Mutex<std::collections::HashMap<CallId, oneshot::Sender<()>>>>,
+    // Calls placed by the server, followed by the task that placed them
+    outbound_calls: Arc<Mutex<std::collections::HashMap<CallId, OutboundCall>>>,
+}
+
+/// Call placed by the server for an origination
+struct OutboundCall {
+    states: mpsc::UnboundedSender<CallStatusInfo>,
+}
+
+/// Hold state of a connected call
+///
+/// Only holds placed by the server are tracked: the client API reports
+/// neither re-INVITEs from the caller nor the direction of their offers.
+struct CallHold {
+    connected: Instant,
+    /// Whether the server has put the caller on hold
+    local: bool,
+    tracker: HoldTracker,
+    // Scheduled hold actions and music on hold
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl CallHold {
+    fn new() -> Self {
+        Self {
+            connected: Instant::now(),
+            local: false,
+            tracker: HoldTracker::new(),
+            tasks: Vec::new(),
+        }
+    }
+
+    fn update_tracker(&mut self) {
+        self.tracker.update(self.local, self.connected.elapsed());
+    }
+}
+
+/// Periodic poll of a call's RTP session into its collector
+struct RtpMonitor {
+    collector: Arc<Mutex<RtpStatsCollector>>,
+    task: JoinHandle<()>,
 }
 
 #[derive(Debug, Default)]
@@ -53,14 +147,34 @@ struct CallStats {
 }
 
 impl AutoAnswerHandler {
-    pub fn new(mp3_handler: Arc<Mp3Handler>, server_config: Arc<ServerConfig>) -> Self {
+    pub fn new(mp3_handler: Arc<Mp3Handler>, server_config: Arc<ServerConfig>, cdr_writer: Option<Arc<CdrWriter>>) -> Self {
+        let tone_generator = ToneGenerator::new_with_config(ToneConfig {
+            sample_rate: server_config.media.audio_sample_rate,
+            ..ToneConfig::default()
+        });
+
         Self {
             client_manager: Arc::new(RwLock::new(None)),
             mp3_handler,
+            tone_generator: Arc::new(tone_generator),
             server_config,
             active_calls: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            call_rules: Arc::new(Mutex::new(std::collections::HashMap::new())),
             call_stats: Arc::new(Mutex::new(CallStats::default())),
-            audio_samples: Arc::new(Mutex::new(None)),
+            prompt: Arc::new(RwLock::new(None)),
+            call_tasks: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            inbound_tasks: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            call_analyses: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            finished_analyses: Arc::new(Mutex::new(VecDeque::new())),
+            call_recorders: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            call_records: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            cdr_writer,
+            rtp_monitors: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            quality_metrics: Arc::new(Mutex::new(CallQualityMetrics::new())),
+            call_impairments: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            call_holds: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            pending_transfers: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            outbound_calls: Arc::new(Mutex::new(std::collections::HashMap::new())),
         }
     }
     
@@ -70,68 +184,830 @@ impl AutoAnswerHandler {
 
 
 
-    /// Prepare audio samples for transmission (called during initialization)
+    /// Prepare the MP3 prompt for transmission (called during initialization)
     pub async fn prepare_audio_samples(&self) -> Result<(), anyhow::Error> {
-        info!("📡 Preparing MP3 audio samples for transmission...");
+        info!("📡 Preparing MP3 prompt for transmission...");
+        
+        let prompt = PromptAudio::open_wav(
+            self.mp3_handler.wav_path(),
+            self.server_config.media.max_buffered_prompt_seconds,
+        )?;
         
-        // Load PCM samples from WAV file
-        let pcm_samples = self.mp3_handler.read_wav_samples()?;
+        info!("✅ Prompt ready for transmission ({:?})", prompt.duration());
+        *self.prompt.write().await = Some(prompt);
+        Ok(())
+    }
+
+    /// Play the prepared MP3 prompt to the call, returning once it has been sent
+    async fn play_mp3_prompt(&self, call_id: &CallId) -> Result<(), anyhow::Error> {
+        let prompt = self.prompt.read().await.clone()
+            .context("Audio prompt not prepared. Call prepare_audio_samples() first.")?;
         
-        // Convert PCM samples to μ-law for PCMU codec
-        let mulaw_samples = self.mp3_handler.pcm_to_mulaw(&pcm_samples);
+        info!("🎵 Playing {:?} MP3 prompt to call {}", prompt.duration(), call_id);
+        self.play_prompt(call_id, &prompt).await
+    }
+
+    /// Pace a prompt out to the call in 20ms frames through its own cursor
+    ///
+    /// With impairments set on the call, frames pass through them and are sent
+    /// when due rather than on the frame clock.
+    async fn play_prompt(&self, call_id: &CallId, prompt: &PromptAudio) -> Result<(), anyhow::Error> {
+        let client = self.client_manager.read().await.clone()
+            .context("Client manager not available")?;
         
-        info!("🔄 Converted {} PCM samples to {} μ-law samples for RTP transmission", 
-              pcm_samples.len(), mulaw_samples.len());
+        client.start_audio_transmission(call_id).await
+            .context("Failed to start audio transmission")?;
         
-        // Store the samples for later use
-        *self.audio_samples.lock().await = Some(mulaw_samples);
+        let sample_rate = prompt.sample_rate();
+        let frame_len = (sample_rate as u64 * FRAME_DURATION.as_millis() as u64 / 1000) as usize;
+        let mut cursor = prompt.cursor()?;
+        let mut frame = Vec::with_capacity(frame_len);
+        let mut timestamp: u32 = 0;
+        let mut frame_interval = FRAME_DURATION;
+        let mut ticker = tokio::time::interval(frame_interval);
+        let started = Instant::now();
+        let mut producing = true;
         
-        info!("✅ Audio samples prepared and ready for transmission");
+        loop {
+            let next_due = self.call_impairments.lock().await.get(call_id).and_then(NetworkImpairment::next_due);
+            if !producing && next_due.is_none() {
+                break;
+            }
+            
+            let mut produced = None;
+            tokio::select! {
+                _ = ticker.tick(), if producing => {
+                    // Holding either way pauses the prompt where it is
+                    if !self.prompt_may_play(call_id).await {
+                        continue;
+                    }
+                    if cursor.next_frame(&mut frame, frame_len).await? {
+                        produced = Some(AudioFrame::new(frame.clone(), sample_rate, 1, timestamp));
+                        timestamp = timestamp.wrapping_add(frame.len() as u32);
+                    } else {
+                        producing = false;
+                    }
+                }
+                _ = tokio::time::sleep_until(started + next_due.unwrap_or_default()), if next_due.is_some() => {}
+            }
+            
+            let now = started.elapsed();
+            let outgoing = match self.call_impairments.lock().await.get_mut(call_id) {
+                Some(impairment) => {
+                    if let Some(produced) = produced {
+                        impairment.push(produced, now);
+                    }
+                    
+                    // Clock drift may be changed while the prompt plays
+                    let interval = impairment.frame_interval(FRAME_DURATION);
+                    if interval != frame_interval {
+                        frame_interval = interval;
+                        ticker = tokio::time::interval_at(Instant::now() + interval, interval);
+                    }
+                    impairment.due(now)
+                }
+                None => produced.into_iter().collect(),
+            };
+            
+            for audio_frame in outgoing {
+                client.send_audio_frame(call_id, audio_frame).await
+                    .context("Failed to send audio frame")?;
+            }
+        }
+        
+        info!("✅ Prompt playback finished for call {}", call_id);
         Ok(())
     }
 
-    /// Start custom audio transmission using pre-converted μ-law samples
-    async fn start_custom_audio_transmission(&self, call_id: &CallId) -> Result<(), anyhow::Error> {
-        info!("🎵 Starting custom audio transmission for call {}", call_id);
+    /// Whether prompts may be sent: the caller is not held by us
+    async fn prompt_may_play(&self, call_id: &CallId) -> bool {
+        self.call_holds.lock().await.get(call_id)
+            .is_none_or(|hold| !hold.local)
+    }
+
+    /// Put the caller on hold after `after`, and take them off it again after `duration`
+    async fn schedule_hold(&self, call_id: CallId, after: Duration, duration: Option<Duration>) {
+        let handler = self.clone();
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(after).await;
+            if let Err(e) = handler.set_local_hold(call_id, true).await {
+                error!("❌ Failed to put call {} on hold: {:#}", call_id, e);
+                return;
+            }
+            if let Some(duration) = duration {
+                tokio::time::sleep(duration).await;
+                if let Err(e) = handler.set_local_hold(call_id, false).await {
+                    error!("❌ Failed to resume call {}: {:#}", call_id, e);
+                }
+            }
+        });
+        
+        if let Some(hold) = self.call_holds.lock().await.get_mut(&call_id) {
+            hold.tasks.push(task);
+        }
+    }
+
+    /// Send a re-INVITE holding (sendonly) or resuming the caller
+    async fn set_local_hold(&self, call_id: CallId, held: bool) -> Result<(), anyhow::Error> {
+        let client = self.client_manager.read().await.clone()
+            .context("Client manager not available")?;
         
-        // Get the pre-converted audio samples
-        let samples = {
-            let audio_samples_guard = self.audio_samples.lock().await;
-            match audio_samples_guard.as_ref() {
-                Some(samples) => samples.clone(),
-                None => {
-                    anyhow::bail!("Audio samples not prepared. Call prepare_audio_samples() first.");
+        if held {
+            client.hold_call(&call_id).await.context("Hold re-INVITE failed")?;
+            info!("⏸️ Call {} put on hold", call_id);
+        } else {
+            client.resume_call(&call_id).await.context("Resume re-INVITE failed")?;
+            info!("▶️ Call {} taken off hold", call_id);
+        }
+        
+        let mut holds = self.call_holds.lock().await;
+        let Some(hold) = holds.get_mut(&call_id) else {
+            return Ok(());
+        };
+        hold.local = held;
+        hold.update_tracker();
+        
+        if let Some(path) = self.server_config.hold.music_file.clone().filter(|_| held) {
+            let handler = self.clone();
+            hold.tasks.push(tokio::spawn(async move {
+                if let Err(e) = handler.play_music_on_hold(call_id, &path).await {
+                    error!("❌ Music on hold failed on call {}: {:#}", call_id, e);
                 }
+            }));
+        }
+        Ok(())
+    }
+
+    /// Loop the music on hold file to the caller for as long as the server holds them
+    async fn play_music_on_hold(&self, call_id: CallId, path: &str) -> Result<(), anyhow::Error> {
+        let client = self.client_manager.read().await.clone()
+            .context("Client manager not available")?;
+        let (path, max_buffered_seconds) = (path.to_string(), self.server_config.media.max_buffered_prompt_seconds);
+        let music = tokio::task::spawn_blocking(move || PromptAudio::open_wav(path, max_buffered_seconds)).await??;
+        info!("🎶 Playing music on hold to call {}", call_id);
+        
+        let sample_rate = music.sample_rate();
+        let frame_len = (sample_rate as u64 * FRAME_DURATION.as_millis() as u64 / 1000) as usize;
+        let mut cursor = music.cursor()?;
+        let mut frame = Vec::with_capacity(frame_len);
+        let mut timestamp: u32 = 0;
+        let mut ticker = tokio::time::interval(FRAME_DURATION);
+        
+        loop {
+            ticker.tick().await;
+            if !self.call_holds.lock().await.get(&call_id).is_some_and(|hold| hold.local) {
+                break;
+            }
+            
+            if !cursor.next_frame(&mut frame, frame_len).await? {
+                cursor = music.cursor()?;
+                continue;
             }
+            client.send_audio_frame(&call_id, AudioFrame::new(frame.clone(), sample_rate, 1, timestamp)).await
+                .context("Failed to send audio frame")?;
+            timestamp = timestamp.wrapping_add(frame.len() as u32);
+        }
+        Ok(())
+    }
+
+    /// Apply network impairments to the audio sent on a call, replacing any set before
+    async fn set_impairment(&self, call_id: &CallId, config: &ImpairmentConfig) {
+        info!("📉 Impairing audio to call {}: loss {}% (burst enter {}% exit {}% loss {}%), jitter {}ms, drift {}ppm, seed {}",
+              call_id, config.loss_percent, config.burst_enter_percent, config.burst_exit_percent,
+              config.burst_loss_percent, config.jitter_ms,
+              config.clock_drift_ppm, config.seed);
+        
+        let mut impairments = self.call_impairments.lock().await;
+        match impairments.get_mut(call_id) {
+            Some(impairment) => impairment.reconfigure(config),
+            None => {
+                impairments.insert(*call_id, NetworkImpairment::new(config));
+            }
+        }
+    }
+
+    /// Stop impairing a call's audio; frames already delayed are still sent
+    async fn clear_impairment(&self, call_id: &CallId) {
+        if let Some(impairment) = self.call_impairments.lock().await.get_mut(call_id) {
+            info!("📉 Impairments cleared on call {}", call_id);
+            let seed = impairment.config().seed;
+            impairment.reconfigure(&ImpairmentConfig { seed, ..ImpairmentConfig::default() });
+        }
+    }
+
+    /// Response to PUT (with an impairment JSON body) or DELETE of a call's impairments
+    async fn impairment_request(&self, method: &str, call_id: &str, body: Option<&[u8]>) -> String {
+        let Some(call_id) = self.find_active_call(call_id).await else {
+            return "HTTP/1.1 404 Not Found\r\n\r\n".to_string();
         };
         
-        info!("📡 Using {} pre-converted μ-law samples for call {}", samples.len(), call_id);
+        if method == "DELETE" {
+            self.clear_impairment(&call_id).await;
+            return "HTTP/1.1 204 No Content\r\n\r\n".to_string();
+        }
         
-        // Use the new rvoip API to start custom audio transmission
-        if let Some(client) = self.client_manager.read().await.as_ref() {
-            client.start_audio_transmission_with_custom_audio(call_id, samples, false).await
-                .context("Failed to start custom audio transmission")?;
+        let config = serde_json::from_slice::<ImpairmentConfig>(body.unwrap_or_default())
+            .map_err(anyhow::Error::from)
+            .and_then(|config| config.validate().map(|()| config));
+        match config {
+            Ok(config) => {
+                self.set_impairment(&call_id, &config).await;
+                "HTTP/1.1 204 No Content\r\n\r\n".to_string()
+            }
+            Err(e) => {
+                let message = format!("{:#}", e);
+                format!("HTTP/1.1 400 Bad Request\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+                        message.len(), message)
+            }
+        }
+    }
+
+    /// Connected call with the given ID
+    async fn find_active_call(&self, call_id: &str) -> Option<CallId> {
+        self.active_calls.lock().await.keys()
+            .find(|id| id.to_string() == call_id)
+            .copied()
+    }
+
+    /// Fall back to generated or pass-through audio when the prompt cannot be played
+    async fn start_fallback_audio(&self, call_id: &CallId) {
+        let Some(client) = self.client_manager.read().await.clone() else {
+            return;
+        };
+        
+        info!("🔄 Attempting fallback to tone generation...");
+        match client.start_audio_transmission_with_tone(call_id).await {
+            Ok(_) => info!("✅ Fallback tone generation started for call {}", call_id),
+            Err(e2) => {
+                error!("❌ Fallback tone generation also failed: {}", e2);
                 
-            info!("✅ Custom audio transmission started successfully for call {}", call_id);
+                // Final fallback: try normal pass-through mode  
+                info!("🔄 Attempting final fallback to pass-through mode...");
+                match client.start_audio_transmission(call_id).await {
+                    Ok(_) => info!("✅ Pass-through audio transmission started for call {}", call_id),
+                    Err(e3) => error!("❌ All audio transmission methods failed for call {}: {}", call_id, e3),
+                }
+            }
+        }
+    }
+
+    /// Hang up the call right away
+    async fn hangup(&self, call_id: &CallId) {
+        if let Some(client) = self.client_manager.read().await.as_ref() {
+            match client.hangup_call(call_id).await {
+                Ok(_) => info!("✅ Call {} hung up successfully", call_id),
+                Err(e) => error!("❌ Failed to hang up call {}: {}", call_id, e),
+            }
+        }
+    }
+
+    /// Feed the caller's audio through the `[inbound_processing]` chain, the analyser and
+    /// the loopback recorder until the call's media ends
+    async fn start_inbound_processing(&self, client: &ClientManager, call_id: CallId, loopback: bool) {
+        let mut subscriber = match client.subscribe_to_audio_frames(&call_id).await {
+            Ok(subscriber) => subscriber,
+            Err(e) => {
+                warn!("⚠️ Failed to subscribe to inbound audio for call {}: {}", call_id, e);
+                return;
+            }
+        };
+        
+        info!("🎙️ Processing inbound audio for call {}", call_id);
+        let inbound = &self.server_config.inbound_processing;
+        let mut pipeline = InboundAudioPipeline::new(inbound.enabled.then(|| inbound.profile.clone()));
+        
+        if self.server_config.inbound_analysis.enabled {
+            let analyzer = SharedAnalyzer::new(self.server_config.inbound_analysis.clone());
+            self.call_analyses.lock().await.insert(call_id, analyzer.clone());
+            pipeline.add_sink(Box::new(analyzer));
+        }
+        
+        if loopback {
+            let recorder = LoopbackRecorder::default();
+            self.call_recorders.lock().await.insert(call_id, recorder.clone());
+            pipeline.add_sink(Box::new(recorder));
+        }
+        
+        // The subscriber closes when the call's media stops
+        let task = tokio::spawn(async move {
+            while let Some(frame) = subscriber.recv().await {
+                pipeline.process_frame(&frame.samples, frame.sample_rate, frame.channels);
+            }
+            pipeline.finish();
             
-            // Schedule call hangup after MP3 duration (30 seconds)
-            let call_id = call_id.clone();
-            let client_ref = Arc::clone(&self.client_manager);
-            tokio::spawn(async move {
-                tokio::time::sleep(Duration::from_secs(30)).await;
-                
-                if let Some(client) = client_ref.read().await.as_ref() {
-                    info!("📴 Hanging up call {} after MP3 completion", call_id);
-                    match client.hangup_call(&call_id).await {
-                        Ok(_) => info!("✅ Call {} hung up successfully after MP3 playback", call_id),
-                        Err(e) => error!("❌ Failed to hang up call {}: {}", call_id, e),
+            if let Some(stats) = pipeline.stats() {
+                info!("🎙️ Inbound audio for call {}: {} frames, input peak {:.1}dBFS RMS {:.1}dBFS, processed peak {:.1}dBFS RMS {:.1}dBFS",
+                      call_id, pipeline.frames(), stats.input.peak_dbfs(), stats.input.rms_dbfs(),
+                      stats.output.peak_dbfs(), stats.output.rms_dbfs());
+            }
+        });
+        self.inbound_tasks.lock().await.insert(call_id, task);
+    }
+
+    /// Poll the call's RTP session statistics until the call ends
+    async fn start_rtp_monitor(&self, client: Arc<ClientManager>, call_id: CallId) {
+        let collector = Arc::new(Mutex::new(RtpStatsCollector::new()));
+        let interval = Duration::from_millis(self.server_config.rtp_stats.poll_interval_ms);
+        
+        let task_collector = collector.clone();
+        let task = tokio::spawn(async move {
+            let started = Instant::now();
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Some(sample) = poll_rtp_sample(&client, &call_id).await {
+                    task_collector.lock().await.observe(&sample, started.elapsed());
+                }
+            }
+        });
+        
+        if let Some(previous) = self.rtp_monitors.lock().await.insert(call_id, RtpMonitor { collector, task }) {
+            previous.task.abort();
+        }
+    }
+
+    /// Analysis of a call in progress or recently ended, by call ID
+    async fn call_analysis(&self, call_id: &str) -> Option<AudioAnalysis> {
+        if let Some(analyzer) = self.call_analyses.lock().await.iter()
+            .find(|(id, _)| id.to_string() == call_id)
+            .map(|(_, analyzer)| analyzer.clone())
+        {
+            return Some(analyzer.snapshot());
+        }
+        
+        self.finished_analyses.lock().await.iter()
+            .find(|(id, _)| id.to_string() == call_id)
+            .map(|(_, analysis)| analysis.clone())
+    }
+
+    /// Prometheus text exposition of the call counters and RTP quality histograms
+    async fn render_metrics(&self) -> String {
+        let mut out = String::new();
+        {
+            let stats = self.call_stats.lock().await;
+            out.push_str(&format!(
+                "# HELP sip_server_active_calls Calls in progress\n\
+                 # TYPE sip_server_active_calls gauge\n\
+                 sip_server_active_calls {}\n\
+                 # HELP sip_server_calls_total Calls received\n\
+                 # TYPE sip_server_calls_total counter\n\
+                 sip_server_calls_total {}\n\
+                 # HELP sip_server_failed_calls_total Calls that failed\n\
+                 # TYPE sip_server_failed_calls_total counter\n\
+                 sip_server_failed_calls_total {}\n",
+                stats.active_calls, stats.total_calls, stats.failed_calls
+            ));
+        }
+        self.quality_metrics.lock().await.render(&mut out);
+        out
+    }
+
+    /// Wait for the call's inbound audio to drain, keep its analysis and write its CDR
+    async fn complete_call(&self, call_id: CallId) {
+        let ended_at = Utc::now();
+        
+        let inbound_task = self.inbound_tasks.lock().await.remove(&call_id);
+        if let Some(task) = inbound_task {
+            if tokio::time::timeout(INBOUND_DRAIN_TIMEOUT, task).await.is_err() {
+                warn!("⚠️ Inbound audio for call {} still open {:?} after hangup", call_id, INBOUND_DRAIN_TIMEOUT);
+            }
+        }
+        
+        self.call_recorders.lock().await.remove(&call_id);
+        
+        let (hold_count, hold_time) = match self.call_holds.lock().await.remove(&call_id) {
+            Some(hold) => {
+                hold.tasks.iter().for_each(JoinHandle::abort);
+                hold.tracker.summary(hold.connected.elapsed())
+            }
+            None => (0, Duration::ZERO),
+        };
+        if hold_count > 0 {
+            info!("⏸️ Call {} was on hold {} times for {:.1}s", call_id, hold_count, hold_time.as_secs_f64());
+        }
+        
+        let impairment = self.call_impairments.lock().await.remove(&call_id).map(|impairment| impairment.stats().clone());
+        if let Some(stats) = &impairment {
+            info!("📉 Impairments on call {} (seed {}): {} frames, {} lost, max added delay {}ms",
+                  call_id, stats.seed, stats.frames, stats.lost, stats.max_delay_ms);
+        }
+        
+        let monitor = self.rtp_monitors.lock().await.remove(&call_id);
+        let rtp = match monitor {
+            Some(monitor) => {
+                monitor.task.abort();
+                let stats = monitor.collector.lock().await.summary();
+                info!("📶 RTP for call {}: sent {}, received {}, lost {} ({:.2}%), out of order {}, jitter {:.1}ms mean / {:.1}ms max, max gap {}ms, RTT {}",
+                      call_id, stats.packets_sent, stats.packets_received, stats.packets_lost, stats.loss_ratio * 100.0,
+                      stats.packets_out_of_order, stats.jitter_ms_mean, stats.jitter_ms_max, stats.max_gap_ms,
+                      stats.rtt_ms_mean.map_or("n/a".to_string(), |rtt| format!("{:.1}ms", rtt)));
+                self.quality_metrics.lock().await.observe_call(&stats);
+                Some(stats)
+            }
+            None => None,
+        };
+        
+        let analysis = self.call_analyses.lock().await.remove(&call_id).map(|analyzer| analyzer.snapshot());
+        if let Some(analysis) = &analysis {
+            let tones: Vec<String> = analysis.tones.iter().map(|tone| format!("{:.0}Hz", tone.frequency_hz)).collect();
+            info!("🔬 Inbound analysis for call {}: peak {:.1}dBFS RMS {:.1}dBFS, {} clipped samples, {:.0}% silence, tones [{}]",
+                  call_id, analysis.peak_dbfs, analysis.rms_dbfs, analysis.clipped_samples,
+                  analysis.silence_ratio * 100.0, tones.join(", "));
+            
+            let mut finished = self.finished_analyses.lock().await;
+            if finished.len() == RECENT_ANALYSES {
+                finished.pop_front();
+            }
+            finished.push_back((call_id, analysis.clone()));
+        }
+        
+        let Some(mut record) = self.call_records.lock().await.remove(&call_id) else {
+            return;
+        };
+        record.ended(ended_at);
+        record.rtp = rtp;
+        record.impairment = impairment;
+        record.hold_count = hold_count;
+        record.hold_seconds = hold_time.as_secs_f64();
+        record.inbound_analysis = analysis;
+        
+        if let Some(writer) = self.cdr_writer.clone() {
+            match tokio::task::spawn_blocking(move || writer.append(&record)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("❌ Failed to write CDR for call {}: {:#}", call_id, e),
+                Err(e) => error!("❌ CDR writer task failed for call {}: {}", call_id, e),
+            }
+        }
+    }
+
+    /// Track the task driving a call, cancelling any previous one
+    async fn set_call_task(&self, call_id: CallId, task: JoinHandle<()>) {
+        if let Some(previous) = self.call_tasks.lock().await.insert(call_id, task) {
+            previous.abort();
+        }
+    }
+
+    /// Execute the actions of a dialplan rule in order
+    async fn run_dialplan(&self, call_id: CallId, rule: DialplanRule) {
+        info!("📋 Running dialplan rule '{}' for call {}", rule.name, call_id);
+
+        for action in &rule.actions {
+            let result = match action {
+                DialplanAction::PlayAudio => self.play_mp3_prompt(&call_id).await,
+                DialplanAction::SendDtmf { digits, dtmf, .. } => {
+                    self.send_dtmf_sequence(&call_id, digits, dtmf).await
+                }
+                DialplanAction::LoopbackTest(test) => self.run_loopback_test(&call_id, test).await,
+                DialplanAction::Hold { after_ms, duration_ms } => {
+                    self.schedule_hold(call_id, Duration::from_millis(*after_ms), duration_ms.map(Duration::from_millis)).await;
+                    Ok(())
+                }
+                DialplanAction::Transfer(transfer) => match self.transfer_caller(call_id, transfer).await {
+                    Ok(()) => return,
+                    Err(e) => Err(e),
+                },
+                DialplanAction::Wait { duration_ms } => {
+                    tokio::time::sleep(Duration::from_millis(*duration_ms)).await;
+                    Ok(())
+                }
+                DialplanAction::Hangup => {
+                    info!("📴 Dialplan hanging up call {}", call_id);
+                    self.hangup(&call_id).await;
+                    return;
+                }
+            };
+
+            if let Err(e) = result {
+                error!("❌ Dialplan rule '{}' failed on call {}: {}", rule.name, call_id, e);
+                return;
+            }
+        }
+    }
+
+    /// Our URI for calls the server places
+    fn local_uri(&self) -> String {
+        format!("sip:server@{}", self.server_config.sip.domain)
+    }
+
+    /// Refer the caller to the action's target and wait for them to leave the call
+    ///
+    /// The client API reports no NOTIFY progress, so the rule ends once the caller
+    /// ends the call. A caller who hangs up instead of following the REFER cannot be
+    /// told apart, so `on_call_state_changed` records the outcome as unconfirmed.
+    async fn transfer_caller(&self, call_id: CallId, transfer: &TransferActionConfig) -> Result<(), anyhow::Error> {
+        let client = self.client_manager.read().await.clone()
+            .context("Client manager not available")?;
+        
+        info!("🔀 Transferring call {} to {}", call_id, transfer.target);
+        self.record_transfer(call_id, &transfer.target, "REFER sent".to_string(), None).await;
+        let (sender, ended) = oneshot::channel();
+        self.pending_transfers.lock().await.insert(call_id, sender);
+        
+        if let Err(e) = client.transfer_call(&call_id, &transfer.target).await {
+            self.pending_transfers.lock().await.remove(&call_id);
+            self.record_transfer(call_id, &transfer.target, format!("REFER could not be sent: {}", e), Some(false)).await;
+            return Err(anyhow::Error::new(e).context(format!("Transfer to {} failed", transfer.target)));
+        }
+        
+        let timeout = Duration::from_millis(self.server_config.transfer.timeout_ms);
+        if tokio::time::timeout(timeout, ended).await.is_ok() {
+            return Ok(());
+        }
+        self.pending_transfers.lock().await.remove(&call_id);
+        let outcome = format!("Caller still on the call {:?} after the REFER", timeout);
+        self.record_transfer(call_id, &transfer.target, outcome.clone(), Some(false)).await;
+        Err(anyhow::anyhow!("Transfer to {} failed: {}", transfer.target, outcome))
+    }
+
+    /// Follow the progress of a call the server placed; it is also handled like an
+    /// incoming call from here on, running `rule` once answered
+    async fn track_outbound_call(&self, call_id: CallId, from: &str, target: &str,
+                                 rule: Option<DialplanRule>) -> mpsc::UnboundedReceiver<CallStatusInfo> {
+        let (sender, states) = mpsc::unbounded_channel();
+        self.outbound_calls.lock().await.insert(call_id, OutboundCall { states: sender });
+        
+        {
+            let mut stats = self.call_stats.lock().await;
+            stats.total_calls += 1;
+            stats.active_calls += 1;
+        }
+        self.active_calls.lock().await.insert(call_id, Instant::now());
+        let mut record = CallDetailRecord::new(call_id.to_string(), from, target);
+        if let Some(rule) = rule {
+            record.dialplan_rule = Some(rule.name.clone());
+            self.call_rules.lock().await.insert(call_id, rule);
+        }
+        self.call_records.lock().await.insert(call_id, record);
+        states
+    }
+
+    /// Place the configured number of calls at the configured rate, keeping at most
+    /// `max_concurrent` up at once, and wait for all of them to end
+    async fn originate(&self, request: OriginateConfig) -> Result<OriginationSummary, anyhow::Error> {
+        let client = self.client_manager.read().await.clone()
+            .context("Client manager not available")?;
+        let rule = self.originate_rule(&request)?;
+        
+        info!("📤 Calling {} {} times at {}/s, at most {} at once", request.target, request.calls,
+              request.calls_per_second, request.max_concurrent);
+        let started = Instant::now();
+        let slots = Arc::new(tokio::sync::Semaphore::new(request.max_concurrent as usize));
+        let mut pacing = tokio::time::interval(Duration::from_secs_f64(1.0 / request.calls_per_second));
+        let mut calls = tokio::task::JoinSet::new();
+        
+        for _ in 0..request.calls {
+            pacing.tick().await;
+            let slot = slots.clone().acquire_owned().await?;
+            let handler = self.clone();
+            let client = client.clone();
+            let request = request.clone();
+            let rule = rule.clone();
+            calls.spawn(async move {
+                let outcome = handler.originate_call(&client, &request, rule).await;
+                drop(slot);
+                outcome
+            });
+        }
+        
+        let mut summary = OriginationSummary::default();
+        while let Some(outcome) = calls.join_next().await {
+            match outcome {
+                Ok(outcome) => summary.record(&outcome),
+                Err(e) => summary.record(&CallOutcome::failed(format!("Call task failed: {}", e))),
+            }
+        }
+        info!("📤 Origination to {} finished in {:.1}s: {} placed, {} answered, {} failed",
+              request.target, started.elapsed().as_secs_f64(), summary.placed, summary.answered, summary.failed);
+        for (reason, count) in &summary.failures {
+            info!("   ❌ {}: {}", reason, count);
+        }
+        Ok(summary)
+    }
+
+    /// Dialplan rule originated calls run
+    fn originate_rule(&self, request: &OriginateConfig) -> Result<Option<DialplanRule>, anyhow::Error> {
+        request.rule.as_ref()
+            .map(|name| self.server_config.dialplan.rule_named(name).cloned()
+                .with_context(|| format!("No dialplan rule named '{}'", name)))
+            .transpose()
+    }
+
+    /// Place calls following a load profile's rate and concurrency ramps, then report on them
+    async fn run_load(&self, request: OriginateConfig, profile: LoadProfile) -> Result<LoadReport, anyhow::Error> {
+        let client = self.client_manager.read().await.clone()
+            .context("Client manager not available")?;
+        let rule = self.originate_rule(&request)?;
+        
+        info!("📈 Load test against {} for {:?}", request.target, profile.duration());
+        let started = Instant::now();
+        let mut ticker = tokio::time::interval(LOAD_TICK);
+        let mut last_progress = started;
+        let mut calls = tokio::task::JoinSet::new();
+        let mut outcomes = Vec::new();
+        let (mut attempted, mut skipped, mut peak) = (0, 0, 0);
+        
+        while started.elapsed() < profile.duration() {
+            ticker.tick().await;
+            while let Some(outcome) = calls.try_join_next() {
+                outcomes.push(outcome.unwrap_or_else(|e| CallOutcome::failed(format!("Call task failed: {}", e))));
+            }
+            
+            let elapsed = started.elapsed();
+            let limit = profile.max_concurrent(elapsed);
+            let due = profile.calls_due(elapsed);
+            while attempted < due {
+                attempted += 1;
+                if calls.len() >= limit as usize {
+                    skipped += 1;
+                    continue;
+                }
+                let handler = self.clone();
+                let client = client.clone();
+                let request = request.clone();
+                let rule = rule.clone();
+                calls.spawn(async move { handler.originate_call(&client, &request, rule).await });
+            }
+            peak = peak.max(calls.len() as u32);
+            
+            if last_progress.elapsed() >= LOAD_PROGRESS_INTERVAL {
+                last_progress = Instant::now();
+                info!("📈 {:.0}s: {:.1} calls/s, {} up (limit {}), {} ended, {} skipped",
+                      elapsed.as_secs_f64(), profile.calls_per_second(elapsed), calls.len(), limit, outcomes.len(), skipped);
+            }
+        }
+        
+        info!("📈 Load profile done, waiting for {} calls to end", calls.len());
+        while let Some(outcome) = calls.join_next().await {
+            outcomes.push(outcome.unwrap_or_else(|e| CallOutcome::failed(format!("Call task failed: {}", e))));
+        }
+        
+        let report = LoadReport::new(&outcomes, started.elapsed(), skipped, peak);
+        info!("📈 Load test finished: {} attempts, {} answered (ASR {:.1}%), {} skipped at the concurrency limit",
+              report.attempts, report.answered, report.asr_percent, report.skipped_at_limit);
+        let pdd = &report.post_dial_delay_ms;
+        info!("   ⏱️ Post-dial delay: p50 {:.0}ms, p90 {:.0}ms, p95 {:.0}ms, p99 {:.0}ms, max {:.0}ms",
+              pdd.p50, pdd.p90, pdd.p95, pdd.p99, pdd.max);
+        for (status, count) in &report.failures {
+            info!("   ❌ {}: {}", status, count);
+        }
+        Ok(report)
+    }
+
+    /// Place one call and follow it until it ends
+    async fn originate_call(&self, client: &ClientManager, request: &OriginateConfig, rule: Option<DialplanRule>) -> CallOutcome {
+        let from = request.from.clone().unwrap_or_else(|| self.local_uri());
+        let dialled = Instant::now();
+        let call_id = match client.make_call(from.clone(), request.target.clone(), None).await {
+            Ok(call_id) => call_id,
+            Err(e) => return CallOutcome::failed(format!("INVITE not sent: {}", e)),
+        };
+        let mut states = self.track_outbound_call(call_id, &from, &request.target, rule).await;
+        
+        let mut outcome = CallOutcome::default();
+        let mut answered_at = None;
+        let max_duration = (request.max_duration_ms > 0).then(|| Duration::from_millis(request.max_duration_ms));
+        let mut deadline = Some(dialled + Duration::from_millis(request.answer_timeout_ms));
+        let mut hung_up = false;
+        loop {
+            let status = tokio::select! {
+                status = states.recv() => status,
+                _ = tokio::time::sleep_until(deadline.unwrap_or(dialled)), if deadline.is_some() => {
+                    if hung_up {
+                        warn!("⚠️ Originated call {} did not end within {:?} of hanging up", call_id, HANGUP_TIMEOUT);
+                        outcome.failure.get_or_insert_with(|| "No termination after hangup".to_string());
+                        break;
+                    }
+                    hung_up = true;
+                    deadline = Some(Instant::now() + HANGUP_TIMEOUT);
+                    if answered_at.is_some() {
+                        info!("⏱️ Hanging up originated call {} after {:?}", call_id, max_duration.unwrap_or_default());
+                    } else {
+                        outcome.failure = Some("No answer".to_string());
                     }
+                    self.hangup(&call_id).await;
+                    continue;
                 }
+            };
+            
+            let Some(status) = status else { break };
+            if matches!(status.new_state, CallState::Ringing | CallState::Connected) {
+                outcome.post_dial_delay.get_or_insert_with(|| dialled.elapsed());
+            }
+            match status.new_state {
+                CallState::Connected if answered_at.is_none() => {
+                    let now = Instant::now();
+                    answered_at = Some(now);
+                    outcome.answer_time = Some(now - dialled);
+                    if !hung_up {
+                        deadline = max_duration.map(|duration| now + duration);
+                    }
+                }
+                CallState::Failed | CallState::Cancelled => {
+                    outcome.failure.get_or_insert_with(|| failure_reason(&status));
+                    break;
+                }
+                CallState::Terminated => break,
+                _ => {}
+            }
+        }
+        
+        self.outbound_calls.lock().await.remove(&call_id);
+        outcome.talk_time = answered_at.map_or(Duration::ZERO, |answered_at| answered_at.elapsed());
+        outcome
+    }
+
+    /// Response to POST /originate, whose JSON body overrides fields of `[originate]`;
+    /// returns once every call has ended
+    async fn originate_request(&self, body: Option<&[u8]>) -> String {
+        let request = match body.filter(|body| !body.is_empty()) {
+            Some(body) => serde_json::from_slice(body).map_err(anyhow::Error::from),
+            None => Ok(serde_json::Value::Object(Default::default())),
+        }
+        .and_then(|overrides| self.server_config.originate.with_overrides(&overrides))
+        .and_then(|request| self.server_config.validate_originate(&request).map(|()| request));
+        
+        let summary = match request {
+            Ok(request) => self.originate(request).await,
+            Err(e) => {
+                let message = format!("{:#}", e);
+                return format!("HTTP/1.1 400 Bad Request\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+                               message.len(), message);
+            }
+        };
+        match summary.and_then(|summary| serde_json::to_string(&summary).map_err(Into::into)) {
+            Ok(body) => json_response(&body),
+            Err(e) => {
+                error!("❌ Origination failed: {:#}", e);
+                "HTTP/1.1 500 Internal Server Error\r\n\r\n".to_string()
+            }
+        }
+    }
+
+    async fn record_transfer(&self, call_id: CallId, target: &str, outcome: String, succeeded: Option<bool>) {
+        if let Some(record) = self.call_records.lock().await.get_mut(&call_id) {
+            record.transfer = Some(TransferRecord {
+                target: target.to_string(),
+                outcome,
+                succeeded,
             });
-        } else {
-            anyhow::bail!("Client manager not available");
+        }
+    }
+
+    /// Play a reference signal, record what the far end returns and score the round trip
+    async fn run_loopback_test(&self, call_id: &CallId, test: &LoopbackTestConfig) -> Result<(), anyhow::Error> {
+        let recorder = self.call_recorders.lock().await.get(call_id).cloned()
+            .context("No inbound audio recorder for the call")?;
+        
+        let sample_rate = self.server_config.media.audio_sample_rate;
+        let reference = loopback::reference_signal(test, sample_rate)?;
+        let pcm: Vec<i16> = reference.iter().map(|&s| (s * 32767.0).clamp(-32768.0, 32767.0) as i16).collect();
+        let prompt = PromptAudio::from_pcm(pcm, sample_rate);
+        
+        info!("🔁 Loopback test on call {}: playing {:?} reference", call_id, prompt.duration());
+        recorder.start();
+        let played = self.play_prompt(call_id, &prompt).await;
+        if played.is_ok() {
+            // Leave time for the tail of the reference to come back
+            tokio::time::sleep(Duration::from_millis(test.max_delay_ms)).await;
+        }
+        let (recorded, recorded_rate) = recorder.stop();
+        played.context("Failed to play loopback reference")?;
+        
+        if recorded.is_empty() {
+            return Err(anyhow::anyhow!("No audio received during the loopback test"));
         }
         
+        let max_delay = (test.max_delay_ms * sample_rate as u64 / 1000) as usize;
+        let score = tokio::task::spawn_blocking(move || {
+            let recorded = loopback::resample(&recorded, recorded_rate, sample_rate);
+            loopback::compare(&reference, &recorded, sample_rate, max_delay)
+        }).await.context("Loopback scoring task failed")??;
+        
+        info!("🔁 Loopback result for call {}: delay {:.1}ms, gain {:.1}dB, SNR {:.1}dB, response deviation {:.1}dB max / {:.1}dB RMS, R {:.1}, MOS {:.2}",
+              call_id, score.delay_ms, score.gain_db, score.snr_db, score.max_deviation_db,
+              score.rms_deviation_db, score.r_factor, score.mos);
+        
+        if let Some(record) = self.call_records.lock().await.get_mut(call_id) {
+            record.loopback_tests.push(score);
+        }
+        Ok(())
+    }
+
+    /// Send a scripted DTMF string to the caller as in-band tones
+    async fn send_dtmf_sequence(
+        &self,
+        call_id: &CallId,
+        digits: &str,
+        dtmf: &DtmfConfig,
+    ) -> Result<(), anyhow::Error> {
+        info!("🔢 Sending DTMF '{}' to call {}", digits, call_id);
+
+        let pcm_samples = self.tone_generator.generate_dtmf_sequence_with_config(digits, dtmf).await?;
+        let tones = PromptAudio::from_pcm(pcm_samples, self.server_config.media.audio_sample_rate);
+        self.play_prompt(call_id, &tones).await
+            .context("Failed to play in-band DTMF")?;
+
+        info!("✅ DTMF '{}' sent to call {}", digits, call_id);
         Ok(())
     }
 }
@@ -153,6 +1029,14 @@ impl ClientEventHandler for AutoAnswerHandler {
             active_calls.insert(call_info.call_id, Instant::now());
         }
         
+        let mut record = CallDetailRecord::new(call_info.call_id.to_string(), &call_info.caller_uri, &call_info.callee_uri);
+        if let Some(rule) = self.server_config.dialplan.find_rule(&call_info.callee_uri) {
+            info!("📋 Call {} matched dialplan rule '{}'", call_info.call_id, rule.name);
+            record.dialplan_rule = Some(rule.name.clone());
+            self.call_rules.lock().await.insert(call_info.call_id, rule.clone());
+        }
+        self.call_records.lock().await.insert(call_info.call_id, record);
+        
         // Auto-answer if enabled
         if self.server_config.behavior.auto_answer {
             info!("⏱️ Auto-answering call {} in {}ms", call_info.call_id, self.server_config.behavior.auto_answer_delay_ms);
@@ -194,9 +1078,18 @@ impl ClientEventHandler for AutoAnswerHandler {
         info!("📱 Call {} state changed to {:?} {}", 
               status_info.call_id, status_info.new_state, state_icon);
 
+        // Calls the server placed report to the task following them
+        if let Some(call) = self.outbound_calls.lock().await.get(&status_info.call_id) {
+            let _ = call.states.send(status_info.clone());
+        }
+
         if status_info.new_state == CallState::Connected {
             info!("🎉 Call {} connected! Starting audio session...", status_info.call_id);
             
+            if let Some(record) = self.call_records.lock().await.get_mut(&status_info.call_id) {
+                record.answered(Utc::now());
+            }
+            
             // Get media info
             if let Some(client) = self.client_manager.read().await.as_ref() {
                 if let Ok(media_info) = client.get_call_media_info(&status_info.call_id).await {
@@ -204,48 +1097,82 @@ impl ClientEventHandler for AutoAnswerHandler {
                         status_info.call_id, media_info.local_rtp_port, media_info.remote_rtp_port, media_info.codec);
                 }
                 
-                // Start custom MP3 audio transmission
-                match self.start_custom_audio_transmission(&status_info.call_id).await {
-                    Ok(_) => {
-                        info!("✅ Started custom MP3 audio transmission for call {}", status_info.call_id);
-                    }
-                    Err(e) => {
-                        error!("❌ Failed to start custom audio transmission: {}", e);
-                        
-                        // Fallback: try tone generation for testing
-                        info!("🔄 Attempting fallback to tone generation...");
-                        match client.start_audio_transmission_with_tone(&status_info.call_id).await {
-                            Ok(_) => info!("✅ Fallback tone generation started for call {}", status_info.call_id),
-                            Err(e2) => {
-                                error!("❌ Fallback tone generation also failed: {}", e2);
-                                
-                                // Final fallback: try normal pass-through mode  
-                                info!("🔄 Attempting final fallback to pass-through mode...");
-                                match client.start_audio_transmission(&status_info.call_id).await {
-                                    Ok(_) => info!("✅ Pass-through audio transmission started for call {}", status_info.call_id),
-                                    Err(e3) => error!("❌ All audio transmission methods failed for call {}: {}", status_info.call_id, e3),
-                                }
+                let call_id = status_info.call_id;
+                if self.server_config.rtp_stats.enabled {
+                    self.start_rtp_monitor(client.clone(), call_id).await;
+                }
+                
+                self.call_holds.lock().await.insert(call_id, CallHold::new());
+                let rule = self.call_rules.lock().await.remove(&call_id);
+                if let Some(impairment) = rule.as_ref().and_then(|rule| rule.impairment.as_ref()) {
+                    self.set_impairment(&call_id, impairment).await;
+                }
+                let loopback = rule.as_ref().is_some_and(|rule| {
+                    rule.actions.iter().any(|action| matches!(action, DialplanAction::LoopbackTest(_)))
+                });
+                
+                if self.server_config.inbound_processing.enabled || self.server_config.inbound_analysis.enabled || loopback {
+                    self.start_inbound_processing(client, call_id, loopback).await;
+                }
+                
+                // Scripted calls follow their dialplan rule instead of the default playback
+                let handler = self.clone();
+                let task = match rule {
+                    Some(rule) => tokio::spawn(async move {
+                        handler.run_dialplan(call_id, rule).await;
+                    }),
+                    None => tokio::spawn(async move {
+                        // Play the MP3 prompt, then hang up once it has finished
+                        match handler.play_mp3_prompt(&call_id).await {
+                            Ok(_) => {
+                                info!("📴 Hanging up call {} after MP3 completion", call_id);
+                                handler.hangup(&call_id).await;
+                            }
+                            Err(e) => {
+                                error!("❌ Failed to play MP3 prompt: {}", e);
+                                handler.start_fallback_audio(&call_id).await;
                             }
                         }
-                    }
-                }
+                    }),
+                };
+                self.set_call_task(call_id, task).await;
+            }
+        } else if matches!(status_info.new_state, CallState::Terminated | CallState::Failed | CallState::Cancelled) {
+            // Calls that never connected are released and recorded the same way
+            if status_info.new_state == CallState::Terminated {
+                info!("📴 Call {} terminated", status_info.call_id);
+            } else {
+                info!("📴 Call {} ended before answer ({:?})", status_info.call_id, status_info.new_state);
             }
-        } else if status_info.new_state == CallState::Terminated {
-            info!("📴 Call {} terminated", status_info.call_id);
             
             // Remove from active calls and update statistics
-            {
-                let mut active_calls = self.active_calls.lock().await;
-                if let Some(start_time) = active_calls.remove(&status_info.call_id) {
-                    let duration = start_time.elapsed();
-                    info!("⏱️ Call {} duration: {:?}", status_info.call_id, duration);
+            let start_time = self.active_calls.lock().await.remove(&status_info.call_id);
+            if let Some(start_time) = start_time {
+                info!("⏱️ Call {} duration: {:?}", status_info.call_id, start_time.elapsed());
+                let mut stats = self.call_stats.lock().await;
+                stats.active_calls = stats.active_calls.saturating_sub(1);
+            }
+            self.call_rules.lock().await.remove(&status_info.call_id);
+            
+            // A caller leaving the call after a REFER ends its transfer, which may or may not
+            // have reached the target
+            if let Some(ended) = self.pending_transfers.lock().await.remove(&status_info.call_id) {
+                let _ = ended.send(());
+                if let Some(transfer) = self.call_records.lock().await.get_mut(&status_info.call_id)
+                    .and_then(|record| record.transfer.as_mut()) {
+                    info!("🔀 Call {} left after the REFER to {} (transfer unconfirmed)", status_info.call_id, transfer.target);
+                    transfer.outcome = "Caller left after REFER (unconfirmed)".to_string();
                 }
             }
             
-            {
-                let mut stats = self.call_stats.lock().await;
-                stats.active_calls = stats.active_calls.saturating_sub(1);
+            // Stop playback; dropping the task releases its prompt cursor
+            if let Some(task) = self.call_tasks.lock().await.remove(&status_info.call_id) {
+                task.abort();
             }
+            
+            let handler = self.clone();
+            let call_id = status_info.call_id;
+            tokio::spawn(async move { handler.complete_call(call_id).await });
         }
     }
 
@@ -312,6 +1239,77 @@ async fn main() -> Result<()> {
                 .help("PID file path")
                 .default_value(DEFAULT_PID_PATH),
         )
+        .subcommand(
+            call_args(Command::new("originate"))
+                .about("Place calls from this instance, then exit; unset options come from [originate]")
+                .arg(
+                    Arg::new("calls")
+                        .short('n')
+                        .long("calls")
+                        .value_name("COUNT")
+                        .value_parser(clap::value_parser!(u32))
+                        .help("Number of calls to place"),
+                )
+                .arg(
+                    Arg::new("rate")
+                        .short('r')
+                        .long("rate")
+                        .value_name("CPS")
+                        .value_parser(clap::value_parser!(f64))
+                        .help("Calls per second"),
+                )
+                .arg(
+                    Arg::new("concurrency")
+                        .long("concurrency")
+                        .value_name("CALLS")
+                        .value_parser(clap::value_parser!(u32))
+                        .help("Most calls up at once"),
+                ),
+        )
+        .subcommand(
+            call_args(Command::new("load"))
+                .about("Run a load test following the [load] profile or a ramp given here, then report")
+                .arg(
+                    Arg::new("peak-cps")
+                        .long("peak-cps")
+                        .value_name("CPS")
+                        .value_parser(clap::value_parser!(f64))
+                        .requires("peak-concurrency")
+                        .help("Ramp to this call rate instead of following [load]"),
+                )
+                .arg(
+                    Arg::new("peak-concurrency")
+                        .long("peak-concurrency")
+                        .value_name("CALLS")
+                        .value_parser(clap::value_parser!(u32))
+                        .requires("peak-cps")
+                        .help("Concurrency limit at the peak"),
+                )
+                .arg(
+                    Arg::new("ramp-up")
+                        .long("ramp-up")
+                        .value_name("SECONDS")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("0")
+                        .help("Time to reach the peak"),
+                )
+                .arg(
+                    Arg::new("hold")
+                        .long("hold")
+                        .value_name("SECONDS")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("60")
+                        .help("Time at the peak"),
+                )
+                .arg(
+                    Arg::new("ramp-down")
+                        .long("ramp-down")
+                        .value_name("SECONDS")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("0")
+                        .help("Time to wind down from the peak"),
+                ),
+        )
         .get_matches();
 
     let config_path = matches.get_one::<String>("config").unwrap();
@@ -331,6 +1329,40 @@ async fn main() -> Result<()> {
 
     // Validate configuration
     server_config.validate()?;
+    
+    let mut originate_request = None;
+    let mut load_request = None;
+    match matches.subcommand() {
+        Some(("originate", args)) => {
+            let mut overrides = call_overrides(args);
+            if let Some(calls) = args.get_one::<u32>("calls") {
+                overrides.insert("calls".to_string(), (*calls).into());
+            }
+            if let Some(rate) = args.get_one::<f64>("rate") {
+                overrides.insert("calls_per_second".to_string(), (*rate).into());
+            }
+            if let Some(concurrency) = args.get_one::<u32>("concurrency") {
+                overrides.insert("max_concurrent".to_string(), (*concurrency).into());
+            }
+            
+            let request = server_config.originate.with_overrides(&overrides.into())?;
+            server_config.validate_originate(&request)?;
+            originate_request = Some(request);
+        }
+        Some(("load", args)) => {
+            let request = server_config.originate.with_overrides(&call_overrides(args).into())?;
+            server_config.validate_originate(&request)?;
+            
+            let mut load = server_config.load.clone();
+            if let (Some(cps), Some(concurrency)) = (args.get_one::<f64>("peak-cps"), args.get_one::<u32>("peak-concurrency")) {
+                let seconds = |name| Duration::from_secs(*args.get_one::<u64>(name).unwrap());
+                load.profile = load::ramp_profile(seconds("ramp-up"), seconds("hold"), seconds("ramp-down"), *cps, *concurrency);
+            }
+            load.validate()?;
+            load_request = Some((request, LoadProfile::new(&load.profile)));
+        }
+        _ => {}
+    }
 
     if daemon_mode {
         info!("🔧 Starting in daemon mode");
@@ -368,7 +1400,8 @@ async fn main() -> Result<()> {
     
     mp3_handler.convert_mp3_to_wav(
         server_config.media.audio_sample_rate,
-        1 // Mono channel for telephony
+        1, // Mono channel for telephony
+        server_config.media.prompt_duration_seconds,
     ).context("Failed to convert MP3 to WAV")?;
     
     info!("✅ MP3 file ready for playback with telephony optimization");
@@ -398,7 +1431,15 @@ async fn main() -> Result<()> {
     info!("   🌐 Domain: {}", server_config.sip.domain);
     
     // Create handler and client using updated API
-    let handler = Arc::new(AutoAnswerHandler::new(mp3_handler, server_config.clone()));
+    let cdr_writer = if server_config.cdr.enabled {
+        let writer = CdrWriter::new(&server_config.cdr).context("Failed to set up CDR output")?;
+        info!("🧾 Writing call detail records to {}", writer.path().display());
+        Some(Arc::new(writer))
+    } else {
+        None
+    };
+    
+    let handler = Arc::new(AutoAnswerHandler::new(mp3_handler, server_config.clone(), cdr_writer));
     
     // Prepare audio samples for transmission
     info!("🎵 Preparing audio samples for transmission...");
@@ -437,6 +1478,17 @@ async fn main() -> Result<()> {
                     let mut request_line = String::new();
                     
                     if buf_reader.read_line(&mut request_line).await.is_ok() {
+                        // GET /calls/<call id>/analysis
+                        let analysis_call_id = request_line.strip_prefix("GET /calls/")
+                            .and_then(|rest| rest.split_whitespace().next())
+                            .and_then(|path| path.strip_suffix("/analysis"));
+                        // PUT or DELETE /calls/<call id>/impairment
+                        let (method, path) = request_line.split_once(' ').unwrap_or_default();
+                        let impairment_call_id = path.split_whitespace().next()
+                            .and_then(|path| path.strip_prefix("/calls/"))
+                            .and_then(|path| path.strip_suffix("/impairment"))
+                            .filter(|_| matches!(method, "PUT" | "DELETE"));
+                        
                         if request_line.contains("GET /health") {
                             let stats = handler.call_stats.lock().await;
                             
@@ -445,11 +1497,29 @@ async fn main() -> Result<()> {
                                 stats.active_calls, stats.total_calls
                             );
                             
+                            let _ = stream.write_all(json_response(&health_response).as_bytes()).await;
+                        } else if request_line.starts_with("GET /metrics") {
+                            let metrics = handler.render_metrics().await;
                             let response = format!(
-                                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
-                                health_response.len(), health_response
+                                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+                                metrics.len(), metrics
                             );
-                            
+                            let _ = stream.write_all(response.as_bytes()).await;
+                        } else if let Some(call_id) = analysis_call_id {
+                            let analysis = handler.call_analysis(call_id).await
+                                .and_then(|analysis| serde_json::to_string(&analysis).ok());
+                            let response = match analysis {
+                                Some(body) => json_response(&body),
+                                None => "HTTP/1.1 404 Not Found\r\n\r\n".to_string(),
+                            };
+                            let _ = stream.write_all(response.as_bytes()).await;
+                        } else if request_line.starts_with("POST /originate") {
+                            let body = read_request_body(&mut buf_reader).await;
+                            let response = handler.originate_request(body.as_deref()).await;
+                            let _ = stream.write_all(response.as_bytes()).await;
+                        } else if let Some(call_id) = impairment_call_id {
+                            let body = read_request_body(&mut buf_reader).await;
+                            let response = handler.impairment_request(method, call_id, body.as_deref()).await;
                             let _ = stream.write_all(response.as_bytes()).await;
                         } else {
                             let response = "HTTP/1.1 404 Not Found\r\n\r\n";
@@ -464,30 +1534,39 @@ async fn main() -> Result<()> {
     client.start().await.context("Failed to start client")?;
     
     // Signal handling for graceful shutdown
-    let mut signals = Signals::new(&[SIGTERM])?;
+    let mut signals = Signals::new([SIGTERM])?;
     let handle = signals.handle();
     let running = Arc::new(RwLock::new(true));
     
     let running_clone = Arc::clone(&running);
     let signal_task = tokio::spawn(async move {
         while let Some(signal) = signals.next().await {
-            match signal {
-                SIGTERM => {
-                    info!("Received SIGTERM, shutting down gracefully...");
-                    *running_clone.write().await = false;
-                    break;
-                }
-                _ => {}
+            if signal == SIGTERM {
+                info!("Received SIGTERM, shutting down gracefully...");
+                *running_clone.write().await = false;
+                break;
             }
         }
     });
 
     info!("✅ rvoip auto-answering SIP server started successfully!");
     info!("📞 Ready to auto-answer calls to: sip:*@{}", server_config.sip.domain);
-    info!("🎵 Will play MP3 audio for 30 seconds on each call");
+    info!("🎵 Will play MP3 audio for {} on each call", describe_prompt_duration(server_config.media.prompt_duration_seconds));
     info!("🎯 rvoip auto-answering SIP server is ready!");
     info!("🏥 Health endpoint started on http://{}:{}/health", health_addr.ip(), health_addr.port());
 
+    // Origination and load tests run their calls, still answering incoming ones, then shut down
+    if let Some(request) = originate_request {
+        let summary = handler.originate(request).await?;
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+        *running.write().await = false;
+    }
+    if let Some((request, profile)) = load_request {
+        let report = handler.run_load(request, profile).await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        *running.write().await = false;
+    }
+
     // Main server loop
     while *running.read().await {
         tokio::time::sleep(Duration::from_secs(15)).await;
@@ -513,6 +1592,95 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Options shared by the subcommands that place calls
+fn call_args(command: Command) -> Command {
+    command
+        .arg(Arg::new("target").value_name("URI").help("URI to call"))
+        .arg(Arg::new("from").long("from").value_name("URI").help("From URI"))
+        .arg(Arg::new("rule").long("rule").value_name("NAME").help("Dialplan rule to run on answered calls"))
+        .arg(
+            Arg::new("max-duration")
+                .long("max-duration")
+                .value_name("MS")
+                .value_parser(clap::value_parser!(u64))
+                .help("Hang up answered calls after this long"),
+        )
+}
+
+/// `[originate]` fields set by the options of `call_args`
+fn call_overrides(args: &clap::ArgMatches) -> serde_json::Map<String, serde_json::Value> {
+    let mut overrides = serde_json::Map::new();
+    for name in ["target", "from", "rule"] {
+        if let Some(value) = args.get_one::<String>(name) {
+            overrides.insert(name.to_string(), value.clone().into());
+        }
+    }
+    if let Some(max_duration) = args.get_one::<u64>("max-duration") {
+        overrides.insert("max_duration_ms".to_string(), (*max_duration).into());
+    }
+    overrides
+}
+
+/// Current RTP counters of a call, if its media session exists
+async fn poll_rtp_sample(client: &ClientManager, call_id: &CallId) -> Option<RtpSample> {
+    let stats = client.get_call_statistics(call_id).await.ok().flatten()?;
+    
+    Some(RtpSample {
+        packets_sent: stats.rtp.packets_sent,
+        packets_received: stats.rtp.packets_received,
+        packets_lost: stats.rtp.packets_lost,
+        packets_out_of_order: stats.rtp.packets_out_of_order,
+        jitter_ms: stats.quality.jitter_ms as f64,
+        // Zero until an RTCP report has given a round trip
+        rtt_ms: (stats.quality.round_trip_ms > 0.0).then_some(stats.quality.round_trip_ms as f64),
+    })
+}
+
+/// Why a call ended, as reported with its final state
+fn failure_reason(status: &CallStatusInfo) -> String {
+    status.reason.clone().unwrap_or_else(|| format!("{:?}", status.new_state))
+}
+
+/// Skip the request headers and read the body they announce
+async fn read_request_body<R: tokio::io::AsyncBufRead + Unpin>(reader: &mut R) -> Option<Vec<u8>> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+    
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await.ok()? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok()?;
+            }
+        }
+    }
+    
+    if content_length > MAX_REQUEST_BODY {
+        return None;
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await.ok()?;
+    Some(body)
+}
+
+fn json_response(body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(), body
+    )
+}
+
+/// Human-readable `media.prompt_duration_seconds` (0 plays the whole file)
+fn describe_prompt_duration(seconds: u32) -> String {
+    match seconds {
+        0 => "the whole file".to_string(),
+        seconds => format!("{} seconds", seconds),
+    }
+}
+
 fn log_server_configuration(config: &ServerConfig) {
     info!("⚙️ rvoip server configuration:");
     info!("   📡 Listening: {}:{}", config.sip.bind_address, config.sip.port);
@@ -520,7 +1688,11 @@ fn log_server_configuration(config: &ServerConfig) {
     info!("   📞 Max concurrent calls: {}", config.behavior.max_concurrent_calls);
     info!("   🎵 Auto-answer enabled: {}", config.behavior.auto_answer);
     info!("   ⏱️ Auto-answer delay: {}ms", config.behavior.auto_answer_delay_ms);
-    info!("   🎶 Audio: MP3 playback for {} seconds", 30);
+    info!("   🎶 Audio: MP3 playback for {}", describe_prompt_duration(config.media.prompt_duration_seconds));
+    info!("   🎙️ Inbound processing: {}", if config.inbound_processing.enabled { "enabled" } else { "disabled" });
+    info!("   🔬 Inbound analysis: {}", if config.inbound_analysis.enabled { "enabled" } else { "disabled" });
+    info!("   📶 RTP statistics: {}", if config.rtp_stats.enabled { "enabled" } else { "disabled" });
+    info!("   🧾 CDR: {}", if config.cdr.enabled { config.cdr.path.as_str() } else { "disabled" });
     
     info!("⚙️ rvoip client configuration:");
     info!("   📡 SIP address: {}:{}", config.sip.bind_address, config.sip.port);
@@ -0,0 +1,144 @@
+use std::fmt::Write;
+
+use crate::rtp_stats::RtpCallStats;
+
+/// Cumulative Prometheus histogram
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    /// Upper bounds, ascending; +Inf is implied
+    bounds: Vec<f64>,
+    /// Observations per bucket (not cumulative)
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[f64]) -> Self {
+        Self { bounds: bounds.to_vec(), counts: vec![0; bounds.len() + 1], sum: 0.0, count: 0 }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        let bucket = self.bounds.iter().position(|&bound| value <= bound).unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Append the text exposition of this histogram
+    pub fn render(&self, name: &str, help: &str, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum);
+        let _ = writeln!(out, "{}_count {}", name, self.count);
+    }
+}
+
+fn render_counter(name: &str, help: &str, value: u64, out: &mut String) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Per-call RTP quality, aggregated over all ended calls
+#[derive(Debug, Clone)]
+pub struct CallQualityMetrics {
+    jitter_ms: Histogram,
+    loss_ratio: Histogram,
+    rtt_ms: Histogram,
+    max_gap_ms: Histogram,
+    packets_sent: u64,
+    packets_received: u64,
+    packets_lost: u64,
+    packets_out_of_order: u64,
+}
+
+impl Default for CallQualityMetrics {
+    fn default() -> Self {
+        Self {
+            jitter_ms: Histogram::new(&[1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 50.0, 100.0]),
+            loss_ratio: Histogram::new(&[0.0, 0.001, 0.005, 0.01, 0.02, 0.05, 0.1, 0.2]),
+            rtt_ms: Histogram::new(&[10.0, 25.0, 50.0, 100.0, 150.0, 200.0, 300.0, 500.0, 1000.0]),
+            max_gap_ms: Histogram::new(&[20.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2000.0, 5000.0]),
+            packets_sent: 0,
+            packets_received: 0,
+            packets_lost: 0,
+            packets_out_of_order: 0,
+        }
+    }
+}
+
+impl CallQualityMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe_call(&mut self, stats: &RtpCallStats) {
+        self.jitter_ms.observe(stats.jitter_ms_mean);
+        self.loss_ratio.observe(stats.loss_ratio);
+        if let Some(rtt) = stats.rtt_ms_mean {
+            self.rtt_ms.observe(rtt);
+        }
+        self.max_gap_ms.observe(stats.max_gap_ms as f64);
+        self.packets_sent += stats.packets_sent;
+        self.packets_received += stats.packets_received;
+        self.packets_lost += stats.packets_lost;
+        self.packets_out_of_order += stats.packets_out_of_order;
+    }
+
+    pub fn render(&self, out: &mut String) {
+        self.jitter_ms.render("sip_server_call_rtp_jitter_ms", "Mean interarrival jitter per call", out);
+        self.loss_ratio.render("sip_server_call_rtp_loss_ratio", "Fraction of expected RTP packets lost per call", out);
+        self.rtt_ms.render("sip_server_call_rtcp_rtt_ms", "Mean RTCP round-trip time per call", out);
+        self.max_gap_ms.render("sip_server_call_rtp_max_gap_ms", "Longest pause in received RTP per call", out);
+        render_counter("sip_server_rtp_packets_sent_total", "RTP packets sent on ended calls", self.packets_sent, out);
+        render_counter("sip_server_rtp_packets_received_total", "RTP packets received on ended calls", self.packets_received, out);
+        render_counter("sip_server_rtp_packets_lost_total", "RTP packets lost on ended calls", self.packets_lost, out);
+        render_counter("sip_server_rtp_packets_out_of_order_total", "RTP packets received out of order on ended calls", self.packets_out_of_order, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_exposition() {
+        let mut histogram = Histogram::new(&[1.0, 5.0]);
+        for value in [0.5, 1.0, 3.0, 7.0] {
+            histogram.observe(value);
+        }
+
+        let mut out = String::new();
+        histogram.render("jitter_ms", "Jitter", &mut out);
+        assert_eq!(out, "# HELP jitter_ms Jitter\n\
+                         # TYPE jitter_ms histogram\n\
+                         jitter_ms_bucket{le=\"1\"} 2\n\
+                         jitter_ms_bucket{le=\"5\"} 3\n\
+                         jitter_ms_bucket{le=\"+Inf\"} 4\n\
+                         jitter_ms_sum 11.5\n\
+                         jitter_ms_count 4\n");
+    }
+
+    #[test]
+    fn test_call_metrics() {
+        let mut metrics = CallQualityMetrics::new();
+        metrics.observe_call(&RtpCallStats { packets_received: 100, packets_lost: 3, loss_ratio: 0.03, ..RtpCallStats::default() });
+        metrics.observe_call(&RtpCallStats { packets_received: 50, rtt_ms_mean: Some(40.0), ..RtpCallStats::default() });
+
+        let mut out = String::new();
+        metrics.render(&mut out);
+        assert!(out.contains("sip_server_call_rtp_loss_ratio_bucket{le=\"0\"} 1\n"));
+        assert!(out.contains("sip_server_call_rtp_loss_ratio_bucket{le=\"0.05\"} 2\n"));
+        // Calls without an RTCP report have no RTT
+        assert!(out.contains("sip_server_call_rtcp_rtt_ms_count 1\n"));
+        assert!(out.contains("sip_server_rtp_packets_received_total 150\n"));
+        assert!(out.contains("sip_server_rtp_packets_lost_total 3\n"));
+    }
+}
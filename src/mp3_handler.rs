@@ -1,8 +1,9 @@
 use anyhow::{Context, Result};
-use log::{info, warn};
+use log::info;
+use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::fs::File;
-use symphonia::core::audio::{AudioBufferRef, Signal};
+use symphonia::core::audio::AudioBuffer;
 use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
 use symphonia::core::errors::Error as SymphoniaError;
 use symphonia::core::formats::FormatOptions;
@@ -11,16 +12,60 @@ use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
 use symphonia::default::get_probe;
 use hound::{WavSpec, WavWriter};
-use crate::config::{AudioProcessingConfig, CompressorBandConfig};
+use crate::audio_stages::{self, AudioStage, LevelStats, StageStats};
+use crate::config::AudioProcessingConfig;
+use crate::loudness::{self, LoudnessReport};
+use crate::resampler::Resampler;
 
 const MP3_FILENAME: &str = "jocofullinterview41.mp3";
 const MP3_URL: &str = "https://archive.org/download/NeverGonnaGiveYouUp/jocofullinterview41.mp3";
 const WAV_FILENAME: &str = "jocofullinterview41.wav";
 
+/// How multi-channel sources are folded into a mono telephony stream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownmixMode {
+    /// Mean of all channels; keeps the level of centre-panned content
+    #[default]
+    Average,
+    /// First channel only
+    Left,
+    /// Second channel only (first channel for mono sources)
+    Right,
+    /// Equal-power sum of the front pair, (L + R) / √2; keeps the level of wide stereo content.
+    /// Correlated channels gain up to 3dB, so the result is clamped to full scale
+    Mid,
+}
+
+/// Fold planar channel data into a single channel
+pub fn downmix(planes: &[&[f32]], mode: DownmixMode) -> Vec<f32> {
+    let Some(first) = planes.first() else {
+        return Vec::new();
+    };
+    let second = planes.get(1).unwrap_or(first);
+
+    match mode {
+        DownmixMode::Left => first.to_vec(),
+        DownmixMode::Right => second.to_vec(),
+        DownmixMode::Average => {
+            let scale = 1.0 / planes.len() as f32;
+            (0..first.len())
+                .map(|i| planes.iter().map(|plane| plane[i]).sum::<f32>() * scale)
+                .collect()
+        }
+        DownmixMode::Mid if planes.len() == 1 => first.to_vec(),
+        DownmixMode::Mid => first
+            .iter()
+            .zip(second.iter())
+            .map(|(&left, &right)| ((left + right) * std::f32::consts::FRAC_1_SQRT_2).clamp(-1.0, 1.0))
+            .collect(),
+    }
+}
+
 pub struct Mp3Handler {
     mp3_path: String,
     wav_path: String,
-    telephony_processor: TelephonyAudioProcessor,
+    audio_config: AudioProcessingConfig,
 }
 
 impl Mp3Handler {
@@ -28,7 +73,7 @@ impl Mp3Handler {
         Self {
             mp3_path: MP3_FILENAME.to_string(),
             wav_path: WAV_FILENAME.to_string(),
-            telephony_processor: TelephonyAudioProcessor::new(8000.0, audio_config.clone()),
+            audio_config: audio_config.clone(),
         }
     }
 
@@ -65,647 +110,529 @@ impl Mp3Handler {
     }
 
     /// Convert MP3 to WAV format with specified parameters and proper resampling
-    pub fn convert_mp3_to_wav(&mut self, target_sample_rate: u32, channels: u16) -> Result<()> {
+    ///
+    /// At most `max_duration_seconds` of audio are rendered; 0 converts the whole file.
+    ///
+    /// An existing WAV file is reused only if it was rendered with the same processing
+    /// settings, which are recorded as a fingerprint in `<wav>.settings`.
+    pub fn convert_mp3_to_wav(&mut self, target_sample_rate: u32, channels: u16, max_duration_seconds: u32) -> Result<()> {
+        let fingerprint = render_fingerprint(&self.audio_config, target_sample_rate, channels, max_duration_seconds)?;
+        let settings_path = format!("{}.settings", self.wav_path);
+
         if Path::new(&self.wav_path).exists() {
-            info!("🎵 WAV file already exists: {}", self.wav_path);
-            return Ok(());
+            match std::fs::read_to_string(&settings_path) {
+                Ok(stored) if stored.trim() == fingerprint => {
+                    info!("🎵 Using cached WAV file {} (delete it to force a new conversion)", self.wav_path);
+                    return Ok(());
+                }
+                _ => info!("🔄 {} was rendered with different processing settings, converting again", self.wav_path),
+            }
+        }
+        // A conversion that fails part way must not leave a matching fingerprint behind
+        if let Err(e) = std::fs::remove_file(&settings_path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e).context("Failed to remove WAV settings file");
+            }
         }
 
         info!("🔄 Converting MP3 to WAV format ({}Hz, {} channels) with telephony processing", target_sample_rate, channels);
 
-        let file = File::open(&self.mp3_path)
-            .context("Failed to open MP3 file")?;
-        
-        let mss = MediaSourceStream::new(Box::new(file), Default::default());
-        
-        let mut hint = Hint::new();
-        hint.with_extension("mp3");
-        
-        let meta_opts: MetadataOptions = Default::default();
-        let fmt_opts: FormatOptions = Default::default();
-        
-        let probed = get_probe()
-            .format(&hint, mss, &fmt_opts, &meta_opts)
-            .context("Failed to probe MP3 file")?;
-        
-        let mut format = probed.format;
-        let track = format
-            .tracks()
-            .iter()
-            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-            .context("No valid audio track found")?;
-        
-        let track_id = track.id;
-        let mut decoder = symphonia::default::get_codecs()
-            .make(&track.codec_params, &DecoderOptions { verify: false })
-            .context("Failed to create decoder")?;
-        
-        // Get source sample rate from the MP3
-        let source_sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
-        info!("🎼 Source MP3 sample rate: {}Hz, target: {}Hz", source_sample_rate, target_sample_rate);
-        
-        let spec = WavSpec {
+        let report = convert_to_telephony_wav(
+            Path::new(&self.mp3_path),
+            Path::new(&self.wav_path),
+            &self.audio_config,
+            target_sample_rate,
             channels,
-            sample_rate: target_sample_rate,
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
+            max_duration_seconds,
+        )?;
+        if let Some(loudness) = &report.loudness {
+            info!("🔊 Prompt loudness: measured {:.1} {unit}, target {:.1} {unit}, applied {:+.1} dB{}",
+                  loudness.measured_level, loudness.target_level, loudness.applied_gain_db,
+                  if loudness.peak_limited { " (limited by peak)" } else { "" },
+                  unit = loudness.method.unit());
+        }
+        std::fs::write(&settings_path, &fingerprint)
+            .context("Failed to write WAV settings file")?;
+        Ok(())
+    }
+
+    /// Path of the processed WAV file used as the call prompt
+    pub fn wav_path(&self) -> &str {
+        &self.wav_path
+    }
+}
+
+/// Fingerprint of everything that shapes a processed WAV file
+///
+/// FNV-1a over the serialized settings; unlike `DefaultHasher` it is stable across builds.
+pub fn render_fingerprint(audio_config: &AudioProcessingConfig, target_sample_rate: u32, channels: u16, max_duration_seconds: u32) -> Result<String> {
+    let settings = serde_json::json!({
+        "sample_rate": target_sample_rate,
+        "channels": channels,
+        "max_duration_seconds": max_duration_seconds,
+        "audio_processing": serde_json::to_value(audio_config)?,
+    });
+    let hash = settings.to_string().bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    Ok(format!("{:016x}", hash))
+}
+
+/// Decode any supported audio file, resample it and run it through the telephony
+/// processor, writing 16-bit PCM WAV
+///
+/// At most `max_duration_seconds` of audio are rendered (0 = whole file). When
+/// `[audio_processing.loudness]` is enabled the written file is then normalized in a
+/// second pass. Returns the processing statistics of each output channel and the
+/// loudness correction.
+pub fn convert_to_telephony_wav(
+    input_path: &Path,
+    output_path: &Path,
+    audio_config: &AudioProcessingConfig,
+    target_sample_rate: u32,
+    channels: u16,
+    max_duration_seconds: u32,
+) -> Result<ConversionReport> {
+    if channels == 0 {
+        return Err(anyhow::anyhow!("Invalid WAV channel count: 0"));
+    }
+
+    let file = File::open(input_path)
+        .with_context(|| format!("Failed to open audio file: {}", input_path.display()))?;
+    
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    
+    let mut hint = Hint::new();
+    if let Some(extension) = input_path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    
+    let meta_opts: MetadataOptions = Default::default();
+    let fmt_opts: FormatOptions = Default::default();
+    
+    let probed = get_probe()
+        .format(&hint, mss, &fmt_opts, &meta_opts)
+        .context("Failed to probe audio file")?;
+    
+    let mut format = probed.format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .context("No valid audio track found")?;
+    
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions { verify: false })
+        .context("Failed to create decoder")?;
+    
+    // Get source sample rate and channel count from the input
+    let source_sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
+    let source_channels = track.codec_params.channels.map(|c| c.count()).unwrap_or(1);
+    info!("🎼 Source audio: {}Hz, {} channels; target: {}Hz, {} channels ({:?} downmix)", 
+          source_sample_rate, source_channels, target_sample_rate, channels, audio_config.downmix_mode);
+    
+    let spec = WavSpec {
+        channels,
+        sample_rate: target_sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    
+    let mut writer = WavWriter::create(output_path, spec)
+        .context("Failed to create WAV writer")?;
+    
+    let mut sample_count = 0;
+    let max_samples = match max_duration_seconds {
+        0 => usize::MAX,
+        seconds => target_sample_rate as usize * seconds as usize,
+    };
+    
+    // Each output channel gets its own resampler and telephony processor state
+    let mut resamplers: Vec<Resampler> = (0..channels)
+        .map(|_| Resampler::new(source_sample_rate, target_sample_rate, audio_config.resampler_quality))
+        .collect();
+    let mut processors: Vec<TelephonyAudioProcessor> = (0..channels)
+        .map(|_| TelephonyAudioProcessor::new(target_sample_rate as f32, audio_config.clone()))
+        .collect();
+    // The first samples out of a delaying stage (the lookahead limiter) are its silent pre-roll
+    let latency = processors[0].latency();
+    let mut pending_delay = latency;
+    
+    // Decoded packets of any sample format are converted into this f32 buffer
+    let mut decoded: Option<AudioBuffer<f32>> = None;
+    
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::ResetRequired) => {
+                // The track list has been changed. Re-examine it and create a new set of decoders,
+                // then restart the decode loop. This is an advanced feature that most applications
+                // do not need.
+                break;
+            }
+            Err(SymphoniaError::IoError(_)) => {
+                // The packet reader has reached the end of the file.
+                break;
+            }
+            Err(err) => {
+                // A unrecoverable error occurred, halt decoding.
+                return Err(err.into());
+            }
         };
         
-        let mut writer = WavWriter::create(&self.wav_path, spec)
-            .context("Failed to create WAV writer")?;
-        
-        let mut sample_count = 0;
-        let max_samples = target_sample_rate as usize * 30; // 30 seconds at target rate
-        let mut resampler = SimpleResampler::new(source_sample_rate, target_sample_rate);
+        if packet.track_id() != track_id {
+            continue;
+        }
         
-        // Reset telephony processor for fresh start
-        self.telephony_processor.reset();
+        let audio_buf = decoder.decode(&packet)
+            .context("Failed to decode audio packet")?;
         
-        loop {
-            let packet = match format.next_packet() {
-                Ok(packet) => packet,
-                Err(SymphoniaError::ResetRequired) => {
-                    // The track list has been changed. Re-examine it and create a new set of decoders,
-                    // then restart the decode loop. This is an advanced feature that most applications
-                    // do not need.
-                    break;
-                }
-                Err(SymphoniaError::IoError(_)) => {
-                    // The packet reader has reached the end of the file.
-                    break;
-                }
-                Err(err) => {
-                    // A unrecoverable error occurred, halt decoding.
-                    return Err(err.into());
-                }
-            };
-            
-            if packet.track_id() != track_id {
-                continue;
-            }
-            
-            let audio_buf = decoder.decode(&packet)
-                .context("Failed to decode audio packet")?;
-            
-            // Convert to the target format and write samples
-            match audio_buf {
-                AudioBufferRef::F32(buf) => {
-                    // Process samples with resampling and telephony processing
-                    for &sample in buf.chan(0) {
-                        if sample_count >= max_samples {
-                            break;
-                        }
-                        
-                        // Resample if needed
-                        let resampled_samples = if source_sample_rate != target_sample_rate {
-                            resampler.process_sample(sample)
-                        } else {
-                            vec![sample]
-                        };
-                        
-                        for resampled_sample in resampled_samples {
-                            if sample_count >= max_samples {
-                                break;
-                            }
-                            
-                            // Apply telephony processing for better phone call quality
-                            let processed_sample = self.telephony_processor.process_sample(resampled_sample);
-                            
-                            let sample_i16 = (processed_sample * 32767.0).clamp(-32768.0, 32767.0) as i16;
-                            writer.write_sample(sample_i16)
-                                .context("Failed to write sample")?;
-                            sample_count += 1;
-                        }
-                    }
-                }
-                AudioBufferRef::F64(buf) => {
-                    // Process samples with resampling and telephony processing
-                    for &sample in buf.chan(0) {
-                        if sample_count >= max_samples {
-                            break;
-                        }
-                        
-                        let sample_f32 = sample as f32;
-                        
-                        // Resample if needed
-                        let resampled_samples = if source_sample_rate != target_sample_rate {
-                            resampler.process_sample(sample_f32)
-                        } else {
-                            vec![sample_f32]
-                        };
-                        
-                        for resampled_sample in resampled_samples {
-                            if sample_count >= max_samples {
-                                break;
-                            }
-                            
-                            // Apply telephony processing for better phone call quality
-                            let processed_sample = self.telephony_processor.process_sample(resampled_sample);
-                            
-                            let sample_i16 = (processed_sample * 32767.0).clamp(-32768.0, 32767.0) as i16;
-                            writer.write_sample(sample_i16)
-                                .context("Failed to write sample")?;
-                            sample_count += 1;
-                        }
-                    }
-                }
-                _ => {
-                    warn!("Unsupported audio buffer format");
-                }
-            }
-            
-            if sample_count >= max_samples {
-                break;
-            }
+        // Convert any sample format (U8, S16, S24, F32, ...) to f32 planes
+        let needs_buffer = match &decoded {
+            Some(buf) => buf.capacity() < audio_buf.capacity() || buf.spec() != audio_buf.spec(),
+            None => true,
+        };
+        if needs_buffer {
+            decoded = Some(audio_buf.make_equivalent::<f32>());
         }
+        let buf = decoded.as_mut().expect("decode buffer allocated above");
+        audio_buf.convert(buf);
         
-        writer.finalize()
-            .context("Failed to finalize WAV file")?;
+        let planes = buf.planes();
+        let planes = planes.planes();
         
-        info!("✅ MP3 converted to WAV with telephony processing: {} ({} samples at {}Hz)", 
-              self.wav_path, sample_count, target_sample_rate);
-        Ok(())
-    }
-
-    /// Read WAV file samples for streaming
-    pub fn read_wav_samples(&self) -> Result<Vec<i16>> {
-        let mut reader = hound::WavReader::open(&self.wav_path)
-            .context("Failed to open WAV file")?;
+        // Downmix to mono, or map source channels onto each output channel
+        let output_channels: Vec<Vec<f32>> = if channels == 1 {
+            vec![downmix(planes, audio_config.downmix_mode)]
+        } else {
+            (0..channels as usize)
+                .map(|c| planes[c.min(planes.len() - 1)].to_vec())
+                .collect()
+        };
         
-        let samples: Result<Vec<i16>, _> = reader.samples::<i16>().collect();
-        let samples = samples.context("Failed to read WAV samples")?;
+        // Resample to the target rate, then apply telephony processing
+        let mut resampled: Vec<Vec<f32>> = output_channels.iter()
+            .zip(resamplers.iter_mut())
+            .map(|(samples, resampler)| resampler.process(samples))
+            .collect();
+        write_processed_frames(&mut writer, &mut processors, &mut resampled, &mut sample_count, max_samples, &mut pending_delay)?;
         
-        info!("📊 Loaded {} samples from WAV file", samples.len());
-        Ok(samples)
+        if sample_count >= max_samples {
+            break;
+        }
     }
     
-    /// Convert PCM samples to μ-law for PCMU codec
-    pub fn pcm_to_mulaw(&self, pcm_samples: &[i16]) -> Vec<u8> {
-        pcm_samples.iter().map(|&sample| {
-            self.linear_to_mulaw(sample)
-        }).collect()
-    }
+    // Drain the resamplers' filter delay
+    let mut tail: Vec<Vec<f32>> = resamplers.iter_mut().map(|resampler| resampler.flush()).collect();
+    write_processed_frames(&mut writer, &mut processors, &mut tail, &mut sample_count, max_samples, &mut pending_delay)?;
     
-    /// Convert linear PCM to μ-law (G.711)
-    fn linear_to_mulaw(&self, pcm: i16) -> u8 {
-        const BIAS: i16 = 0x84;
-        const CLIP: i16 = 32635;
-
-        let sign = if pcm < 0 { 0x80 } else { 0 };
-        let sample = if pcm < 0 { -pcm } else { pcm };
-        let sample = if sample > CLIP { CLIP } else { sample };
-        let sample = sample + BIAS;
-
-        let exponent = if sample >= 0x7FFF { 7 }
-        else if sample >= 0x4000 { 6 }
-        else if sample >= 0x2000 { 5 }
-        else if sample >= 0x1000 { 4 }
-        else if sample >= 0x0800 { 3 }
-        else if sample >= 0x0400 { 2 }
-        else if sample >= 0x0200 { 1 }
-        else { 0 };
-
-        let mantissa = (sample >> (exponent + 3)) & 0x0F;
-        let mulaw = sign | (exponent << 4) | mantissa;
-        !mulaw as u8
-    }
+    // Push silence through the processors to drain their delay
+    let mut tail = vec![vec![0.0; latency]; channels as usize];
+    write_processed_frames(&mut writer, &mut processors, &mut tail, &mut sample_count, max_samples, &mut pending_delay)?;
+    
+    writer.finalize()
+        .context("Failed to finalize WAV file")?;
+    
+    info!("✅ Converted to WAV with telephony processing: {} ({} samples at {}Hz)", 
+          output_path.display(), sample_count, target_sample_rate);
+
+    let loudness = if audio_config.loudness.enabled {
+        let report = loudness::normalize_wav(output_path, &audio_config.loudness)?;
+        if report.is_none() {
+            log::warn!("⚠️ No active signal in {}, loudness normalization skipped", output_path.display());
+        }
+        report
+    } else {
+        None
+    };
+
+    Ok(ConversionReport {
+        channels: processors.iter().map(TelephonyAudioProcessor::stats).collect(),
+        loudness,
+    })
 }
 
-/// Simple linear resampler for basic sample rate conversion
-struct SimpleResampler {
-    source_rate: u32,
-    target_rate: u32,
-    position: f64,
-    last_sample: f32,
+/// Everything measured while converting one file
+#[derive(Debug, Clone)]
+pub struct ConversionReport {
+    /// Telephony processor statistics of each output channel
+    pub channels: Vec<ProcessorStats>,
+    /// Gain applied by loudness normalization, if enabled and the file had signal
+    pub loudness: Option<LoudnessReport>,
 }
 
-impl SimpleResampler {
-    fn new(source_rate: u32, target_rate: u32) -> Self {
-        Self {
-            source_rate,
-            target_rate,
-            position: 0.0,
-            last_sample: 0.0,
-        }
+/// Run resampled frames through the telephony processors and write them as interleaved 16-bit PCM
+///
+/// The first `pending_delay` processed frames are the processors' pre-roll and are dropped.
+fn write_processed_frames<W: std::io::Write + std::io::Seek>(
+    writer: &mut WavWriter<W>,
+    processors: &mut [TelephonyAudioProcessor],
+    channels: &mut [Vec<f32>],
+    frame_count: &mut usize,
+    max_frames: usize,
+    pending_delay: &mut usize,
+) -> Result<()> {
+    let available = channels.iter().map(Vec::len).min().unwrap_or(0);
+    let frames = available.min(max_frames.saturating_sub(*frame_count).saturating_add(*pending_delay));
+    
+    // Apply telephony processing for better phone call quality
+    for (samples, processor) in channels.iter_mut().zip(processors.iter_mut()) {
+        processor.process_block(&mut samples[..frames]);
     }
     
-    fn process_sample(&mut self, input_sample: f32) -> Vec<f32> {
-        let mut output_samples = Vec::new();
-        
-        // For downsampling, advance position by target_rate/source_rate  
-        self.position += self.target_rate as f64 / self.source_rate as f64;
-        
-        // When position >= 1.0, output a sample
-        if self.position >= 1.0 {
-            // Use linear interpolation for better quality
-            let interpolated = self.last_sample + (input_sample - self.last_sample) * 0.5;
-            output_samples.push(interpolated);
-            self.position -= 1.0;
+    let skipped = frames.min(*pending_delay);
+    *pending_delay -= skipped;
+    
+    for i in skipped..frames {
+        for samples in channels.iter() {
+            let sample_i16 = (samples[i] * 32767.0).clamp(-32768.0, 32767.0) as i16;
+            writer.write_sample(sample_i16)
+                .context("Failed to write sample")?;
         }
-        
-        self.last_sample = input_sample;
-        output_samples
     }
+    *frame_count += frames - skipped;
+    Ok(())
 }
 
-/// Telephony-optimized audio processor for 8000Hz phone calls
-pub struct TelephonyAudioProcessor {
-    sample_rate: f32,
-    config: AudioProcessingConfig,
-    // Preemphasis filter state
-    preemphasis_prev: f32,
-    // Bandpass filter states (2nd order Butterworth)
-    bandpass_x1: f32,
-    bandpass_x2: f32,
-    bandpass_y1: f32,
-    bandpass_y2: f32,
-    // 3-band compressor components
-    band_filters: BandSplitFilters,
-    band1_compressor: BandCompressor,
-    band2_compressor: BandCompressor,
-    band3_compressor: BandCompressor,
+/// Statistics gathered by the telephony processor, for tuning `[audio_processing]`
+#[derive(Debug, Clone, Default)]
+pub struct ProcessorStats {
+    pub samples: u64,
+    pub input: LevelStats,
+    pub output: LevelStats,
+    /// Activity of each stage, in chain order
+    pub stages: Vec<(&'static str, StageStats)>,
 }
 
-/// Band-splitting filters for 3-band processing
-struct BandSplitFilters {
-    // Low-pass filter for band 1 (low-mid)
-    lowpass1_x1: f32,
-    lowpass1_x2: f32,
-    lowpass1_y1: f32,
-    lowpass1_y2: f32,
-    // High-pass filter for band 3 (high-mid)
-    highpass2_x1: f32,
-    highpass2_x2: f32,
-    highpass2_y1: f32,
-    highpass2_y2: f32,
-    // Bandpass filter for band 2 (mid)
-    bandpass2_x1: f32,
-    bandpass2_x2: f32,
-    bandpass2_y1: f32,
-    bandpass2_y2: f32,
+/// Telephony-optimized audio processor for 8000Hz phone calls
+///
+/// Runs each sample, or each block of samples, through the configured chain of `AudioStage`s.
+pub struct TelephonyAudioProcessor {
+    stages: Vec<Box<dyn AudioStage>>,
+    samples: u64,
+    input: LevelStats,
+    output: LevelStats,
 }
 
-/// Individual compressor for each band
-struct BandCompressor {
-    envelope: f32,
-}
+impl TelephonyAudioProcessor {
+    pub fn new(sample_rate: f32, config: AudioProcessingConfig) -> Self {
+        Self::with_stages(audio_stages::build_chain(&config, sample_rate))
+    }
 
-impl BandSplitFilters {
-    fn new() -> Self {
+    /// Build a processor from an explicit list of stages
+    pub fn with_stages(stages: Vec<Box<dyn AudioStage>>) -> Self {
         Self {
-            lowpass1_x1: 0.0,
-            lowpass1_x2: 0.0,
-            lowpass1_y1: 0.0,
-            lowpass1_y2: 0.0,
-            highpass2_x1: 0.0,
-            highpass2_x2: 0.0,
-            highpass2_y1: 0.0,
-            highpass2_y2: 0.0,
-            bandpass2_x1: 0.0,
-            bandpass2_x2: 0.0,
-            bandpass2_y1: 0.0,
-            bandpass2_y2: 0.0,
+            stages,
+            samples: 0,
+            input: LevelStats::default(),
+            output: LevelStats::default(),
         }
     }
-}
 
-impl BandCompressor {
-    fn new() -> Self {
-        Self {
-            envelope: 0.0,
-        }
+    /// Names of the stages in processing order
+    pub fn stage_names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
     }
-}
 
-impl TelephonyAudioProcessor {
-    pub fn new(sample_rate: f32, config: AudioProcessingConfig) -> Self {
-        Self {
-            sample_rate,
-            config,
-            preemphasis_prev: 0.0,
-            bandpass_x1: 0.0,
-            bandpass_x2: 0.0,
-            bandpass_y1: 0.0,
-            bandpass_y2: 0.0,
-            band_filters: BandSplitFilters::new(),
-            band1_compressor: BandCompressor::new(),
-            band2_compressor: BandCompressor::new(),
-            band3_compressor: BandCompressor::new(),
+    /// Samples between input and output, summed over the stages
+    pub fn latency(&self) -> usize {
+        self.stages.iter().map(|stage| stage.latency()).sum()
+    }
+
+    /// Level and per-stage statistics since creation or the last reset
+    pub fn stats(&self) -> ProcessorStats {
+        ProcessorStats {
+            samples: self.samples,
+            input: self.input,
+            output: self.output,
+            stages: self.stages.iter().map(|stage| (stage.name(), stage.stats())).collect(),
         }
     }
     
     /// Process audio sample through the telephony pipeline
     pub fn process_sample(&mut self, input: f32) -> f32 {
-        // Step 1: Preemphasis filter (boost high frequencies)
-        let preemphasized = self.preemphasis_filter(input);
-        
-        // Step 2: Bandpass filter (300-3400Hz for telephony)
-        let bandpassed = self.bandpass_filter(preemphasized);
-        
-        // Step 3: 3-band dynamic range compression
-        let compressed = self.three_band_compressor(bandpassed);
+        self.samples += 1;
+        self.input.observe(input);
         
-        // Step 4: Noise gate
-        let gated = self.noise_gate(compressed);
+        let output = self.stages.iter_mut().fold(input, |sample, stage| stage.process_sample(sample));
         
-        // Step 5: Final limiting to prevent clipping
-        self.soft_limiter(gated)
-    }
-    
-    /// Preemphasis filter - boosts high frequencies for better telephony transmission
-    fn preemphasis_filter(&mut self, input: f32) -> f32 {
-        // Simple first-order high-pass filter with configurable alpha
-        let alpha = self.config.preemphasis_alpha;
-        let output = input - alpha * self.preemphasis_prev;
-        self.preemphasis_prev = input;
+        self.output.observe(output);
         output
     }
-    
-    /// Bandpass filter (configurable telephony bandwidth)
-    fn bandpass_filter(&mut self, input: f32) -> f32 {
-        // 2nd order Butterworth bandpass filter coefficients with configurable frequencies
-        let low_freq: f32 = self.config.bandpass_low_freq;
-        let high_freq: f32 = self.config.bandpass_high_freq;
-        let nyquist = self.sample_rate / 2.0;
-        
-        // Ensure frequencies are within Nyquist limit
-        let low_freq = low_freq.min(nyquist * 0.95);
-        let high_freq = high_freq.min(nyquist * 0.95);
-        
-        // Normalized frequencies (0 to 1, where 1 is Nyquist)
-        let wc1 = low_freq / nyquist;
-        let wc2 = high_freq / nyquist;
-        
-        // Pre-warped frequencies for bilinear transform
-        let wc1_pre = (std::f32::consts::PI * wc1 / 2.0).tan();
-        let wc2_pre = (std::f32::consts::PI * wc2 / 2.0).tan();
-        
-        // Bandpass filter design using proper bilinear transform
-        let bw = wc2_pre - wc1_pre;
-        let wc = (wc1_pre * wc2_pre).sqrt();
-        
-        // Second-order bandpass coefficients
-        let norm = 1.0 + bw + wc * wc;
-        let b0 = bw / norm;
-        let b1 = 0.0;
-        let b2 = -bw / norm;
-        let a1 = (2.0 * (wc * wc - 1.0)) / norm;
-        let a2 = (1.0 - bw + wc * wc) / norm;
-        
-        // Apply filter (Direct Form II)
-        let output = b0 * input + b1 * self.bandpass_x1 + b2 * self.bandpass_x2 
-                   - a1 * self.bandpass_y1 - a2 * self.bandpass_y2;
-        
-        // Update state variables
-        self.bandpass_x2 = self.bandpass_x1;
-        self.bandpass_x1 = input;
-        self.bandpass_y2 = self.bandpass_y1;
-        self.bandpass_y1 = output;
-        
-        // Prevent NaN/Inf propagation
-        if output.is_finite() { output } else { 0.0 }
+
+    /// Process a block of samples in place, one stage at a time
+    pub fn process_block(&mut self, block: &mut [f32]) {
+        self.samples += block.len() as u64;
+        block.iter().for_each(|&sample| self.input.observe(sample));
+
+        for stage in &mut self.stages {
+            stage.process_block(block);
+        }
+
+        block.iter().for_each(|&sample| self.output.observe(sample));
     }
     
-    /// Apply low-pass filter for band splitting
-    fn apply_lowpass_filter(input: f32, cutoff_freq: f32, sample_rate: f32, x1: &mut f32, x2: &mut f32, y1: &mut f32, y2: &mut f32) -> f32 {
-        let nyquist = sample_rate / 2.0;
-        let wc = cutoff_freq / nyquist;
-        let wc_pre = (std::f32::consts::PI * wc / 2.0).tan();
-        
-        // 2nd order Butterworth low-pass coefficients
-        let norm = 1.0 + std::f32::consts::SQRT_2 * wc_pre + wc_pre * wc_pre;
-        let b0 = wc_pre * wc_pre / norm;
-        let b1 = 2.0 * b0;
-        let b2 = b0;
-        let a1 = (2.0 * (wc_pre * wc_pre - 1.0)) / norm;
-        let a2 = (1.0 - std::f32::consts::SQRT_2 * wc_pre + wc_pre * wc_pre) / norm;
-        
-        // Apply filter
-        let output = b0 * input + b1 * *x1 + b2 * *x2 - a1 * *y1 - a2 * *y2;
-        
-        // Update state
-        *x2 = *x1;
-        *x1 = input;
-        *y2 = *y1;
-        *y1 = output;
-        
-        if output.is_finite() { output } else { 0.0 }
+    /// Reset all filter states
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {
+            stage.reset();
+        }
+        self.samples = 0;
+        self.input = LevelStats::default();
+        self.output = LevelStats::default();
     }
-    
-    /// Apply high-pass filter for band splitting
-    fn apply_highpass_filter(input: f32, cutoff_freq: f32, sample_rate: f32, x1: &mut f32, x2: &mut f32, y1: &mut f32, y2: &mut f32) -> f32 {
-        let nyquist = sample_rate / 2.0;
-        let wc = cutoff_freq / nyquist;
-        let wc_pre = (std::f32::consts::PI * wc / 2.0).tan();
-        
-        // 2nd order Butterworth high-pass coefficients
-        let norm = 1.0 + std::f32::consts::SQRT_2 * wc_pre + wc_pre * wc_pre;
-        let b0 = 1.0 / norm;
-        let b1 = -2.0 * b0;
-        let b2 = b0;
-        let a1 = (2.0 * (wc_pre * wc_pre - 1.0)) / norm;
-        let a2 = (1.0 - std::f32::consts::SQRT_2 * wc_pre + wc_pre * wc_pre) / norm;
-        
-        // Apply filter
-        let output = b0 * input + b1 * *x1 + b2 * *x2 - a1 * *y1 - a2 * *y2;
-        
-        // Update state
-        *x2 = *x1;
-        *x1 = input;
-        *y2 = *y1;
-        *y1 = output;
-        
-        if output.is_finite() { output } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_downmix_modes() {
+        let left = [1.0, 0.5, 0.0];
+        let right = [0.0, 0.5, -1.0];
+        let stereo: [&[f32]; 2] = [&left, &right];
+
+        assert_eq!(downmix(&stereo, DownmixMode::Left), left.to_vec());
+        assert_eq!(downmix(&stereo, DownmixMode::Right), right.to_vec());
+        assert_eq!(downmix(&stereo, DownmixMode::Average), vec![0.5, 0.5, -0.5]);
+
+        let mid = downmix(&stereo, DownmixMode::Mid);
+        assert!((mid[0] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert!((mid[1] - std::f32::consts::SQRT_2 * 0.5).abs() < 1e-6);
     }
-    
-    /// Apply band-pass filter for band splitting
-    fn apply_bandpass_filter(input: f32, low_freq: f32, high_freq: f32, sample_rate: f32, x1: &mut f32, x2: &mut f32, y1: &mut f32, y2: &mut f32) -> f32 {
-        let nyquist = sample_rate / 2.0;
-        let wc1 = low_freq / nyquist;
-        let wc2 = high_freq / nyquist;
-        
-        let wc1_pre = (std::f32::consts::PI * wc1 / 2.0).tan();
-        let wc2_pre = (std::f32::consts::PI * wc2 / 2.0).tan();
-        
-        // Bandpass filter design
-        let bw = wc2_pre - wc1_pre;
-        let wc = (wc1_pre * wc2_pre).sqrt();
-        
-        // 2nd order bandpass coefficients
-        let norm = 1.0 + bw + wc * wc;
-        let b0 = bw / norm;
-        let b1 = 0.0;
-        let b2 = -bw / norm;
-        let a1 = (2.0 * (wc * wc - 1.0)) / norm;
-        let a2 = (1.0 - bw + wc * wc) / norm;
-        
-        // Apply filter
-        let output = b0 * input + b1 * *x1 + b2 * *x2 - a1 * *y1 - a2 * *y2;
-        
-        // Update state
-        *x2 = *x1;
-        *x1 = input;
-        *y2 = *y1;
-        *y1 = output;
-        
-        if output.is_finite() { output } else { 0.0 }
+
+    #[test]
+    fn test_downmix_mid_full_scale() {
+        let full_scale = [1.0, -1.0, 0.5];
+        let stereo: [&[f32]; 2] = [&full_scale, &full_scale];
+
+        let mid = downmix(&stereo, DownmixMode::Mid);
+        assert_eq!(&mid[..2], &[1.0, -1.0]);
+        assert!((mid[2] - std::f32::consts::SQRT_2 * 0.5).abs() < 1e-6);
     }
-    
-    /// 3-band dynamic range compressor for consistent volume levels
-    fn three_band_compressor(&mut self, input: f32) -> f32 {
-        // Split the input into 3 frequency bands
-        let (band1, band2, band3) = self.split_into_bands(input);
-        
-        // Extract needed values to avoid borrowing conflicts
-        let sample_rate = self.sample_rate;
-        let band1_config = self.config.band1_compressor.clone();
-        let band2_config = self.config.band2_compressor.clone();
-        let band3_config = self.config.band3_compressor.clone();
-        
-        // Apply compression to each band independently
-        let compressed_band1 = Self::compress_band(band1, &band1_config, &mut self.band1_compressor, sample_rate);
-        let compressed_band2 = Self::compress_band(band2, &band2_config, &mut self.band2_compressor, sample_rate);
-        let compressed_band3 = Self::compress_band(band3, &band3_config, &mut self.band3_compressor, sample_rate);
-        
-        // Combine the bands back together
-        let combined = compressed_band1 + compressed_band2 + compressed_band3;
-        
-        // Prevent NaN/Inf propagation
-        if combined.is_finite() { combined } else { 0.0 }
+
+    #[test]
+    fn test_downmix_mono_source() {
+        let mono = [0.25, -0.25];
+        let planes: [&[f32]; 1] = [&mono];
+
+        for mode in [DownmixMode::Average, DownmixMode::Left, DownmixMode::Right, DownmixMode::Mid] {
+            assert_eq!(downmix(&planes, mode), mono.to_vec(), "{:?}", mode);
+        }
     }
-    
-    /// Split audio into 3 frequency bands
-    fn split_into_bands(&mut self, input: f32) -> (f32, f32, f32) {
-        let nyquist = self.sample_rate / 2.0;
-        let split_freq_1 = self.config.band_split_freq_1.min(nyquist * 0.95);
-        let split_freq_2 = self.config.band_split_freq_2.min(nyquist * 0.95);
-        let sample_rate = self.sample_rate;
-        
-        // Band 1: Low-pass filter (300Hz - split_freq_1)
-        let band1 = Self::apply_lowpass_filter(input, split_freq_1, sample_rate,
-            &mut self.band_filters.lowpass1_x1, &mut self.band_filters.lowpass1_x2,
-            &mut self.band_filters.lowpass1_y1, &mut self.band_filters.lowpass1_y2);
-        
-        // Band 3: High-pass filter (split_freq_2 - 3400Hz)
-        let band3 = Self::apply_highpass_filter(input, split_freq_2, sample_rate,
-            &mut self.band_filters.highpass2_x1, &mut self.band_filters.highpass2_x2,
-            &mut self.band_filters.highpass2_y1, &mut self.band_filters.highpass2_y2);
-        
-        // Band 2: Bandpass filter (split_freq_1 - split_freq_2)
-        let band2 = Self::apply_bandpass_filter(input, split_freq_1, split_freq_2, sample_rate,
-            &mut self.band_filters.bandpass2_x1, &mut self.band_filters.bandpass2_x2,
-            &mut self.band_filters.bandpass2_y1, &mut self.band_filters.bandpass2_y2);
-        
-        (band1, band2, band3)
+
+    #[test]
+    fn test_downmix_surround_average() {
+        let planes: Vec<Vec<f32>> = (0..6).map(|c| vec![c as f32]).collect();
+        let planes: Vec<&[f32]> = planes.iter().map(Vec::as_slice).collect();
+
+        assert_eq!(downmix(&planes, DownmixMode::Average), vec![2.5]);
+        assert_eq!(downmix(&planes, DownmixMode::Right), vec![1.0]);
     }
-    
-    /// Apply compression to a single band
-    fn compress_band(input: f32, config: &CompressorBandConfig, compressor: &mut BandCompressor, sample_rate: f32) -> f32 {
-        if !config.enabled {
-            return input;
-        }
-        
-        let input_level = input.abs();
-        let target_level = config.target_level;
-        let attack_time = config.attack_time;
-        let release_time = config.release_time;
-        
-        let attack_coeff = (-1.0 / (attack_time * sample_rate)).exp();
-        let release_coeff = (-1.0 / (release_time * sample_rate)).exp();
-        
-        // Envelope follower with proper attack/release
-        if input_level > compressor.envelope {
-            compressor.envelope = attack_coeff * compressor.envelope + (1.0 - attack_coeff) * input_level;
-        } else {
-            compressor.envelope = release_coeff * compressor.envelope + (1.0 - release_coeff) * input_level;
+
+    #[test]
+    fn test_processor_stats() {
+        let mut processor = TelephonyAudioProcessor::new(8000.0, AudioProcessingConfig::default());
+        for i in 0..8000 {
+            let t = i as f32 / 8000.0;
+            processor.process_sample(0.9 * (2.0 * std::f32::consts::PI * 1400.0 * t).sin());
         }
-        
-        // Professional compressor with proper knee
-        let ratio = config.ratio;
-        let threshold = target_level * config.threshold_factor;
-        let knee_width = config.knee_width;
-        
-        let gain = if compressor.envelope > threshold {
-            let excess = compressor.envelope - threshold;
-            
-            // Soft knee compression
-            let knee_ratio = if excess < knee_width {
-                1.0 + (ratio - 1.0) * (excess / knee_width).powi(2)
-            } else {
-                ratio
-            };
-            
-            let compressed_excess = excess / knee_ratio;
-            let compressed_level = threshold + compressed_excess;
-            
-            // Calculate gain reduction
-            if compressor.envelope > 1e-10 {
-                compressed_level / compressor.envelope
-            } else {
-                1.0
-            }
-        } else {
-            // Gentle makeup gain for quiet signals
-            let makeup_gain = (target_level / (threshold + 1e-10)).min(1.2);
-            makeup_gain
-        };
-        
-        // Apply gain with safety limits
-        let output = input * gain.clamp(0.1, 2.0);
-        
-        // Prevent NaN/Inf propagation
-        if output.is_finite() { output } else { 0.0 }
+
+        let stats = processor.stats();
+        assert_eq!(stats.samples, 8000);
+        assert!((stats.input.peak - 0.9).abs() < 0.01);
+        assert!((stats.input.rms_dbfs() - audio_stages::to_dbfs(0.9 / std::f32::consts::SQRT_2)).abs() < 0.1);
+
+        // A loud tone in the middle of the 800-2500Hz band drives it into compression
+        let (_, compressor) = stats.stages.iter().find(|(name, _)| *name == "compressor").unwrap();
+        let mid = &compressor.bands[1];
+        assert!(mid.compressed_samples > 0);
+        assert!(mid.max_gain_reduction_db > 0.0);
+        assert!(mid.level.rms() > compressor.bands[0].level.rms());
+
+        processor.reset();
+        assert_eq!(processor.stats().samples, 0);
     }
-    
-    /// Noise gate to reduce background noise
-    fn noise_gate(&mut self, input: f32) -> f32 {
-        let input_level = input.abs();
-        
-        if input_level < self.config.noise_gate_threshold {
-            input * self.config.noise_gate_ratio
-        } else {
-            input
+
+    #[test]
+    fn test_conversion_compensates_limiter_latency() {
+        use crate::config::{AudioStageConfig, StageKind};
+
+        let dir = tempfile::tempdir().unwrap();
+        let input_path = dir.path().join("burst.wav");
+        let output_path = dir.path().join("burst.processed.wav");
+
+        // A tone burst between two stretches of silence, already at the target rate
+        let input: Vec<i16> = (0..4000)
+            .map(|i| match i {
+                1000..3000 => (8000.0 * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 8000.0).sin()) as i16,
+                _ => 0,
+            })
+            .collect();
+        let spec = WavSpec { channels: 1, sample_rate: 8000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = WavWriter::create(&input_path, spec).unwrap();
+        input.iter().for_each(|&sample| writer.write_sample(sample).unwrap());
+        writer.finalize().unwrap();
+
+        let config = AudioProcessingConfig {
+            stages: vec![AudioStageConfig {
+                enabled: true,
+                kind: StageKind::Limiter { ceiling_dbfs: -1.0, release_time: 0.05, lookahead_time: 0.002 },
+            }],
+            ..AudioProcessingConfig::default()
+        };
+        assert!(TelephonyAudioProcessor::new(8000.0, config.clone()).latency() > 0);
+        convert_to_telephony_wav(&input_path, &output_path, &config, 8000, 1, 0).unwrap();
+
+        // The burst stays below the ceiling, so the limiter output is the input, sample for sample
+        let output: Vec<i16> = hound::WavReader::open(&output_path).unwrap()
+            .into_samples::<i16>()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(output.len(), input.len());
+        for (i, (&out, &expected)) in output.iter().zip(&input).enumerate() {
+            assert!((out as i32 - expected as i32).abs() <= 1, "sample {}: {} != {}", i, out, expected);
         }
     }
-    
-    /// Soft limiter to prevent clipping
-    fn soft_limiter(&self, input: f32) -> f32 {
-        let threshold = self.config.soft_limiter_threshold;
-        
-        if input.abs() > threshold {
-            threshold * input.signum() * (1.0 - (-3.0 * (input.abs() - threshold)).exp())
-        } else {
-            input
-        }
+
+    #[test]
+    fn test_render_fingerprint_tracks_settings() {
+        let config = AudioProcessingConfig::default();
+        let fingerprint = render_fingerprint(&config, 8000, 1, 30).unwrap();
+        assert_eq!(fingerprint, render_fingerprint(&config.clone(), 8000, 1, 30).unwrap());
+
+        assert_ne!(fingerprint, render_fingerprint(&config, 8000, 1, 60).unwrap());
+        assert_ne!(fingerprint, render_fingerprint(&config, 16000, 1, 30).unwrap());
+
+        let mut loudness = config.clone();
+        loudness.loudness.enabled = !loudness.loudness.enabled;
+        assert_ne!(fingerprint, render_fingerprint(&loudness, 8000, 1, 30).unwrap());
+
+        let mut gate = config;
+        gate.noise_gate.open_threshold *= 2.0;
+        assert_ne!(fingerprint, render_fingerprint(&gate, 8000, 1, 30).unwrap());
     }
-    
-    /// Reset all filter states
-    pub fn reset(&mut self) {
-        self.preemphasis_prev = 0.0;
-        self.bandpass_x1 = 0.0;
-        self.bandpass_x2 = 0.0;
-        self.bandpass_y1 = 0.0;
-        self.bandpass_y2 = 0.0;
-        
-        // Reset band filter states
-        self.band_filters.lowpass1_x1 = 0.0;
-        self.band_filters.lowpass1_x2 = 0.0;
-        self.band_filters.lowpass1_y1 = 0.0;
-        self.band_filters.lowpass1_y2 = 0.0;
-        self.band_filters.highpass2_x1 = 0.0;
-        self.band_filters.highpass2_x2 = 0.0;
-        self.band_filters.highpass2_y1 = 0.0;
-        self.band_filters.highpass2_y2 = 0.0;
-        self.band_filters.bandpass2_x1 = 0.0;
-        self.band_filters.bandpass2_x2 = 0.0;
-        self.band_filters.bandpass2_y1 = 0.0;
-        self.band_filters.bandpass2_y2 = 0.0;
-        
-        // Reset compressor states
-        self.band1_compressor.envelope = 0.0;
-        self.band2_compressor.envelope = 0.0;
-        self.band3_compressor.envelope = 0.0;
+
+    #[test]
+    fn test_custom_stage_chain() {
+        struct Gain(f32);
+
+        impl AudioStage for Gain {
+            fn name(&self) -> &'static str {
+                "gain"
+            }
+
+            fn process_sample(&mut self, input: f32) -> f32 {
+                input * self.0
+            }
+
+            fn reset(&mut self) {}
+        }
+
+        let mut processor = TelephonyAudioProcessor::with_stages(vec![Box::new(Gain(0.5)), Box::new(Gain(3.0))]);
+        assert_eq!(processor.stage_names(), ["gain", "gain"]);
+        assert_eq!(processor.process_sample(0.25), 0.375);
     }
 }
-
- 
\ No newline at end of file
@@ -0,0 +1,82 @@
+//! Outbound calls placed by the server, and what became of them
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// What happened to one originated call
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CallOutcome {
+    /// Time from sending the INVITE to the answer
+    pub answer_time: Option<Duration>,
+    /// Time from sending the INVITE to ringing, or to the answer without ringing
+    pub post_dial_delay: Option<Duration>,
+    /// Why an unanswered call failed
+    pub failure: Option<String>,
+    /// Time from the answer to the end of the call
+    pub talk_time: Duration,
+}
+
+impl CallOutcome {
+    pub fn failed(reason: impl Into<String>) -> Self {
+        Self { failure: Some(reason.into()), ..Self::default() }
+    }
+}
+
+/// Totals of a batch of originated calls
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct OriginationSummary {
+    pub placed: u32,
+    pub answered: u32,
+    pub failed: u32,
+    /// Failed calls by reason
+    pub failures: BTreeMap<String, u32>,
+    /// Mean time to answer of the answered calls
+    pub mean_answer_ms: f64,
+    pub talk_seconds: f64,
+}
+
+impl OriginationSummary {
+    pub fn record(&mut self, outcome: &CallOutcome) {
+        self.placed += 1;
+        match outcome.answer_time {
+            Some(answer_time) => {
+                self.answered += 1;
+                let answer_ms = answer_time.as_secs_f64() * 1000.0;
+                self.mean_answer_ms += (answer_ms - self.mean_answer_ms) / self.answered as f64;
+                self.talk_seconds += outcome.talk_time.as_secs_f64();
+            }
+            None => {
+                self.failed += 1;
+                let reason = outcome.failure.clone().unwrap_or_else(|| "unknown".to_string());
+                *self.failures.entry(reason).or_default() += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let answered = |ms, talk| CallOutcome {
+            answer_time: Some(Duration::from_millis(ms)),
+            post_dial_delay: Some(Duration::from_millis(ms)),
+            failure: None,
+            talk_time: Duration::from_secs(talk),
+        };
+        let mut summary = OriginationSummary::default();
+        for outcome in [answered(100, 10), CallOutcome::failed("486 Busy Here"), answered(300, 20),
+                        CallOutcome::failed("486 Busy Here"), CallOutcome::failed("No answer")] {
+            summary.record(&outcome);
+        }
+
+        assert_eq!((summary.placed, summary.answered, summary.failed), (5, 2, 3));
+        assert!((summary.mean_answer_ms - 200.0).abs() < 1e-9);
+        assert_eq!(summary.talk_seconds, 30.0);
+        assert_eq!(summary.failures.get("486 Busy Here"), Some(&2));
+        assert_eq!(summary.failures.len(), 2);
+    }
+}
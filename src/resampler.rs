@@ -0,0 +1,288 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Trade-off between CPU cost and filter sharpness of the resampler
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResamplerQuality {
+    Low,
+    Medium,
+    #[default]
+    High,
+}
+
+impl ResamplerQuality {
+    /// (zero crossings per side, Kaiser beta, cutoff as a fraction of the lower Nyquist)
+    fn parameters(self) -> (usize, f64, f64) {
+        match self {
+            ResamplerQuality::Low => (8, 5.0, 0.80),
+            ResamplerQuality::Medium => (16, 7.0, 0.86),
+            ResamplerQuality::High => (32, 9.5, 0.92),
+        }
+    }
+}
+
+/// Kernel table entries per zero crossing; finer phases are linearly interpolated
+const TABLE_RESOLUTION: usize = 512;
+
+/// Band-limited (Kaiser-windowed sinc) resampler for arbitrary rate ratios
+///
+/// The low-pass cutoff follows the lower of the two Nyquist frequencies, so the
+/// same filter serves as anti-alias filter when downsampling and as anti-image
+/// filter when upsampling. Each output sample is computed at its exact fractional
+/// input position.
+pub struct Resampler {
+    /// Input samples advanced per output sample
+    step: f64,
+    /// Normalised cutoff in cycles per input sample
+    cutoff: f64,
+    /// Kernel half-width in input samples
+    half_width: f64,
+    zero_crossings: usize,
+    /// Windowed sinc sampled at `TABLE_RESOLUTION` points per zero crossing
+    table: Vec<f32>,
+    /// Pending input, starting with zero padding before the first real sample
+    buffer: Vec<f32>,
+    /// Position of the next output sample within `buffer`
+    time: f64,
+    /// Total input samples received, used to bound the flushed tail
+    input_count: u64,
+    /// Total output samples produced
+    output_count: u64,
+    passthrough: bool,
+}
+
+impl Resampler {
+    pub fn new(source_rate: u32, target_rate: u32, quality: ResamplerQuality) -> Self {
+        let (zero_crossings, beta, rolloff) = quality.parameters();
+        let ratio = target_rate as f64 / source_rate as f64;
+        let cutoff = 0.5 * ratio.min(1.0) * rolloff;
+        let half_width = zero_crossings as f64 / (2.0 * cutoff);
+
+        let table_len = zero_crossings * TABLE_RESOLUTION + 2;
+        let i0_beta = bessel_i0(beta);
+        let table = (0..table_len)
+            .map(|i| {
+                let z = i as f64 / TABLE_RESOLUTION as f64;
+                let x = z / zero_crossings as f64;
+                let window = if x < 1.0 { bessel_i0(beta * (1.0 - x * x).sqrt()) / i0_beta } else { 0.0 };
+                (sinc(z) * window) as f32
+            })
+            .collect();
+
+        let padding = half_width.ceil() as usize;
+
+        Self {
+            step: 1.0 / ratio,
+            cutoff,
+            half_width,
+            zero_crossings,
+            table,
+            buffer: vec![0.0; padding],
+            time: padding as f64,
+            input_count: 0,
+            output_count: 0,
+            passthrough: source_rate == target_rate,
+        }
+    }
+
+    /// Resample a block of input, returning every output sample that is fully determined so far
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.passthrough {
+            return input.to_vec();
+        }
+
+        self.input_count += input.len() as u64;
+        self.buffer.extend_from_slice(input);
+        self.drain(u64::MAX)
+    }
+
+    /// Emit the remaining outputs covering the end of the input
+    pub fn flush(&mut self) -> Vec<f32> {
+        if self.passthrough {
+            return Vec::new();
+        }
+
+        let total_outputs = (self.input_count as f64 / self.step).ceil() as u64;
+        let padding = self.half_width.ceil() as usize + 1;
+        self.buffer.resize(self.buffer.len() + padding, 0.0);
+        self.drain(total_outputs)
+    }
+
+    fn drain(&mut self, max_outputs: u64) -> Vec<f32> {
+        let mut output = Vec::new();
+
+        while self.output_count < max_outputs && self.time + self.half_width < self.buffer.len() as f64 {
+            output.push(self.interpolate(self.time));
+            self.time += self.step;
+            self.output_count += 1;
+        }
+
+        // Drop input that can no longer contribute to future outputs
+        let keep_from = (self.time - self.half_width).floor().max(0.0) as usize;
+        if keep_from > 0 {
+            self.buffer.drain(..keep_from);
+            self.time -= keep_from as f64;
+        }
+
+        output
+    }
+
+    fn interpolate(&self, time: f64) -> f32 {
+        let first = (time - self.half_width).ceil().max(0.0) as usize;
+        let last = ((time + self.half_width).floor() as usize).min(self.buffer.len() - 1);
+        let scale = 2.0 * self.cutoff * TABLE_RESOLUTION as f64;
+        let table_limit = (self.zero_crossings * TABLE_RESOLUTION) as f64;
+
+        let mut sum = 0.0f32;
+        for (k, &x) in self.buffer[first..=last].iter().enumerate() {
+            let position = ((first + k) as f64 - time).abs() * scale;
+            if position >= table_limit {
+                continue;
+            }
+
+            let index = position as usize;
+            let frac = (position - index as f64) as f32;
+            let coefficient = self.table[index] + (self.table[index + 1] - self.table[index]) * frac;
+            sum += x * coefficient;
+        }
+
+        sum * (2.0 * self.cutoff) as f32
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-12 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Zeroth-order modified Bessel function of the first kind (for the Kaiser window)
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+    for k in 1..50 {
+        term *= (half_x / k as f64) * (half_x / k as f64);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f64, sample_rate: u32, seconds: f64) -> Vec<f32> {
+        let count = (sample_rate as f64 * seconds) as usize;
+        (0..count)
+            .map(|i| (0.5 * (2.0 * PI * freq * i as f64 / sample_rate as f64).sin()) as f32)
+            .collect()
+    }
+
+    fn resample(input: &[f32], source_rate: u32, target_rate: u32, quality: ResamplerQuality) -> Vec<f32> {
+        let mut resampler = Resampler::new(source_rate, target_rate, quality);
+        // Feed in uneven chunks to exercise the streaming state
+        let mut output = Vec::new();
+        for chunk in input.chunks(1153) {
+            output.extend(resampler.process(chunk));
+        }
+        output.extend(resampler.flush());
+        output
+    }
+
+    /// Amplitude of a frequency component (single-bin DFT), skipping filter settling at the edges
+    fn amplitude_at(samples: &[f32], freq: f64, sample_rate: u32) -> f64 {
+        let skip = samples.len() / 10;
+        let window = &samples[skip..samples.len() - skip];
+        let (re, im) = window.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, &s)| {
+            let phase = 2.0 * PI * freq * i as f64 / sample_rate as f64;
+            (re + s as f64 * phase.cos(), im + s as f64 * phase.sin())
+        });
+        2.0 * (re * re + im * im).sqrt() / window.len() as f64
+    }
+
+    fn db(ratio: f64) -> f64 {
+        20.0 * ratio.log10()
+    }
+
+    #[test]
+    fn test_output_length() {
+        for (source, target) in [(44100, 8000), (8000, 44100), (48000, 8000), (8000, 16000), (11025, 8000)] {
+            let input = sine(440.0, source, 1.0);
+            let output = resample(&input, source, target, ResamplerQuality::Medium);
+            assert_eq!(output.len(), target as usize, "{} -> {}", source, target);
+        }
+    }
+
+    #[test]
+    fn test_passband_preserved() {
+        let input = sine(1000.0, 44100, 1.0);
+        let output = resample(&input, 44100, 8000, ResamplerQuality::High);
+        let gain = db(amplitude_at(&output, 1000.0, 8000) / 0.5);
+        assert!(gain.abs() < 0.1, "passband gain {:.3}dB", gain);
+    }
+
+    #[test]
+    fn test_downsampling_stopband_rejection() {
+        // Tones above the 4kHz target Nyquist must not alias back into the band
+        for (quality, min_rejection) in [
+            (ResamplerQuality::Low, 55.0),
+            (ResamplerQuality::Medium, 75.0),
+            (ResamplerQuality::High, 95.0),
+        ] {
+            for freq in [5000.0, 6000.0, 10000.0, 15000.0] {
+                let input = sine(freq, 44100, 1.0);
+                let output = resample(&input, 44100, 8000, quality);
+
+                // Skip the onset and cut-off clicks, which are genuinely broadband
+                let settled = &output[800..output.len() - 800];
+                let rms = (settled.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / settled.len() as f64).sqrt();
+                let rejection = -db(rms * 2f64.sqrt() / 0.5);
+                assert!(rejection > min_rejection,
+                    "{:?}: {}Hz only rejected by {:.1}dB", quality, freq, rejection);
+            }
+        }
+    }
+
+    #[test]
+    fn test_upsampling_image_rejection() {
+        let input = sine(1000.0, 8000, 1.0);
+        let output = resample(&input, 8000, 44100, ResamplerQuality::High);
+
+        let wanted = amplitude_at(&output, 1000.0, 44100);
+        assert!(db(wanted / 0.5).abs() < 0.1);
+
+        // Spectral images of the 1kHz tone around multiples of 8kHz
+        for image in [7000.0, 9000.0, 15000.0, 17000.0] {
+            let rejection = db(wanted / amplitude_at(&output, image, 44100));
+            assert!(rejection > 80.0, "image at {}Hz only rejected by {:.1}dB", image, rejection);
+        }
+    }
+
+    #[test]
+    fn test_fractional_phase_alignment() {
+        // A band-limited ramp should come out time-aligned, i.e. output n sits at input n * step
+        let input = sine(100.0, 44100, 0.5);
+        let output = resample(&input, 44100, 8000, ResamplerQuality::High);
+
+        for (n, &y) in output.iter().enumerate().skip(400).take(2000) {
+            let t = n as f64 / 8000.0;
+            let expected = 0.5 * (2.0 * PI * 100.0 * t).sin();
+            assert!((y as f64 - expected).abs() < 1e-3, "sample {} off by {}", n, y as f64 - expected);
+        }
+    }
+
+    #[test]
+    fn test_same_rate_passthrough() {
+        let input = sine(440.0, 8000, 0.1);
+        let mut resampler = Resampler::new(8000, 8000, ResamplerQuality::High);
+        assert_eq!(resampler.process(&input), input);
+        assert!(resampler.flush().is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// One poll of a call's RTP session, as reported by the media layer
+///
+/// Counters are cumulative since the media session started.
+#[derive(Debug, Clone, Default)]
+pub struct RtpSample {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub packets_lost: u64,
+    pub packets_out_of_order: u64,
+    pub jitter_ms: f64,
+    /// Round trip from the latest RTCP report, if one has arrived
+    pub rtt_ms: Option<f64>,
+}
+
+/// RTP/RTCP summary of one call
+///
+/// SSRC changes and discarded packets are not counted: the client API reports neither.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RtpCallStats {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub packets_lost: u64,
+    /// Lost packets out of those expected
+    pub loss_ratio: f64,
+    pub packets_out_of_order: u64,
+    pub jitter_ms_mean: f64,
+    pub jitter_ms_max: f64,
+    /// Longest time without a new packet once media had started (poll resolution)
+    pub max_gap_ms: u64,
+    pub rtt_ms_mean: Option<f64>,
+    pub rtt_ms_max: Option<f64>,
+    /// Number of polls the summary is based on
+    pub samples: u64,
+}
+
+/// Cumulative counters, carried across media session restarts
+#[derive(Debug, Clone, Copy, Default)]
+struct Counters {
+    sent: u64,
+    received: u64,
+    lost: u64,
+    out_of_order: u64,
+}
+
+impl Counters {
+    fn of(sample: &RtpSample) -> Self {
+        Self {
+            sent: sample.packets_sent,
+            received: sample.packets_received,
+            lost: sample.packets_lost,
+            out_of_order: sample.packets_out_of_order,
+        }
+    }
+
+    fn plus(self, other: Self) -> Self {
+        Self {
+            sent: self.sent + other.sent,
+            received: self.received + other.received,
+            lost: self.lost + other.lost,
+            out_of_order: self.out_of_order + other.out_of_order,
+        }
+    }
+}
+
+/// Builds a call's `RtpCallStats` from periodic `RtpSample`s
+#[derive(Debug, Default)]
+pub struct RtpStatsCollector {
+    /// Totals of media sessions that have since been replaced
+    carried: Counters,
+    latest: Counters,
+    samples: u64,
+    jitter_sum: f64,
+    jitter_samples: u64,
+    jitter_max: f64,
+    rtt_sum: f64,
+    rtt_samples: u64,
+    rtt_max: f64,
+    /// When the received count last moved
+    last_progress: Option<Duration>,
+    max_gap: Duration,
+}
+
+impl RtpStatsCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a poll taken `elapsed` after the call's media started
+    pub fn observe(&mut self, sample: &RtpSample, elapsed: Duration) {
+        let counters = Counters::of(sample);
+        if counters.received < self.latest.received || counters.sent < self.latest.sent {
+            // The media session was recreated and its counters restarted
+            self.carried = self.carried.plus(self.latest);
+            self.latest = Counters::default();
+        }
+
+        let received_more = counters.received > self.latest.received;
+        if received_more {
+            self.last_progress = Some(elapsed);
+            self.jitter_sum += sample.jitter_ms;
+            self.jitter_samples += 1;
+            self.jitter_max = self.jitter_max.max(sample.jitter_ms);
+        } else if let Some(progress) = self.last_progress {
+            self.max_gap = self.max_gap.max(elapsed.saturating_sub(progress));
+        }
+        self.latest = counters;
+        self.samples += 1;
+
+        if let Some(rtt) = sample.rtt_ms {
+            self.rtt_sum += rtt;
+            self.rtt_samples += 1;
+            self.rtt_max = self.rtt_max.max(rtt);
+        }
+    }
+
+    pub fn summary(&self) -> RtpCallStats {
+        let totals = self.carried.plus(self.latest);
+        let expected = totals.received + totals.lost;
+
+        RtpCallStats {
+            packets_sent: totals.sent,
+            packets_received: totals.received,
+            packets_lost: totals.lost,
+            loss_ratio: if expected == 0 { 0.0 } else { totals.lost as f64 / expected as f64 },
+            packets_out_of_order: totals.out_of_order,
+            jitter_ms_mean: if self.jitter_samples == 0 { 0.0 } else { self.jitter_sum / self.jitter_samples as f64 },
+            jitter_ms_max: self.jitter_max,
+            max_gap_ms: self.max_gap.as_millis() as u64,
+            rtt_ms_mean: (self.rtt_samples > 0).then(|| self.rtt_sum / self.rtt_samples as f64),
+            rtt_ms_max: (self.rtt_samples > 0).then_some(self.rtt_max),
+            samples: self.samples,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(sent: u64, received: u64, lost: u64, jitter_ms: f64) -> RtpSample {
+        RtpSample {
+            packets_sent: sent,
+            packets_received: received,
+            packets_lost: lost,
+            jitter_ms,
+            ..RtpSample::default()
+        }
+    }
+
+    fn at(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_summary_of_a_call() {
+        let mut collector = RtpStatsCollector::new();
+        collector.observe(&sample(0, 0, 0, 0.0), at(0));
+        collector.observe(&sample(50, 48, 2, 4.0), at(1000));
+        // Nothing arrives for two polls
+        collector.observe(&sample(100, 48, 2, 4.0), at(2000));
+        collector.observe(&sample(150, 48, 2, 4.0), at(3000));
+        collector.observe(&RtpSample { rtt_ms: Some(80.0), ..sample(200, 96, 4, 10.0) }, at(4000));
+        collector.observe(&RtpSample { rtt_ms: Some(120.0), ..sample(250, 146, 4, 6.0) }, at(5000));
+
+        let stats = collector.summary();
+        assert_eq!((stats.packets_sent, stats.packets_received, stats.packets_lost), (250, 146, 4));
+        assert!((stats.loss_ratio - 4.0 / 150.0).abs() < 1e-9);
+        assert_eq!(stats.max_gap_ms, 2000);
+        assert!((stats.jitter_ms_mean - 20.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.jitter_ms_max, 10.0);
+        assert_eq!((stats.rtt_ms_mean, stats.rtt_ms_max), (Some(100.0), Some(120.0)));
+        assert_eq!(stats.samples, 6);
+    }
+
+    #[test]
+    fn test_no_gap_before_media_and_restarted_counters() {
+        let mut collector = RtpStatsCollector::new();
+        // Silence before the first packet is not a gap
+        collector.observe(&sample(10, 0, 0, 0.0), at(0));
+        collector.observe(&sample(20, 0, 0, 0.0), at(3000));
+        collector.observe(&sample(70, 50, 0, 1.0), at(4000));
+        // Re-INVITE: a new media session starts counting from zero
+        collector.observe(&sample(5, 5, 1, 1.0), at(4200));
+
+        let stats = collector.summary();
+        assert_eq!(stats.max_gap_ms, 0);
+        assert_eq!((stats.packets_sent, stats.packets_received, stats.packets_lost), (75, 55, 1));
+        assert!(stats.rtt_ms_mean.is_none());
+    }
+}
@@ -1,13 +1,17 @@
 use anyhow::Result;
 use log::{debug, info};
+use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::g711;
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)] // Part of complete tone generation API
 pub struct ToneConfig {
     pub frequency: f32,
+    /// Peak level of generated test tones; DTMF levels come from `DtmfConfig` instead
     pub amplitude: f32,
     pub sample_rate: u32,
     pub duration_seconds: f32,
@@ -24,19 +28,133 @@ impl Default for ToneConfig {
     }
 }
 
+/// Level of a full-scale sine wave in G.711 (ITU-T G.711 defines it as +3.17 dBm0)
+const FULL_SCALE_SINE_DBM0: f32 = 3.17;
+
+/// Timing and level settings for generated DTMF
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DtmfConfig {
+    /// Tone duration of each digit
+    pub on_time_ms: u64,
+    /// Silence after each digit
+    pub off_time_ms: u64,
+    /// Silence inserted for each ',' in a sequence
+    pub pause_ms: u64,
+    /// Level of the low-group (row) tone
+    pub low_group_level_dbm0: f32,
+    /// Level of the high-group (column) tone
+    pub high_group_level_dbm0: f32,
+}
+
+impl Default for DtmfConfig {
+    fn default() -> Self {
+        Self {
+            on_time_ms: 100,
+            off_time_ms: 100,
+            pause_ms: 500,
+            low_group_level_dbm0: -8.0,
+            high_group_level_dbm0: -6.0, // +2dB twist compensates high-frequency line loss
+        }
+    }
+}
+
+impl DtmfConfig {
+    /// Twist in dB: high-group level relative to the low-group level
+    pub fn twist_db(&self) -> f32 {
+        self.high_group_level_dbm0 - self.low_group_level_dbm0
+    }
+
+    /// Set the high-group level so it sits `twist_db` above the low group
+    pub fn with_twist(mut self, twist_db: f32) -> Self {
+        self.high_group_level_dbm0 = self.low_group_level_dbm0 + twist_db;
+        self
+    }
+
+    /// Peak of the two tones summed, relative to full scale; above 1.0 they would clip
+    pub fn peak_amplitude(&self) -> f32 {
+        dbm0_to_amplitude(self.low_group_level_dbm0) + dbm0_to_amplitude(self.high_group_level_dbm0)
+    }
+}
+
+/// One step of a DTMF string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DtmfEvent {
+    Digit(char),
+    Pause,
+}
+
+/// Parse a DTMF string such as "1234,,#" into digits and pauses
+pub fn parse_dtmf_sequence(digits: &str) -> Result<Vec<DtmfEvent>> {
+    let mut events = Vec::with_capacity(digits.len());
+
+    for c in digits.chars() {
+        match c {
+            '0'..='9' | '*' | '#' => events.push(DtmfEvent::Digit(c)),
+            'A'..='D' | 'a'..='d' => events.push(DtmfEvent::Digit(c.to_ascii_uppercase())),
+            ',' => events.push(DtmfEvent::Pause),
+            c if c.is_whitespace() => {}
+            _ => return Err(anyhow::anyhow!("Invalid DTMF character: '{}'", c)),
+        }
+    }
+
+    if events.is_empty() {
+        return Err(anyhow::anyhow!("DTMF sequence is empty"));
+    }
+
+    Ok(events)
+}
+
+/// Row and column frequencies for a DTMF digit
+fn dtmf_frequencies(digit: char) -> Result<(f32, f32)> {
+    let frequencies = match digit {
+        '1' => (697.0, 1209.0),
+        '2' => (697.0, 1336.0),
+        '3' => (697.0, 1477.0),
+        'A' => (697.0, 1633.0),
+        '4' => (770.0, 1209.0),
+        '5' => (770.0, 1336.0),
+        '6' => (770.0, 1477.0),
+        'B' => (770.0, 1633.0),
+        '7' => (852.0, 1209.0),
+        '8' => (852.0, 1336.0),
+        '9' => (852.0, 1477.0),
+        'C' => (852.0, 1633.0),
+        '*' => (941.0, 1209.0),
+        '0' => (941.0, 1336.0),
+        '#' => (941.0, 1477.0),
+        'D' => (941.0, 1633.0),
+        _ => return Err(anyhow::anyhow!("Invalid DTMF digit: {}", digit)),
+    };
+    Ok(frequencies)
+}
+
+/// Peak amplitude (relative to full scale) of a sine at the given dBm0 level
+fn dbm0_to_amplitude(level_dbm0: f32) -> f32 {
+    10f32.powf((level_dbm0 - FULL_SCALE_SINE_DBM0) / 20.0)
+}
+
 /// Tone generator for creating audio tones
 #[derive(Debug)]
 #[allow(dead_code)] // Part of complete tone generation API
 pub struct ToneGenerator {
     config: Arc<RwLock<ToneConfig>>,
+    dtmf_config: Arc<RwLock<DtmfConfig>>,
     is_generating: Arc<RwLock<bool>>,
 }
 
+impl Default for ToneGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[allow(dead_code)] // Complete tone generation API for future use
 impl ToneGenerator {
     pub fn new() -> Self {
         Self {
             config: Arc::new(RwLock::new(ToneConfig::default())),
+            dtmf_config: Arc::new(RwLock::new(DtmfConfig::default())),
             is_generating: Arc::new(RwLock::new(false)),
         }
     }
@@ -44,6 +162,7 @@ impl ToneGenerator {
     pub fn new_with_config(config: ToneConfig) -> Self {
         Self {
             config: Arc::new(RwLock::new(config)),
+            dtmf_config: Arc::new(RwLock::new(DtmfConfig::default())),
             is_generating: Arc::new(RwLock::new(false)),
         }
     }
@@ -59,6 +178,17 @@ impl ToneGenerator {
         config.clone()
     }
 
+    pub async fn set_dtmf_config(&self, dtmf_config: DtmfConfig) {
+        let mut current_config = self.dtmf_config.write().await;
+        *current_config = dtmf_config;
+        debug!("DTMF configuration updated");
+    }
+
+    pub async fn get_dtmf_config(&self) -> DtmfConfig {
+        let dtmf_config = self.dtmf_config.read().await;
+        dtmf_config.clone()
+    }
+
     pub async fn is_generating(&self) -> bool {
         let generating = self.is_generating.read().await;
         *generating
@@ -118,50 +248,81 @@ impl ToneGenerator {
     }
 
     /// Generate DTMF tone (dual tone multi-frequency)
+    ///
+    /// The tone levels come from the per-group dBm0 levels in `DtmfConfig`;
+    /// `ToneConfig.amplitude` no longer applies to DTMF.
     pub async fn generate_dtmf_tone(&self, digit: char, duration_ms: u64) -> Result<Vec<i16>> {
-        let (freq1, freq2) = match digit {
-            '1' => (697.0, 1209.0),
-            '2' => (697.0, 1336.0),
-            '3' => (697.0, 1477.0),
-            'A' => (697.0, 1633.0),
-            '4' => (770.0, 1209.0),
-            '5' => (770.0, 1336.0),
-            '6' => (770.0, 1477.0),
-            'B' => (770.0, 1633.0),
-            '7' => (852.0, 1209.0),
-            '8' => (852.0, 1336.0),
-            '9' => (852.0, 1477.0),
-            'C' => (852.0, 1633.0),
-            '*' => (941.0, 1209.0),
-            '0' => (941.0, 1336.0),
-            '#' => (941.0, 1477.0),
-            'D' => (941.0, 1633.0),
-            _ => return Err(anyhow::anyhow!("Invalid DTMF digit: {}", digit)),
-        };
-
+        let (freq1, freq2) = dtmf_frequencies(digit)?;
         let config = self.get_config().await;
-        let duration_seconds = duration_ms as f32 / 1000.0;
-        let total_samples = (config.sample_rate as f32 * duration_seconds) as usize;
-        let mut samples = Vec::with_capacity(total_samples);
+        let dtmf_config = self.get_dtmf_config().await;
+
+        let total_samples = (config.sample_rate as u64 * duration_ms / 1000) as usize;
+        let samples = Self::render_dual_tone(freq1, freq2, &dtmf_config, config.sample_rate, total_samples)?;
+
+        info!("Generated DTMF tone for digit '{}': {:.1}Hz + {:.1}Hz, {}ms", 
+              digit, freq1, freq2, duration_ms);
+        Ok(samples)
+    }
+
+    /// Render a whole DTMF string using the configured DTMF timing and levels
+    pub async fn generate_dtmf_sequence(&self, digits: &str) -> Result<Vec<i16>> {
+        let dtmf_config = self.get_dtmf_config().await;
+        self.generate_dtmf_sequence_with_config(digits, &dtmf_config).await
+    }
+
+    /// Render a whole DTMF string with explicit timing and levels
+    pub async fn generate_dtmf_sequence_with_config(&self, digits: &str, dtmf_config: &DtmfConfig) -> Result<Vec<i16>> {
+        let events = parse_dtmf_sequence(digits)?;
+        let sample_rate = self.get_config().await.sample_rate;
+        let samples_for = |duration_ms: u64| (sample_rate as u64 * duration_ms / 1000) as usize;
+
+        let mut samples = Vec::new();
+        for event in events {
+            match event {
+                DtmfEvent::Digit(digit) => {
+                    let (freq1, freq2) = dtmf_frequencies(digit)?;
+                    samples.extend(Self::render_dual_tone(
+                        freq1, freq2, dtmf_config, sample_rate, samples_for(dtmf_config.on_time_ms),
+                    )?);
+                    samples.resize(samples.len() + samples_for(dtmf_config.off_time_ms), 0);
+                }
+                DtmfEvent::Pause => {
+                    samples.resize(samples.len() + samples_for(dtmf_config.pause_ms), 0);
+                }
+            }
+        }
+
+        info!("Generated DTMF sequence '{}': {} samples, {}ms on / {}ms off, twist {:.1}dB", 
+              digits, samples.len(), dtmf_config.on_time_ms, dtmf_config.off_time_ms, dtmf_config.twist_db());
+        Ok(samples)
+    }
+
+    /// Mix the low- and high-group tones at their configured levels
+    ///
+    /// Levels whose summed peak exceeds full scale are rejected rather than clipped,
+    /// as clipping would distort the tones.
+    fn render_dual_tone(freq1: f32, freq2: f32, dtmf_config: &DtmfConfig, sample_rate: u32, total_samples: usize) -> Result<Vec<i16>> {
+        let peak = dtmf_config.peak_amplitude();
+        if peak > 1.0 {
+            return Err(anyhow::anyhow!("DTMF levels {}dBm0 / {}dBm0 would clip: the tones sum to {:.2} of full scale",
+                dtmf_config.low_group_level_dbm0, dtmf_config.high_group_level_dbm0, peak));
+        }
+
+        let amplitude1 = dbm0_to_amplitude(dtmf_config.low_group_level_dbm0);
+        let amplitude2 = dbm0_to_amplitude(dtmf_config.high_group_level_dbm0);
 
         let angular_freq1 = 2.0 * PI * freq1;
         let angular_freq2 = 2.0 * PI * freq2;
-        let sample_duration = 1.0 / config.sample_rate as f32;
+        let sample_duration = 1.0 / sample_rate as f32;
 
-        for i in 0..total_samples {
+        Ok((0..total_samples).map(|i| {
             let time = i as f32 * sample_duration;
-            let sample1 = (angular_freq1 * time).sin();
-            let sample2 = (angular_freq2 * time).sin();
-            let combined_sample = (sample1 + sample2) * 0.5 * config.amplitude;
-            
-            // Convert to 16-bit PCM
-            let pcm_value = (combined_sample * i16::MAX as f32) as i16;
-            samples.push(pcm_value);
-        }
+            let combined_sample = amplitude1 * (angular_freq1 * time).sin()
+                + amplitude2 * (angular_freq2 * time).sin();
 
-        info!("Generated DTMF tone for digit '{}': {:.1}Hz + {:.1}Hz, {}ms", 
-              digit, freq1, freq2, duration_ms);
-        Ok(samples)
+            // Convert to 16-bit PCM
+            (combined_sample * i16::MAX as f32) as i16
+        }).collect())
     }
 
     /// Generate a comfort noise tone (for silence periods)
@@ -190,67 +351,12 @@ impl ToneGenerator {
 
     /// Convert PCM samples to μ-law encoding (commonly used in telephony)
     pub fn pcm_to_mulaw(&self, pcm_samples: &[i16]) -> Vec<u8> {
-        pcm_samples.iter().map(|&sample| {
-            self.linear_to_mulaw(sample)
-        }).collect()
+        g711::pcm_to_mulaw(pcm_samples)
     }
 
     /// Convert PCM samples to A-law encoding (commonly used in telephony)
     pub fn pcm_to_alaw(&self, pcm_samples: &[i16]) -> Vec<u8> {
-        pcm_samples.iter().map(|&sample| {
-            self.linear_to_alaw(sample)
-        }).collect()
-    }
-
-    fn linear_to_mulaw(&self, sample: i16) -> u8 {
-        // μ-law encoding implementation
-        const BIAS: i16 = 0x84;
-        const CLIP: i16 = 32635;
-
-        let sign = if sample < 0 { 0x80 } else { 0 };
-        let sample = if sample < 0 { -sample } else { sample };
-        let sample = if sample > CLIP { CLIP } else { sample };
-        let sample = sample + BIAS;
-
-        let exponent = if sample >= 0x7FFF { 7 }
-        else if sample >= 0x4000 { 6 }
-        else if sample >= 0x2000 { 5 }
-        else if sample >= 0x1000 { 4 }
-        else if sample >= 0x0800 { 3 }
-        else if sample >= 0x0400 { 2 }
-        else if sample >= 0x0200 { 1 }
-        else { 0 };
-
-        let mantissa = (sample >> (exponent + 3)) & 0x0F;
-        let mulaw = sign | (exponent << 4) | mantissa;
-        !mulaw as u8
-    }
-
-    fn linear_to_alaw(&self, sample: i16) -> u8 {
-        // A-law encoding implementation
-        const ALAW_MAX: i16 = 0x7FFF;
-        
-        let sign = if sample < 0 { 0x80 } else { 0 };
-        let sample = if sample < 0 { -sample } else { sample };
-        let sample = if sample > ALAW_MAX { ALAW_MAX } else { sample };
-
-        let exponent = if sample >= 0x4000 { 7 }
-        else if sample >= 0x2000 { 6 }
-        else if sample >= 0x1000 { 5 }
-        else if sample >= 0x0800 { 4 }
-        else if sample >= 0x0400 { 3 }
-        else if sample >= 0x0200 { 2 }
-        else if sample >= 0x0100 { 1 }
-        else { 0 };
-
-        let mantissa = if exponent == 0 {
-            sample >> 4
-        } else {
-            (sample >> (exponent + 3)) & 0x0F
-        };
-
-        let alaw = sign | (exponent << 4) | mantissa;
-        alaw as u8 ^ 0x55
+        g711::pcm_to_alaw(pcm_samples)
     }
 
     /// Stop any ongoing tone generation
@@ -264,7 +370,6 @@ impl ToneGenerator {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tokio::test;
 
     #[tokio::test]
     async fn test_tone_generation() {
@@ -298,6 +403,85 @@ mod tests {
         assert!(samples.iter().any(|&s| s != 0));
     }
 
+    #[test]
+    fn test_parse_dtmf_sequence() {
+        let events = parse_dtmf_sequence("12,,#a").unwrap();
+        assert_eq!(events, vec![
+            DtmfEvent::Digit('1'),
+            DtmfEvent::Digit('2'),
+            DtmfEvent::Pause,
+            DtmfEvent::Pause,
+            DtmfEvent::Digit('#'),
+            DtmfEvent::Digit('A'),
+        ]);
+
+        assert!(parse_dtmf_sequence("12x").is_err());
+        assert!(parse_dtmf_sequence("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dtmf_sequence_timing() {
+        let generator = ToneGenerator::new();
+        generator.set_dtmf_config(DtmfConfig {
+            on_time_ms: 40,
+            off_time_ms: 40,
+            pause_ms: 500,
+            ..DtmfConfig::default()
+        }).await;
+
+        let samples = generator.generate_dtmf_sequence("1,2").await.unwrap();
+
+        // Two digits of 40ms on + 40ms off plus one 500ms pause at 8kHz
+        assert_eq!(samples.len(), 2 * (320 + 320) + 4000);
+        assert!(samples[..320].iter().any(|&s| s != 0));
+        assert!(samples[320..640].iter().all(|&s| s == 0));
+    }
+
+    #[tokio::test]
+    async fn test_dtmf_levels_and_twist() {
+        let generator = ToneGenerator::new();
+        let dtmf_config = DtmfConfig {
+            low_group_level_dbm0: -10.0,
+            ..DtmfConfig::default()
+        }.with_twist(4.0);
+        assert_eq!(dtmf_config.twist_db(), 4.0);
+        generator.set_dtmf_config(dtmf_config).await;
+
+        // 1s of '5' (770Hz + 1336Hz) gives an integer number of cycles for both tones
+        let samples = generator.generate_dtmf_tone('5', 1000).await.unwrap();
+        let amplitude_at = |freq: f32| {
+            let (re, im) = samples.iter().enumerate().fold((0.0f32, 0.0f32), |(re, im), (i, &s)| {
+                let phase = 2.0 * PI * freq * i as f32 / 8000.0;
+                let x = s as f32 / i16::MAX as f32;
+                (re + x * phase.cos(), im + x * phase.sin())
+            });
+            2.0 * (re * re + im * im).sqrt() / samples.len() as f32
+        };
+
+        let low_dbm0 = 20.0 * amplitude_at(770.0).log10() + FULL_SCALE_SINE_DBM0;
+        let high_dbm0 = 20.0 * amplitude_at(1336.0).log10() + FULL_SCALE_SINE_DBM0;
+        assert!((low_dbm0 - -10.0).abs() < 0.1, "low group at {:.2}dBm0", low_dbm0);
+        assert!((high_dbm0 - -6.0).abs() < 0.1, "high group at {:.2}dBm0", high_dbm0);
+    }
+
+    #[tokio::test]
+    async fn test_dtmf_levels_that_would_clip_are_rejected() {
+        let generator = ToneGenerator::new();
+        let loud = DtmfConfig {
+            low_group_level_dbm0: -2.0,
+            high_group_level_dbm0: -2.0,
+            ..DtmfConfig::default()
+        };
+        assert!(loud.peak_amplitude() > 1.0);
+        assert!(generator.generate_dtmf_sequence_with_config("5", &loud).await.is_err());
+
+        // A loud high group fits when the low group is lowered to make room
+        let twisted = DtmfConfig { low_group_level_dbm0: -12.0, ..loud }.with_twist(11.0);
+        assert!(twisted.peak_amplitude() <= 1.0);
+        let samples = generator.generate_dtmf_sequence_with_config("5", &twisted).await.unwrap();
+        assert!(samples.iter().all(|&s| s > i16::MIN && s < i16::MAX));
+    }
+
     #[tokio::test]
     async fn test_comfort_noise() {
         let generator = ToneGenerator::new();
@@ -326,12 +510,14 @@ mod tests {
     #[test]
     fn test_alaw_encoding() {
         let generator = ToneGenerator::new();
-        let pcm_samples = vec![0, 1000, -1000, 32000, -32000];
+        let pcm_samples = vec![0, 1000, -1000, 32000, i16::MIN];
         let alaw_samples = generator.pcm_to_alaw(&pcm_samples);
         
         assert_eq!(alaw_samples.len(), pcm_samples.len());
         
-        // A-law of 0 should be 0x55
-        assert_eq!(alaw_samples[0], 0x55);
+        // A-law of 0 should be 0xD5 (G.711 even-bit inversion of a positive zero)
+        assert_eq!(alaw_samples[0], 0xD5);
+        // Full-scale negative input must not overflow
+        assert_eq!(alaw_samples[4], 0x2A);
     }
 }
\ No newline at end of file
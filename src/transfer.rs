@@ -0,0 +1,75 @@
+//! Call transfer with REFER (RFC 3515)
+
+use serde::{Deserialize, Serialize};
+
+/// Whether `uri` is something we can place a call to
+pub fn is_sip_uri(uri: &str) -> bool {
+    sip_uri_host(uri).is_some()
+}
+
+/// Host part of a sip: or sips: URI, e.g. `pbx.example.com` for `sip:queue@pbx.example.com:5060;transport=tcp`
+///
+/// The user part is optional, so `sip:pbx.example.com` is a valid target.
+pub fn sip_uri_host(uri: &str) -> Option<&str> {
+    let (scheme, rest) = uri.split_once(':')?;
+    if !scheme.eq_ignore_ascii_case("sip") && !scheme.eq_ignore_ascii_case("sips") {
+        return None;
+    }
+    let hostport = rest.split([';', '?']).next()?;
+    let hostport = hostport.rsplit_once('@').map_or(hostport, |(_, hostport)| hostport);
+
+    let (host, port) = match hostport.strip_prefix('[') {
+        // IPv6 reference
+        Some(v6) => {
+            let (address, port) = v6.split_once(']')?;
+            address.parse::<std::net::Ipv6Addr>().ok()?;
+            (&hostport[..address.len() + 2], port)
+        }
+        None => {
+            let end = hostport.find(':').unwrap_or(hostport.len());
+            let host = &hostport[..end];
+            let valid = !host.is_empty()
+                && host.split('.').all(|label| {
+                    !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                });
+            if !valid {
+                return None;
+            }
+            (host, &hostport[end..])
+        }
+    };
+
+    match port.strip_prefix(':') {
+        Some(port) => port.parse::<u16>().ok().map(|_| host),
+        None if port.is_empty() => Some(host),
+        None => None,
+    }
+}
+
+/// How a transfer of the call went, for the CDR
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferRecord {
+    pub target: String,
+    /// How the transfer ended, or why it failed
+    pub outcome: String,
+    /// False if the transfer failed; unset when its result is unknown, as the client
+    /// API reports no NOTIFY progress and a caller leaving may just have hung up
+    pub succeeded: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sip_uri_host() {
+        assert_eq!(sip_uri_host("sip:queue@pbx.example.com"), Some("pbx.example.com"));
+        assert_eq!(sip_uri_host("sip:pbx.example.com"), Some("pbx.example.com"));
+        assert_eq!(sip_uri_host("SIPS:alice:secret@10.0.0.1:5061;transport=tls"), Some("10.0.0.1"));
+        assert_eq!(sip_uri_host("sip:bob@[2001:db8::1]:5060?Subject=x"), Some("[2001:db8::1]"));
+
+        for invalid in ["sip:", "sip:queue@", "tel:+15551234", "sip:pbx..example.com", "sip:pbx:port", "sip:[::1"] {
+            assert!(!is_sip_uri(invalid), "{}", invalid);
+        }
+    }
+}